@@ -1,7 +1,8 @@
+use std::io::{self, Write};
 use std::net::{IpAddr, Ipv4Addr};
 
 use super::Cli;
-use crate::commands::{self, dev::Protocol};
+use crate::commands::{self, dev::DevOptions, dev::Protocol};
 use crate::settings::{global_user::GlobalUser, toml::Manifest};
 
 use anyhow::Result;
@@ -12,6 +13,7 @@ pub fn dev(
     mut port: Option<u16>,
     mut local_protocol: Option<Protocol>,
     mut upstream_protocol: Option<Protocol>,
+    mut options: DevOptions,
     cli_params: &Cli,
 ) -> Result<()> {
     log::info!("Starting dev server");
@@ -30,11 +32,31 @@ pub fn dev(
     let local_protocol = local_protocol.unwrap_or(Protocol::Http);
     let upstream_protocol = upstream_protocol.unwrap_or(Protocol::Https);
 
+    // --preview-host overrides the host configured for the environment
+    if options.preview_host.is_none() {
+        options.preview_host = manifest.get_preview_host(cli_params.environment.as_deref())?;
+    }
+
+    if options.reload_on_config_change {
+        commands::dev::reload_config_on_change(
+            &cli_params.config,
+            cli_params.environment.as_deref(),
+        )?;
+    }
+
     let deployments = manifest.get_deployments(cli_params.environment.as_deref())?;
     let target = manifest.get_target(cli_params.environment.as_deref(), true)?;
     let user = GlobalUser::new().ok();
 
-    let server_config = commands::dev::ServerConfig::new(host, ip, port, upstream_protocol)?;
+    let server_config =
+        commands::dev::ServerConfig::new(host, ip, port, upstream_protocol, options)?;
+
+    // with --port 0 the OS picks the port, so whatever started wrangler dev is told where
+    // to find it, before anything else the session writes to stdout
+    if port == 0 {
+        println!("WRANGLER_DEV_URL={}", server_config.url(local_protocol));
+        io::stdout().flush()?;
+    }
 
     commands::dev::dev(
         target,
@@ -33,7 +33,7 @@ pub mod exec {
 use std::net::IpAddr;
 use std::path::PathBuf;
 
-use crate::commands::dev::Protocol;
+use crate::commands::dev::{DevOptions, Protocol};
 use crate::preview::HttpMethod;
 use crate::settings::toml::migrations::{
     DurableObjectsMigration, Migration, MigrationConfig, Migrations, RenameClass, TransferClass,
@@ -158,11 +158,13 @@ pub enum Command {
         #[structopt(long, short = "h")]
         host: Option<String>,
 
-        /// IP to listen on. Defaults to 127.0.0.1
+        /// IP to listen on. Defaults to 127.0.0.1, and :: listens on both IPv6 and IPv4
         #[structopt(long, short = "i")]
         ip: Option<IpAddr>,
 
-        /// Port to listen on. Defaults to 8787
+        /// Port to listen on. Defaults to 8787, and 0 listens on a port the OS picks.
+        /// With 0, the first line printed to stdout is WRANGLER_DEV_URL= followed by
+        /// the URL to reach the server on, like WRANGLER_DEV_URL=http://127.0.0.1:54321
         #[structopt(long, short = "p")]
         port: Option<u16>,
 
@@ -175,6 +177,9 @@ pub enum Command {
         /// but can be set to http
         #[structopt(name = "upstream-protocol")]
         upstream_protocol: Option<Protocol>,
+
+        #[structopt(flatten)]
+        options: DevOptions,
     },
 
     /// Publish your worker to the orange cloud
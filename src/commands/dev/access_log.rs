@@ -0,0 +1,210 @@
+//! `--log-format common` and `--log-format combined` print the request log in
+//! the Common and Combined Log Formats of Apache and most other servers, so it
+//! can be fed to log analysers like GoAccess or AWStats:
+//!
+//! ```text
+//! 127.0.0.1 - - [20/Apr/2020:15:25:54 +0000] "GET /users?page=2 HTTP/1.1" 200 1043
+//! 127.0.0.1 - - [20/Apr/2020:15:25:54 +0000] "GET /users?page=2 HTTP/1.1" 200 1043 "http://localhost:8787/" "curl/7.64.1"
+//! ```
+//!
+//! The size is the response's `Content-Length`, or `-` for a response streamed
+//! without one, as the line is printed before the body is sent. The notes the
+//! default `pretty` format adds to a line, like the route a request matched,
+//! are left out, as tools reading these formats wouldn't expect them.
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::{DateTime, TimeZone};
+use hyper::header::{HeaderMap, REFERER, USER_AGENT};
+use hyper::server::conn::AddrStream;
+use hyper::{Body, Request, StatusCode};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Common,
+    Combined,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Pretty
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "common" => Ok(LogFormat::Common),
+            "combined" => Ok(LogFormat::Combined),
+            _ => anyhow::bail!("Expected pretty, common or combined, got {}", s),
+        }
+    }
+}
+
+/// the address a request was received from, added to it by the server that accepted it
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ClientAddr(pub(super) SocketAddr);
+
+impl ClientAddr {
+    /// the address a connection to an http server came from
+    pub(super) fn of_tcp(conn: &AddrStream) -> Option<ClientAddr> {
+        Some(ClientAddr(conn.remote_addr()))
+    }
+
+    /// the address a connection to an https server came from
+    pub(super) fn of_tls(conn: &TlsStream<TcpStream>) -> Option<ClientAddr> {
+        conn.get_ref().0.peer_addr().ok().map(ClientAddr)
+    }
+
+    /// marks `req` as received from `client_addr`, if it's known
+    pub(super) fn mark(req: &mut Request<Body>, client_addr: Option<ClientAddr>) {
+        if let Some(client_addr) = client_addr {
+            req.extensions_mut().insert(client_addr);
+        }
+    }
+}
+
+/// what the access log says about who made a request
+#[derive(Debug, Default)]
+pub(super) struct Client {
+    ip: Option<IpAddr>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Client {
+    pub(super) fn of(req: &Request<Body>) -> Client {
+        let header = |headers: &HeaderMap, name| {
+            headers
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        };
+        Client {
+            ip: req.extensions().get::<ClientAddr>().map(|addr| addr.0.ip()),
+            referer: header(req.headers(), REFERER),
+            user_agent: header(req.headers(), USER_AGENT),
+        }
+    }
+}
+
+/// a request as a line in the Common Log Format, or with `--log-format combined`, the
+/// Combined Log Format. `request_line` is the method, target and version as they were sent
+pub(super) fn line<Tz>(
+    format: LogFormat,
+    now: &DateTime<Tz>,
+    client: &Client,
+    request_line: &str,
+    status: StatusCode,
+    size: Option<u64>,
+) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let mut line = format!(
+        "{} - - [{}] \"{}\" {} {}",
+        client
+            .ip
+            .map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+        now.format("%d/%b/%Y:%H:%M:%S %z"),
+        escape(request_line),
+        status.as_u16(),
+        size.map_or_else(|| "-".to_string(), |size| size.to_string())
+    );
+    if format == LogFormat::Combined {
+        line.push_str(&format!(
+            " \"{}\" \"{}\"",
+            client.referer.as_deref().map_or("-".into(), escape),
+            client.user_agent.as_deref().map_or("-".into(), escape)
+        ));
+    }
+    line
+}
+
+/// a value to quote in a log line, with its quotes, backslashes and control
+/// characters escaped so it can't break the line up
+fn escape(value: &str) -> String {
+    value.escape_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east(2 * 3600)
+            .ymd(2020, 4, 20)
+            .and_hms(15, 25, 54)
+    }
+
+    fn client() -> Client {
+        let req = Request::get("/")
+            .header(REFERER, "http://localhost:8787/")
+            .header(USER_AGENT, "curl/7.64.1 \"test\"")
+            .extension(ClientAddr("127.0.0.1:52000".parse().unwrap()))
+            .body(Body::empty())
+            .unwrap();
+        Client::of(&req)
+    }
+
+    #[test]
+    fn requests_are_logged_in_the_common_log_format() {
+        assert_eq!(
+            line(
+                LogFormat::Common,
+                &now(),
+                &client(),
+                "GET /users?page=2 HTTP/1.1",
+                StatusCode::OK,
+                Some(1043)
+            ),
+            "127.0.0.1 - - [20/Apr/2020:15:25:54 +0200] \"GET /users?page=2 HTTP/1.1\" 200 1043"
+        );
+        assert_eq!(
+            line(
+                LogFormat::Common,
+                &now(),
+                &Client::default(),
+                "POST /upload HTTP/2.0",
+                StatusCode::PAYLOAD_TOO_LARGE,
+                None
+            ),
+            "- - - [20/Apr/2020:15:25:54 +0200] \"POST /upload HTTP/2.0\" 413 -"
+        );
+    }
+
+    #[test]
+    fn requests_are_logged_in_the_combined_log_format() {
+        assert_eq!(
+            line(
+                LogFormat::Combined,
+                &now(),
+                &client(),
+                "GET / HTTP/1.1",
+                StatusCode::NOT_FOUND,
+                Some(0)
+            ),
+            "127.0.0.1 - - [20/Apr/2020:15:25:54 +0200] \"GET / HTTP/1.1\" 404 0 \"http://localhost:8787/\" \"curl/7.64.1 \\\"test\\\"\""
+        );
+        assert_eq!(
+            line(
+                LogFormat::Combined,
+                &now(),
+                &Client::default(),
+                "GET / HTTP/1.1",
+                StatusCode::OK,
+                None
+            ),
+            "- - - [20/Apr/2020:15:25:54 +0200] \"GET / HTTP/1.1\" 200 - \"-\" \"-\""
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::commands::dev::error_response;
+use crate::terminal::message::{Message, StdErr};
+
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderValue, ALLOW, AUTHORIZATION, CONTENT_TYPE, COOKIE, PROXY_AUTHORIZATION};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// what a disabled TRACE is told it may use instead
+const ALLOW_WITHOUT_TRACE: &str = "GET, HEAD, POST, PUT, DELETE, PATCH, OPTIONS";
+
+/// parses a method given to `--allowed-methods`, whatever its case
+pub fn parse(method: &str) -> Result<Method> {
+    Method::from_bytes(method.trim().to_uppercase().as_bytes())
+        .map_err(|_| anyhow!("{} is not an HTTP method", method))
+}
+
+/// builds a 405 if `--allowed-methods` was given and doesn't include `method`
+pub(super) fn reject(method: &Method, path: &str, allowed: &[Method]) -> Option<Response<Body>> {
+    if allowed.is_empty() || allowed.contains(method) {
+        return None;
+    }
+
+    StdErr::warn(&format!(
+        "Rejected {} {} with a 405, as it isn't one of the methods set by --allowed-methods",
+        method, path
+    ));
+
+    let allow = allowed
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut resp = error_response::build(
+        StatusCode::METHOD_NOT_ALLOWED,
+        format!(
+            "{} is not allowed by wrangler dev, only {} are",
+            method, allow
+        ),
+    );
+    if let Ok(allow) = HeaderValue::from_str(&allow) {
+        resp.headers_mut().insert(ALLOW, allow);
+    }
+    Some(resp)
+}
+
+/// answers a TRACE locally, as it never reaches the Worker: with a 405, as production
+/// servers usually do, or with `--allow-trace` by echoing the request back
+pub(super) fn trace(req: &Request<Body>, allow_trace: bool) -> Option<Response<Body>> {
+    if req.method() != Method::TRACE {
+        return None;
+    }
+
+    if !allow_trace {
+        let mut resp = error_response::build(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "TRACE is disabled, as it usually is in production. Allow it with --allow-trace",
+        );
+        resp.headers_mut()
+            .insert(ALLOW, HeaderValue::from_static(ALLOW_WITHOUT_TRACE));
+        return Some(resp);
+    }
+
+    let mut echo = format!("{} {} {:?}\r\n", req.method(), req.uri(), req.version());
+    for (name, value) in req.headers() {
+        // credentials are left out, so a script that can send a TRACE can't read them back
+        if name == AUTHORIZATION || name == COOKIE || name == PROXY_AUTHORIZATION {
+            continue;
+        }
+        echo.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    echo.push_str("\r\n");
+
+    let mut resp = Response::new(Body::from(echo));
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("message/http"));
+    Some(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn methods_are_parsed_in_any_case() {
+        assert_eq!(parse("get").unwrap(), Method::GET);
+        assert_eq!(parse(" POST ").unwrap(), Method::POST);
+        assert!(parse("GET POST").is_err());
+    }
+
+    #[tokio::test]
+    async fn traces_are_echoed_without_credentials() {
+        let req = Request::builder()
+            .method(Method::TRACE)
+            .uri("/debug")
+            .header("x-test", "1")
+            .header(COOKIE, "session=secret")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = trace(&req, true).unwrap();
+        assert_eq!(resp.headers()[CONTENT_TYPE], "message/http");
+        let echo = hyper::body::to_bytes(resp).await.unwrap();
+        assert_eq!(echo, "TRACE /debug HTTP/1.1\r\nx-test: 1\r\n\r\n");
+    }
+}
@@ -0,0 +1,125 @@
+//! `--base-path <prefix>` is for Workers that are reached under a path prefix in
+//! production, behind something that takes the prefix off before the Worker
+//! sees the request. Requests to dev under the prefix have it stripped before
+//! they're sent upstream, so `/app/users?page=2` reaches the Worker as
+//! `/users?page=2`, and `/app` as `/`. A redirect from the Worker to a path gets
+//! the prefix back, so following it stays under the prefix.
+//!
+//! Requests outside the prefix wouldn't reach the Worker in production, so
+//! they're answered with a 404 by dev. With `--base-path-passthrough` they're
+//! sent upstream unchanged instead. The request log shows paths as they were
+//! requested.
+use crate::commands::dev::error_response;
+
+use anyhow::Result;
+use hyper::header::{HeaderMap, HeaderValue, LOCATION};
+use hyper::http::uri::{PathAndQuery, Uri};
+use hyper::{Body, Response, StatusCode};
+
+/// a prefix given to `--base-path`, which starts with a `/` and doesn't end with one
+pub(super) fn parse(prefix: &str) -> Result<String> {
+    let prefix = prefix.trim();
+    let trimmed = prefix.trim_end_matches('/');
+    if trimmed.is_empty() && !prefix.is_empty() {
+        anyhow::bail!("--base-path / would strip nothing, leave it out instead")
+    }
+    if !trimmed.starts_with('/') {
+        anyhow::bail!("--base-path must start with a /, like /app")
+    }
+    if trimmed.parse::<PathAndQuery>().is_err() || trimmed.contains('?') {
+        anyhow::bail!("{} is not a path", prefix)
+    }
+    Ok(trimmed.to_string())
+}
+
+/// `uri` without `prefix`, or nothing if it isn't under the prefix
+pub(super) fn strip(uri: &Uri, prefix: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(prefix)?;
+    let path = match rest {
+        "" => "/",
+        rest if rest.starts_with('/') => rest,
+        // `/application` isn't under `/app`
+        _ => return None,
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// puts `prefix` back on a redirect to a path, so the client is sent somewhere under it
+pub(super) fn restore_location(headers: &mut HeaderMap, prefix: &str) {
+    let location = match headers.get(LOCATION).and_then(|value| value.to_str().ok()) {
+        // `//host/path` is another host, without a scheme
+        Some(location) if location.starts_with('/') && !location.starts_with("//") => location,
+        _ => return,
+    };
+    if let Ok(prefixed) = HeaderValue::from_str(&format!("{}{}", prefix, location)) {
+        headers.insert(LOCATION, prefixed);
+    }
+}
+
+/// the response to a request outside the prefix, which never reaches the Worker
+pub(super) fn not_found(prefix: &str) -> Response<Body> {
+    error_response::build(
+        StatusCode::NOT_FOUND,
+        format!(
+            "Not found: wrangler dev only sends requests under {} to your Worker, as --base-path was given",
+            prefix
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripped(uri: &str) -> Option<String> {
+        strip(&uri.parse().unwrap(), "/app").map(|uri| uri.to_string())
+    }
+
+    #[test]
+    fn the_prefix_is_stripped() {
+        assert_eq!(
+            stripped("/app/users?page=2").as_deref(),
+            Some("/users?page=2")
+        );
+        assert_eq!(stripped("/app").as_deref(), Some("/"));
+        assert_eq!(stripped("/app/").as_deref(), Some("/"));
+        assert_eq!(stripped("/app?q=1").as_deref(), Some("/?q=1"));
+    }
+
+    #[test]
+    fn paths_outside_the_prefix_are_not_matched() {
+        assert_eq!(stripped("/"), None);
+        assert_eq!(stripped("/application"), None);
+        assert_eq!(stripped("/other/app"), None);
+    }
+
+    #[test]
+    fn prefixes_are_normalized() {
+        assert_eq!(parse("/app/").unwrap(), "/app");
+        assert_eq!(parse("/api/v1").unwrap(), "/api/v1");
+        assert!(parse("app").is_err());
+        assert!(parse("/").is_err());
+    }
+
+    #[test]
+    fn redirects_to_paths_stay_under_the_prefix() {
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, HeaderValue::from_static("/login"));
+        restore_location(&mut headers, "/app");
+        assert_eq!(headers[LOCATION], "/app/login");
+
+        headers.insert(
+            LOCATION,
+            HeaderValue::from_static("https://example.com/login"),
+        );
+        restore_location(&mut headers, "/app");
+        assert_eq!(headers[LOCATION], "https://example.com/login");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::commands::dev::error_response;
+use crate::terminal::message::{Message, StdErr};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, CONNECTION};
+use hyper::{Body, Request, Response, StatusCode};
+
+/// a request body that has to finish arriving within a time limit,
+/// so a client trickling its body in can't hold a connection open forever
+pub(super) struct BodyTimeout {
+    timed_out: Arc<AtomicBool>,
+}
+
+impl BodyTimeout {
+    /// forwards the request body as it arrives, cutting it off once `timeout` runs out
+    pub(super) fn guard(req: Request<Body>, timeout: Duration) -> (Request<Body>, BodyTimeout) {
+        let timed_out = Arc::new(AtomicBool::new(false));
+        if req.body().is_end_stream() {
+            return (req, BodyTimeout { timed_out });
+        }
+
+        let (parts, mut body) = req.into_parts();
+        let (mut sender, guarded) = Body::channel();
+
+        let body_timed_out = Arc::clone(&timed_out);
+        tokio::spawn(async move {
+            let forwarded = tokio::time::timeout(timeout, async {
+                while let Some(chunk) = body.data().await {
+                    if sender.send_data(chunk?).await.is_err() {
+                        // the request is no longer being sent upstream
+                        break;
+                    }
+                }
+                Ok::<_, hyper::Error>(())
+            })
+            .await;
+
+            match forwarded {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    log::debug!("Failed to read request body: {}", e);
+                    sender.abort();
+                }
+                Err(_) => {
+                    body_timed_out.store(true, Ordering::SeqCst);
+                    sender.abort();
+                }
+            }
+        });
+
+        (
+            Request::from_parts(parts, guarded),
+            BodyTimeout { timed_out },
+        )
+    }
+
+    pub(super) fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::SeqCst)
+    }
+}
+
+/// a 408 that also closes the connection, as the rest of the body may still be on its way
+pub(super) fn request_timeout(method: &str, path: &str, timeout: Duration) -> Response<Body> {
+    StdErr::warn(&format!(
+        "Rejected {} {} with a 408, its body took longer than {} seconds to arrive, the limit set by --body-read-timeout",
+        method,
+        path,
+        timeout.as_secs()
+    ));
+
+    let mut resp = error_response::build(
+        StatusCode::REQUEST_TIMEOUT,
+        format!(
+            "The request body took longer than {} seconds to arrive",
+            timeout.as_secs()
+        ),
+    );
+    resp.headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("close"));
+    resp
+}
@@ -0,0 +1,198 @@
+//! What is uploaded to the preview service for a target, hashed so
+//! `--reuse-preview` can tell whether it changed, and measured for `--banner-info`
+use crate::settings::toml::migrations::Migrations;
+use crate::settings::toml::{
+    Builder, DurableObjects, Site, Target, TargetType, UploadFormat, UsageModel,
+};
+use crate::upload::Package;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+use twox_hash::XxHash64;
+
+/// the configuration of a target that goes into its upload, with maps sorted
+/// so the same configuration always serializes the same way
+#[derive(Serialize)]
+struct Config<'a> {
+    name: &'a str,
+    target_type: &'a TargetType,
+    build: Option<&'a Builder>,
+    site: Option<&'a Site>,
+    /// the binding and id of each namespace
+    kv_namespaces: Vec<(&'a str, &'a str)>,
+    durable_objects: Option<&'a DurableObjects>,
+    migrations: Option<&'a Migrations>,
+    usage_model: Option<&'a UsageModel>,
+    vars: Option<BTreeMap<&'a String, &'a String>>,
+    text_blobs: Option<BTreeMap<&'a String, &'a PathBuf>>,
+    wasm_modules: Option<BTreeMap<&'a String, &'a PathBuf>>,
+    script_path: Option<&'a PathBuf>,
+}
+
+impl<'a> From<&'a Target> for Config<'a> {
+    fn from(target: &'a Target) -> Self {
+        Config {
+            name: &target.name,
+            target_type: &target.target_type,
+            build: target.build.as_ref(),
+            site: target.site.as_ref(),
+            kv_namespaces: target
+                .kv_namespaces
+                .iter()
+                .map(|namespace| (namespace.binding.as_str(), namespace.id.as_str()))
+                .collect(),
+            durable_objects: target.durable_objects.as_ref(),
+            migrations: target.migrations.as_ref(),
+            usage_model: target.usage_model.as_ref(),
+            vars: target.vars.as_ref().map(sorted),
+            text_blobs: target.text_blobs.as_ref().map(sorted),
+            wasm_modules: target.wasm_modules.as_ref().map(sorted),
+            script_path: target.script_path.as_ref(),
+        }
+    }
+}
+
+fn sorted<V>(map: &HashMap<String, V>) -> BTreeMap<&String, &V> {
+    map.iter().collect()
+}
+
+/// hashes the target's configuration and every file that is uploaded for it,
+/// so a change to either means a fresh upload
+pub(super) fn hash(target: &Target) -> Result<String> {
+    let mut hasher = XxHash64::default();
+    hasher.write(&serde_json::to_vec(&Config::from(target))?);
+    for path in bundle_paths(target)? {
+        hash_path(&mut hasher, &path)?;
+    }
+    Ok(format!("{:x}", hasher.finish()))
+}
+
+/// the size in bytes of every file that is uploaded for the target
+pub(super) fn size(target: &Target) -> Result<u64> {
+    bundle_paths(target)?
+        .iter()
+        .map(|path| path_size(path))
+        .sum()
+}
+
+/// the files `upload::form::build` reads for a target
+fn bundle_paths(target: &Target) -> Result<Vec<PathBuf>> {
+    if let Some(script_path) = &target.script_path {
+        return Ok(vec![script_path.clone()]);
+    }
+
+    let mut paths = match &target.target_type {
+        TargetType::Rust => vec![PathBuf::from("./pkg"), PathBuf::from("./worker/generated")],
+        TargetType::Webpack => vec![target.package_dir()?.join("worker")],
+        TargetType::JavaScript => match target.build.as_ref().map(|build| &build.upload) {
+            Some(UploadFormat::Modules { dir, .. }) => vec![dir.clone()],
+            _ => {
+                let package_dir = target.package_dir()?;
+                let package = Package::new(&package_dir)?;
+                vec![package_dir.join(package.main(&package_dir)?)]
+            }
+        },
+    };
+
+    if let Some(blobs) = &target.text_blobs {
+        paths.extend(blobs.values().cloned());
+    }
+    if let Some(modules) = &target.wasm_modules {
+        paths.extend(modules.values().cloned());
+    }
+    Ok(paths)
+}
+
+fn hash_path(hasher: &mut XxHash64, path: &Path) -> Result<()> {
+    if path.is_dir() {
+        for entry in sorted_entries(path)? {
+            hash_path(hasher, &entry)?;
+        }
+    } else if path.is_file() {
+        hasher.write(path.to_string_lossy().as_bytes());
+        hasher.write(&fs::read(path)?);
+    }
+    Ok(())
+}
+
+fn path_size(path: &Path) -> Result<u64> {
+    if path.is_dir() {
+        sorted_entries(path)?
+            .iter()
+            .map(|entry| path_size(entry))
+            .sum()
+    } else if path.is_file() {
+        Ok(fs::metadata(path)?.len())
+    } else {
+        Ok(0)
+    }
+}
+
+/// directory order isn't stable, but the hash needs to be
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(path: &Path) -> u64 {
+        let mut hasher = XxHash64::default();
+        hash_path(&mut hasher, path).unwrap();
+        hasher.finish()
+    }
+
+    #[test]
+    fn hashes_change_with_the_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.js"), "export default {}").unwrap();
+        fs::write(dir.path().join("b.wasm"), [0, 1, 2]).unwrap();
+
+        let before = hash(dir.path());
+        assert_eq!(before, hash(dir.path()));
+
+        fs::write(dir.path().join("a.js"), "export default { fetch }").unwrap();
+        assert_ne!(before, hash(dir.path()));
+    }
+
+    #[test]
+    fn configs_serialize_the_same_whatever_the_order_of_their_maps() {
+        let target = |names: &[&str]| Target {
+            name: "worker".to_string(),
+            vars: Some(
+                names
+                    .iter()
+                    .map(|name| (name.to_string(), "value".to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let config = |target: &Target| serde_json::to_vec(&Config::from(target)).unwrap();
+
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let mut reversed = names;
+        reversed.reverse();
+        assert_eq!(config(&target(&names)), config(&target(&reversed)));
+        assert_ne!(config(&target(&names)), config(&target(&names[1..])));
+    }
+
+    #[test]
+    fn sizes_count_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("a.js"), "export default {}").unwrap();
+        fs::write(dir.path().join("nested").join("b.wasm"), [0, 1, 2]).unwrap();
+
+        assert_eq!(path_size(dir.path()).unwrap(), 20);
+    }
+}
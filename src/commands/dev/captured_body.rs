@@ -0,0 +1,164 @@
+//! `--har` and `--diff` keep the start of each response body as it streams to
+//! the client. A gzipped body, whether dev compressed it with `--compress` or
+//! the Worker sent it that way, is kept gunzipped: HAR records bodies decoded,
+//! and `--diff` can only normalize and compare text it can read.
+use crate::commands::dev::har::BODY_LIMIT;
+
+use std::io::{self, Write};
+
+use flate2::write::GzDecoder;
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, CONTENT_ENCODING};
+
+/// a response body being captured, chunk by chunk
+pub(super) struct Capture {
+    sent: Limited,
+    /// while the body is gzipped and gunzips cleanly
+    gunzipped: Option<GzDecoder<Limited>>,
+}
+
+/// what was captured of a response body
+pub(super) struct Captured {
+    /// the first `BODY_LIMIT` bytes of the body, decoded
+    pub(super) body: Bytes,
+    /// the size of the whole body, decoded
+    pub(super) size: usize,
+    /// the size of the body as it was sent to the client
+    pub(super) sent: usize,
+}
+
+impl Capture {
+    /// starts capturing the body of a response with `headers`
+    pub(super) fn new(headers: &HeaderMap) -> Capture {
+        let gzipped = headers.get(CONTENT_ENCODING).map_or(false, |encoding| {
+            encoding.as_bytes().eq_ignore_ascii_case(b"gzip")
+        });
+        Capture {
+            sent: Limited::default(),
+            gunzipped: if gzipped {
+                Some(GzDecoder::new(Limited::default()))
+            } else {
+                None
+            },
+        }
+    }
+
+    pub(super) fn write(&mut self, chunk: &[u8]) {
+        self.sent.keep(chunk);
+        if let Some(decoder) = &mut self.gunzipped {
+            if let Err(e) = decoder.write_all(chunk) {
+                log::debug!(
+                    "Failed to gunzip a response body, keeping it as sent: {}",
+                    e
+                );
+                self.gunzipped = None;
+            }
+        }
+    }
+
+    pub(super) fn finish(self) -> Captured {
+        let sent = self.sent.size;
+        let decoded = match self.gunzipped.map(GzDecoder::finish) {
+            Some(Ok(gunzipped)) => gunzipped,
+            Some(Err(e)) => {
+                log::debug!(
+                    "Failed to gunzip a response body, keeping it as sent: {}",
+                    e
+                );
+                self.sent
+            }
+            None => self.sent,
+        };
+        Captured {
+            body: Bytes::from(decoded.kept),
+            size: decoded.size,
+            sent,
+        }
+    }
+}
+
+/// keeps the first `BODY_LIMIT` bytes written to it, and counts all of them
+#[derive(Default)]
+struct Limited {
+    kept: Vec<u8>,
+    size: usize,
+}
+
+impl Limited {
+    fn keep(&mut self, buf: &[u8]) {
+        self.size += buf.len();
+        let remaining = BODY_LIMIT.saturating_sub(self.kept.len());
+        self.kept
+            .extend_from_slice(&buf[..remaining.min(buf.len())]);
+    }
+}
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.keep(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use hyper::header::HeaderValue;
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gzipped() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        headers
+    }
+
+    #[test]
+    fn gzipped_bodies_are_captured_decoded() {
+        let body = b"{\"date\":\"Mon, 20 Apr 2020 15:25:54 GMT\"}".repeat(10);
+        let sent = gzip(&body);
+
+        let mut capture = Capture::new(&gzipped());
+        for chunk in sent.chunks(7) {
+            capture.write(chunk);
+        }
+        let captured = capture.finish();
+        assert_eq!(captured.body, body);
+        assert_eq!(captured.size, body.len());
+        assert_eq!(captured.sent, sent.len());
+    }
+
+    #[test]
+    fn decoded_bodies_are_captured_up_to_the_limit() {
+        let body = vec![b'a'; BODY_LIMIT + 1];
+        let mut capture = Capture::new(&gzipped());
+        capture.write(&gzip(&body));
+        let captured = capture.finish();
+        assert_eq!(captured.body.len(), BODY_LIMIT);
+        assert_eq!(captured.size, BODY_LIMIT + 1);
+    }
+
+    #[test]
+    fn other_bodies_are_captured_as_sent() {
+        let mut capture = Capture::new(&HeaderMap::new());
+        capture.write(b"hello");
+        let captured = capture.finish();
+        assert_eq!(captured.body, "hello");
+        assert_eq!((captured.size, captured.sent), (5, 5));
+
+        // and so is one that only claims to be gzipped
+        let mut capture = Capture::new(&gzipped());
+        capture.write(b"not gzip");
+        assert_eq!(capture.finish().body, "not gzip");
+    }
+}
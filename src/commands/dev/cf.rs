@@ -0,0 +1,128 @@
+//! `--cf`, `--cf-country` and `--cf-colo` describe where a dev request should
+//! appear to come from. The preview service fills in `request.cf` itself and
+//! doesn't let clients override it, so the properties are sent to the Worker
+//! as JSON in the `x-wrangler-dev-cf` request header instead, for it to fall
+//! back on in dev:
+//!
+//! ```js
+//! const cf = JSON.parse(request.headers.get('x-wrangler-dev-cf') || 'null') || request.cf
+//! ```
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::{Map, Value};
+
+use crate::commands::dev::DevOptions;
+
+pub(super) const CF_HEADER: &str = "x-wrangler-dev-cf";
+
+/// the properties of `request.cf` that can be given to `--cf`
+const SUPPORTED: &[&str] = &[
+    "asn",
+    "botManagement",
+    "city",
+    "clientTcpRtt",
+    "colo",
+    "continent",
+    "country",
+    "httpProtocol",
+    "latitude",
+    "longitude",
+    "metroCode",
+    "postalCode",
+    "region",
+    "regionCode",
+    "timezone",
+    "tlsCipher",
+    "tlsVersion",
+];
+
+/// parses the JSON object given to `--cf`, which may only set supported properties
+pub fn parse(json: &str) -> Result<Map<String, Value>> {
+    let properties = match serde_json::from_str(json)? {
+        Value::Object(properties) => properties,
+        _ => anyhow::bail!("Expected a JSON object, like {{\"country\": \"US\"}}"),
+    };
+
+    if let Some(unsupported) = properties
+        .keys()
+        .find(|key| !SUPPORTED.contains(&key.as_str()))
+    {
+        anyhow::bail!(
+            "{} is not a supported request.cf property, use one of {}",
+            unsupported,
+            SUPPORTED.join(", ")
+        )
+    }
+    Ok(properties)
+}
+
+/// parses a two letter country code, like US
+pub fn parse_country(country: &str) -> Result<String> {
+    parse_code(country, 2, "country code, like US")
+}
+
+/// parses a three letter colo code, like SFO
+pub fn parse_colo(colo: &str) -> Result<String> {
+    parse_code(colo, 3, "colo, like SFO")
+}
+
+fn parse_code(code: &str, len: usize, expected: &str) -> Result<String> {
+    if code.len() == len && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_uppercase())
+    } else {
+        Err(anyhow!("{} is not a {}", code, expected))
+    }
+}
+
+/// the header value for the properties set by `--cf` and its shortcuts,
+/// which take precedence over the same properties in the JSON
+pub(super) fn properties(options: &DevOptions) -> Result<Option<HeaderValue>> {
+    let mut properties = options.cf.clone().unwrap_or_default();
+    if let Some(country) = &options.cf_country {
+        properties.insert("country".to_string(), Value::from(country.as_str()));
+    }
+    if let Some(colo) = &options.cf_colo {
+        properties.insert("colo".to_string(), Value::from(colo.as_str()));
+    }
+
+    if options.cf.is_none() && properties.is_empty() {
+        return Ok(None);
+    }
+    let json = serde_json::to_string(&properties)?;
+    Ok(Some(HeaderValue::from_str(&json)?))
+}
+
+/// replaces whatever the client sent in the properties header with the dev properties
+pub(super) fn inject(headers: &mut HeaderMap, properties: &HeaderValue) {
+    headers.insert(HeaderName::from_static(CF_HEADER), properties.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_supported_properties_are_accepted() {
+        let properties = parse(r#"{"country": "DE", "latitude": "52.52"}"#).unwrap();
+        assert_eq!(properties["country"], "DE");
+
+        assert!(parse(r#"{"countryCode": "DE"}"#).is_err());
+        assert!(parse(r#"["DE"]"#).is_err());
+    }
+
+    #[test]
+    fn shortcuts_override_the_json() {
+        let options = DevOptions {
+            cf: Some(parse(r#"{"country": "DE", "city": "Berlin"}"#).unwrap()),
+            cf_country: Some(parse_country("us").unwrap()),
+            ..Default::default()
+        };
+        let sent = properties(&options).unwrap().unwrap();
+        let sent: Value = serde_json::from_slice(sent.as_bytes()).unwrap();
+        assert_eq!(sent["country"], "US");
+        assert_eq!(sent["city"], "Berlin");
+
+        assert!(properties(&DevOptions::default()).unwrap().is_none());
+        assert!(parse_colo("SF").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! `--emulate-cf-headers` adds the headers Cloudflare puts on responses in
+//! production to those from the preview service, which leaves them out, for
+//! clients and tests that look for them. A header the Worker set itself is left
+//! alone. The values are made up, and `cf-ray` is made to look it, so it can't
+//! be mistaken for a real request to Cloudflare:
+//!
+//! | Header            | Value                                                     |
+//! | ----------------- | --------------------------------------------------------- |
+//! | `cf-ray`          | a random id from a `DEV` colo, like `8f3a9b2e1c04d5c7-DEV` |
+//! | `cf-cache-status` | `DYNAMIC`, as a response from a Worker isn't cached        |
+//! | `server`          | `cloudflare`                                              |
+//! | `alt-svc`         | `h3=":443"; ma=86400`                                     |
+//!
+//! Only the preview service used without authentication needs them, as
+//! responses in an authenticated session come through Cloudflare's edge.
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, ALT_SVC, SERVER};
+
+const CF_RAY: &str = "cf-ray";
+const CF_CACHE_STATUS: &str = "cf-cache-status";
+
+/// adds the Cloudflare headers the response doesn't already have
+pub(super) fn emulate(headers: &mut HeaderMap) {
+    let ray = format!("{:016x}-DEV", rand::random::<u64>());
+    let emulated = vec![
+        (
+            HeaderName::from_static(CF_RAY),
+            HeaderValue::from_str(&ray).expect("a ray id is a valid header value"),
+        ),
+        (
+            HeaderName::from_static(CF_CACHE_STATUS),
+            HeaderValue::from_static("DYNAMIC"),
+        ),
+        (SERVER, HeaderValue::from_static("cloudflare")),
+        (ALT_SVC, HeaderValue::from_static("h3=\":443\"; ma=86400")),
+    ];
+    for (name, value) in emulated {
+        headers.entry(name).or_insert(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_headers_are_added() {
+        let mut headers = HeaderMap::new();
+        emulate(&mut headers);
+        assert_eq!(headers[CF_CACHE_STATUS], "DYNAMIC");
+        assert_eq!(headers[SERVER], "cloudflare");
+        assert_eq!(headers[ALT_SVC], "h3=\":443\"; ma=86400");
+
+        let ray = headers[CF_RAY].to_str().unwrap();
+        let (id, colo) = ray.split_once('-').unwrap();
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(colo, "DEV");
+    }
+
+    #[test]
+    fn headers_set_by_the_worker_are_kept() {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, HeaderValue::from_static("my-worker"));
+        headers.insert(CF_CACHE_STATUS, HeaderValue::from_static("HIT"));
+        emulate(&mut headers);
+        assert_eq!(headers[SERVER], "my-worker");
+        assert_eq!(headers[CF_CACHE_STATUS], "HIT");
+        assert_eq!(headers.get_all(SERVER).iter().count(), 1);
+        assert!(headers.contains_key(CF_RAY));
+    }
+}
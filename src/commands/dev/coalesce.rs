@@ -0,0 +1,184 @@
+//! `--coalesce` sends only one of several identical GET requests that are in
+//! flight at the same time to the preview service, and answers all of them
+//! with its response. Pages that fire the same request more than once, like
+//! React's StrictMode does, then only cost the preview service one request.
+//!
+//! Requests are identical when their host, path, query and the request headers
+//! in `VARY` match. Only GETs without a body are ever coalesced.
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::HeaderMap;
+use hyper::{Body, Method, Request, Response, StatusCode, Version};
+use once_cell::sync::Lazy;
+
+/// request headers that commonly change the response, so must match to coalesce
+const VARY: &[&str] = &[
+    "accept",
+    "accept-encoding",
+    "accept-language",
+    "authorization",
+    "cookie",
+];
+
+type Flight = Shared<BoxFuture<'static, Result<Buffered, String>>>;
+
+static IN_FLIGHT: Lazy<Mutex<HashMap<String, Flight>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// a response read into memory so it can be sent to every coalesced request
+#[derive(Clone)]
+struct Buffered {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Buffered {
+    async fn read(resp: Response<Body>) -> Result<Buffered> {
+        let (parts, body) = resp.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        Ok(Buffered {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+        })
+    }
+
+    fn into_response(self) -> Response<Body> {
+        let mut resp = Response::new(Body::from(self.body));
+        *resp.status_mut() = self.status;
+        *resp.version_mut() = self.version;
+        *resp.headers_mut() = self.headers;
+        resp
+    }
+}
+
+/// identifies the requests that can share a response with this one, if it can be coalesced
+pub(super) fn key(req: &Request<Body>, host: &str) -> Option<String> {
+    if req.method() != Method::GET || !req.body().is_end_stream() {
+        return None;
+    }
+
+    let mut key = format!("{}{}", host, req.uri());
+    for name in VARY {
+        for value in req.headers().get_all(*name) {
+            key.push_str(&format!(
+                "\n{}: {}",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
+        }
+    }
+    Some(key)
+}
+
+/// waits for the response to an identical request if one is in flight,
+/// otherwise sends this one with `upstream` for others to wait on
+///
+/// returns the response, and whether it was shared with an earlier request
+pub(super) async fn fetch<Fut>(key: String, upstream: Fut) -> Result<(Response<Body>, bool)>
+where
+    Fut: Future<Output = Result<Response<Body>>> + Send + 'static,
+{
+    let (flight, coalesced) = {
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        match in_flight.get(&key) {
+            Some(flight) => (flight.clone(), true),
+            None => {
+                let flight = send(key.clone(), upstream);
+                in_flight.insert(key, flight.clone());
+                (flight, false)
+            }
+        }
+    };
+
+    let buffered = flight.await.map_err(anyhow::Error::msg)?;
+    Ok((buffered.into_response(), coalesced))
+}
+
+fn send<Fut>(key: String, upstream: Fut) -> Flight
+where
+    Fut: Future<Output = Result<Response<Body>>> + Send + 'static,
+{
+    async move {
+        let buffered = match upstream.await {
+            Ok(resp) => Buffered::read(resp).await,
+            Err(e) => Err(e),
+        };
+        // the flight is over as soon as it has a response,
+        // requests from now on are sent upstream again
+        IN_FLIGHT.lock().unwrap().remove(&key);
+        buffered.map_err(|e| e.to_string())
+    }
+    .boxed()
+    .shared()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bodyless_gets_are_coalesced() {
+        let get = Request::get("/").body(Body::empty()).unwrap();
+        let post = Request::post("/").body(Body::empty()).unwrap();
+        let with_body = Request::get("/").body(Body::from("body")).unwrap();
+
+        assert!(key(&get, "example.com").is_some());
+        assert!(key(&post, "example.com").is_none());
+        assert!(key(&with_body, "example.com").is_none());
+    }
+
+    #[test]
+    fn requests_that_vary_are_not_coalesced() {
+        let english = Request::get("/")
+            .header("accept-language", "en")
+            .body(Body::empty())
+            .unwrap();
+        let french = Request::get("/")
+            .header("accept-language", "fr")
+            .body(Body::empty())
+            .unwrap();
+        let untracked = Request::get("/")
+            .header("accept-language", "en")
+            .header("x-request-id", "1")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_ne!(key(&english, "example.com"), key(&french, "example.com"));
+        assert_eq!(key(&english, "example.com"), key(&untracked, "example.com"));
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_a_response() {
+        let key = "coalesce.test/".to_string();
+        let (first, second) = tokio::join!(
+            fetch(key.clone(), async {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                Ok(Response::new(Body::from("first")))
+            }),
+            fetch(key.clone(), async {
+                Ok(Response::new(Body::from("second")))
+            }),
+        );
+
+        let (first, first_coalesced) = first.unwrap();
+        let (second, second_coalesced) = second.unwrap();
+        assert!(!first_coalesced);
+        assert!(second_coalesced);
+        assert_eq!(
+            hyper::body::to_bytes(first.into_body()).await.unwrap(),
+            "first"
+        );
+        assert_eq!(
+            hyper::body::to_bytes(second.into_body()).await.unwrap(),
+            "first"
+        );
+    }
+}
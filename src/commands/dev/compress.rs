@@ -0,0 +1,153 @@
+//! `--compress` gzips text responses for clients that accept it, as Cloudflare
+//! does in production, so a frontend's handling of compressed responses is
+//! exercised in dev too. Only gzip is offered, brotli is not.
+//!
+//! Responses the Worker already encoded, ones marked `Cache-Control:
+//! no-transform`, event streams, byte ranges and anything that isn't text are
+//! passed on as they are, as are responses to HEAD requests, which have no body
+//! to compress. Compressing means reading the whole body first. Like production, a
+//! compressed response gets `Vary: Accept-Encoding` and any strong `ETag` is
+//! made weak, since it no longer describes the bytes sent.
+use std::io::Write;
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::header::{
+    HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    VARY,
+};
+use hyper::{Body, Response, StatusCode};
+
+use crate::commands::dev::{replace, response_body};
+
+/// gzips the body of `resp` if the client accepts it and it's worth compressing
+pub(super) async fn gzip(
+    resp: Response<Body>,
+    accept_encoding: Option<&HeaderValue>,
+) -> Result<Response<Body>> {
+    if !accepts_gzip(accept_encoding) || !is_compressible(resp.status(), resp.headers()) {
+        return Ok(resp);
+    }
+
+    let (mut parts, body) = resp.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&body)?;
+    let compressed = encoder.finish()?;
+
+    let headers = &mut parts.headers;
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    headers.append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    if let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok()) {
+        if !etag.starts_with("W/") {
+            let weak = HeaderValue::from_str(&format!("W/{}", etag))?;
+            headers.insert(ETAG, weak);
+        }
+    }
+    Ok(response_body::replace(parts, compressed))
+}
+
+/// whether `Accept-Encoding` lists gzip, or `*`, without a q of 0
+fn accepts_gzip(accept_encoding: Option<&HeaderValue>) -> bool {
+    let accept_encoding = match accept_encoding.and_then(|value| value.to_str().ok()) {
+        Some(accept_encoding) => accept_encoding,
+        None => return false,
+    };
+    accept_encoding.split(',').any(|coding| {
+        let mut params = coding.split(';').map(str::trim);
+        let name = params.next().unwrap_or("").to_lowercase();
+        let refused = params.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .map_or(false, |q| q == 0.0)
+        });
+        (name == "gzip" || name == "*") && !refused
+    })
+}
+
+fn is_compressible(status: StatusCode, headers: &HeaderMap) -> bool {
+    let no_body = status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED;
+    // a compressed range wouldn't be the bytes its Content-Range says it is
+    let range = status == StatusCode::PARTIAL_CONTENT || headers.contains_key(CONTENT_RANGE);
+    let no_transform = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+    // each event has to reach the client as it's sent, so streams can't be buffered
+    let event_stream = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, |content_type| {
+            content_type.to_lowercase().starts_with("text/event-stream")
+        });
+
+    // `is_text` is false for a body that is already encoded
+    !no_body && !range && !no_transform && !event_stream && replace::is_text(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn html(cache_control: Option<&str>) -> Response<Body> {
+        let mut builder = Response::builder()
+            .header(CONTENT_TYPE, "text/html")
+            .header(ETAG, "\"abc\"");
+        if let Some(cache_control) = cache_control {
+            builder = builder.header(CACHE_CONTROL, cache_control);
+        }
+        builder.body(Body::from("<h1>hello</h1>")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn text_is_gzipped_for_clients_that_accept_it() {
+        let accept = HeaderValue::from_static("br, gzip;q=0.8");
+        let resp = gzip(html(None), Some(&accept)).await.unwrap();
+        assert_eq!(resp.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.headers()[ETAG], "W/\"abc\"");
+
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        let mut decoded = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "<h1>hello</h1>");
+    }
+
+    #[tokio::test]
+    async fn some_responses_are_left_alone() {
+        let refused = HeaderValue::from_static("gzip;q=0");
+        let resp = gzip(html(None), Some(&refused)).await.unwrap();
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+
+        let accept = HeaderValue::from_static("gzip");
+        let resp = gzip(html(Some("public, no-transform")), Some(&accept))
+            .await
+            .unwrap();
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+
+        let resp = gzip(html(None), None).await.unwrap();
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+    }
+
+    #[tokio::test]
+    async fn byte_ranges_are_left_alone() {
+        let accept = HeaderValue::from_static("gzip");
+        let mut partial = html(None);
+        *partial.status_mut() = StatusCode::PARTIAL_CONTENT;
+        partial
+            .headers_mut()
+            .insert(CONTENT_RANGE, HeaderValue::from_static("bytes 0-13/100"));
+        let resp = gzip(partial, Some(&accept)).await.unwrap();
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(resp.headers()[CONTENT_RANGE], "bytes 0-13/100");
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        assert_eq!(body, "<h1>hello</h1>");
+    }
+}
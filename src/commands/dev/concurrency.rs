@@ -0,0 +1,99 @@
+//! `--upstream-concurrency` caps how many requests are in flight to the preview
+//! service at once, however many connections are open to wrangler dev, so a load
+//! test doesn't trip the preview service's rate limits. Requests over the cap wait
+//! their turn, for up to `--upstream-queue-timeout` if it is set
+use crate::commands::dev::error_response;
+use crate::terminal::message::{Message, StdErr};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::header::{HeaderValue, RETRY_AFTER};
+use hyper::{Body, Response, StatusCode};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug)]
+pub struct UpstreamLimit {
+    permits: Arc<Semaphore>,
+    max: usize,
+    queue_timeout: Option<Duration>,
+    /// the first queued request is warned about, the rest are only logged
+    warned: AtomicBool,
+}
+
+impl UpstreamLimit {
+    pub fn new(max: usize, queue_timeout: Option<Duration>) -> UpstreamLimit {
+        UpstreamLimit {
+            permits: Arc::new(Semaphore::new(max)),
+            max,
+            queue_timeout,
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// waits for a slot to send `description` upstream in, which is held until
+    /// the permit is dropped, or builds a 503 if it waited too long
+    pub(super) async fn acquire(
+        &self,
+        description: &str,
+    ) -> Result<OwnedSemaphorePermit, Response<Body>> {
+        if let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let queued = format!(
+            "Queuing {} as {} requests are already in flight to the preview service (--upstream-concurrency)",
+            description, self.max
+        );
+        if self.warned.swap(true, Ordering::SeqCst) {
+            log::info!("{}", queued);
+        } else {
+            StdErr::warn(&queued);
+        }
+
+        let permit = Arc::clone(&self.permits).acquire_owned();
+        let permit = match self.queue_timeout {
+            Some(queue_timeout) => match tokio::time::timeout(queue_timeout, permit).await {
+                Ok(permit) => permit,
+                Err(_) => {
+                    StdErr::warn(&format!(
+                        "Answered {} with a 503 after it was queued for {}ms",
+                        description,
+                        queue_timeout.as_millis()
+                    ));
+                    return Err(queue_full());
+                }
+            },
+            None => permit.await,
+        };
+        Ok(permit.expect("the upstream semaphore is never closed"))
+    }
+}
+
+fn queue_full() -> Response<Body> {
+    let mut resp = error_response::build(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "wrangler dev has too many requests in flight to the preview service, try again shortly",
+    );
+    resp.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_over_the_cap_wait_then_give_up() {
+        let limit = UpstreamLimit::new(1, Some(Duration::from_millis(10)));
+
+        let permit = limit.acquire("GET /a").await.unwrap();
+        let resp = limit.acquire("GET /b").await.unwrap_err();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(permit);
+        assert!(limit.acquire("GET /c").await.is_ok());
+    }
+}
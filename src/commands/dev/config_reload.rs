@@ -0,0 +1,172 @@
+//! `--reload-on-config-change` watches wrangler.toml on its own, apart from the
+//! project's source, and uploads the Worker again when it changes, so vars and
+//! bindings can be tweaked without restarting `wrangler dev` or touching the
+//! source. The source watcher leaves the file alone, so a change isn't uploaded
+//! twice.
+//!
+//! A changed file is read for the environment given with `--env`, and only used
+//! if it's valid. If it isn't, the error is logged and the Worker keeps running
+//! with the configuration it had. Everything that makes up the Worker's upload
+//! is reloaded: vars, KV namespaces, Durable Objects, text blobs, Wasm modules
+//! and the build command. Routes and the `[dev]` settings are only read at
+//! startup, so changing them still needs a restart.
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::commands::dev::rebuild;
+use crate::settings::toml::{Manifest, Target};
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
+use crate::watch::COOLDOWN_PERIOD;
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+/// the target from the latest reload, until the watcher uploads it
+static RELOADED: Lazy<Mutex<Option<Target>>> = Lazy::new(|| Mutex::new(None));
+
+struct Config {
+    path: PathBuf,
+    environment: Option<String>,
+}
+
+impl Config {
+    fn load(&self) -> Result<Target> {
+        let manifest = Manifest::new(&self.path)?;
+        manifest.get_target(self.environment.as_deref(), true)
+    }
+}
+
+/// reloads the config file at `path` for `environment` whenever it changes,
+/// once the project is being watched
+pub fn reload_config_on_change(path: &Path, environment: Option<&str>) -> Result<()> {
+    let config = Config {
+        path: env::current_dir()?.join(path),
+        environment: environment.map(String::from),
+    };
+    if CONFIG.set(config).is_err() {
+        log::debug!("--reload-on-config-change was already set up");
+    }
+    Ok(())
+}
+
+/// the config file watched with `--reload-on-config-change`
+pub(super) fn watched() -> Option<&'static Path> {
+    CONFIG.get().map(|config| config.path.as_path())
+}
+
+/// starts watching the config file, if `--reload-on-config-change` was given
+pub(super) fn start() -> Result<()> {
+    let config = match CONFIG.get() {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::watcher(sender, Duration::from_secs(1))?;
+    // the directory is watched, as editors often replace the file rather than write to it
+    let dir = config.path.parent().unwrap_or_else(|| Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    thread::spawn(move || {
+        // watching stops once the watcher is dropped
+        let _watcher = watcher;
+        while let Ok(event) = receiver.recv() {
+            if !changes(&event, &config.path) {
+                continue;
+            }
+            // saving in an editor can take a few writes
+            while receiver.recv_timeout(COOLDOWN_PERIOD).is_ok() {}
+            reload(config);
+        }
+    });
+    Ok(())
+}
+
+fn reload(config: &Config) {
+    let file = config.path.display().to_string();
+    StdErr::working(&format!(
+        "{} changed, reloading it...",
+        styles::highlight(&file)
+    ));
+    match config.load() {
+        Ok(target) => {
+            *RELOADED.lock().unwrap() = Some(target);
+            // the forced rebuild is what uploads it, and reports how that went
+            if rebuild::force().is_none() {
+                StdErr::warn("The configuration was reloaded, but it will only be used once the Worker is rebuilt");
+            }
+        }
+        Err(e) => StdErr::warn(&format!(
+            "Kept the previous configuration, as {} is not valid: {}",
+            file, e
+        )),
+    }
+}
+
+/// the configuration reloaded since the Worker was last uploaded, if any, to upload it
+/// with. It keeps what was given on the command line, like a script from `--script`
+pub(super) fn take(current: &Target) -> Option<Target> {
+    let mut reloaded = RELOADED.lock().unwrap().take()?;
+    reloaded.script_path = current.script_path.clone();
+    Some(reloaded)
+}
+
+/// whether `event` is a change to the config file at `path`
+fn changes(event: &DebouncedEvent, path: &Path) -> bool {
+    match event {
+        DebouncedEvent::Create(changed)
+        | DebouncedEvent::Write(changed)
+        | DebouncedEvent::Rename(_, changed) => changed == path,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changes_to_the_config_file_count() {
+        let config = Path::new("/project/wrangler.toml");
+        let write = |path: &str| DebouncedEvent::Write(PathBuf::from(path));
+
+        assert!(changes(&write("/project/wrangler.toml"), config));
+        assert!(!changes(&write("/project/index.js"), config));
+        // editors that save to a temporary file and move it into place
+        let renamed = DebouncedEvent::Rename(
+            PathBuf::from("/project/.wrangler.toml.swp"),
+            PathBuf::from("/project/wrangler.toml"),
+        );
+        assert!(changes(&renamed, config));
+        assert!(!changes(
+            &DebouncedEvent::Remove(PathBuf::from("/project/wrangler.toml")),
+            config
+        ));
+    }
+
+    #[test]
+    fn invalid_config_is_not_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wrangler.toml");
+        std::fs::write(
+            &path,
+            "name = \"worker\"\ntype = \"javascript\"\n[vars]\nA = \"1\"\n",
+        )
+        .unwrap();
+        let config = Config {
+            path: path.clone(),
+            environment: None,
+        };
+        let target = config.load().unwrap();
+        assert_eq!(target.vars.unwrap()["A"], "1");
+
+        std::fs::write(&path, "name = \"worker\"\ntype = \"javascript\"\n[vars\n").unwrap();
+        assert!(config.load().is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! `--cors` answers CORS preflights locally, without involving the Worker, and adds
+//! the matching headers to every response to a cross-origin request
+use crate::commands::dev::DevOptions;
+
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// how long browsers may cache a preflight answered by wrangler dev, in seconds
+const MAX_AGE: &str = "600";
+
+/// a preflight is an OPTIONS request asking whether a cross-origin request is allowed
+pub(super) fn is_preflight(req: &Request<Body>) -> bool {
+    req.method() == Method::OPTIONS
+        && req.headers().contains_key(ORIGIN)
+        && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// allows whatever the preflight asks for, unless `--cors-allow-*` says otherwise
+pub(super) fn preflight(req: &Request<Body>, options: &DevOptions) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = StatusCode::NO_CONTENT;
+
+    let requested = req.headers();
+    let headers = resp.headers_mut();
+    allow_origin(headers, requested.get(ORIGIN), options);
+
+    let methods = options
+        .cors_allow_methods
+        .as_ref()
+        .or_else(|| requested.get(ACCESS_CONTROL_REQUEST_METHOD));
+    if let Some(methods) = methods {
+        headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods.clone());
+    }
+
+    let allow_headers = options
+        .cors_allow_headers
+        .as_ref()
+        .or_else(|| requested.get(ACCESS_CONTROL_REQUEST_HEADERS));
+    if let Some(allow_headers) = allow_headers {
+        headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers.clone());
+    }
+
+    headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static(MAX_AGE));
+    resp
+}
+
+/// adds the CORS headers a browser needs to read the response to a cross-origin request,
+/// replacing any the Worker set itself
+pub(super) fn allow(resp: &mut Response<Body>, origin: Option<&HeaderValue>, options: &DevOptions) {
+    if origin.is_some() {
+        allow_origin(resp.headers_mut(), origin, options);
+    }
+}
+
+/// `--cors-allow-origin`, or the origin of the request so credentials are allowed too
+fn allow_origin(headers: &mut HeaderMap, origin: Option<&HeaderValue>, options: &DevOptions) {
+    match (&options.cors_allow_origin, origin) {
+        (Some(allowed), _) => {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allowed.clone());
+        }
+        (None, Some(origin)) => {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+            // the response depends on the origin, so caches must keep them apart
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preflight_request() -> Request<Body> {
+        Request::options("/api")
+            .header(ORIGIN, "http://localhost:3000")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn only_options_with_a_requested_method_are_preflights() {
+        assert!(is_preflight(&preflight_request()));
+
+        let options = Request::options("/api")
+            .header(ORIGIN, "http://localhost:3000")
+            .body(Body::empty())
+            .unwrap();
+        assert!(!is_preflight(&options));
+
+        let get = Request::get("/api")
+            .header(ORIGIN, "http://localhost:3000")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .body(Body::empty())
+            .unwrap();
+        assert!(!is_preflight(&get));
+    }
+
+    #[test]
+    fn preflights_allow_what_was_requested() {
+        let resp = preflight(&preflight_request(), &DevOptions::default());
+        let headers = resp.headers();
+
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "PUT");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[VARY], "Origin");
+    }
+
+    #[test]
+    fn configured_headers_replace_what_was_requested() {
+        let options = DevOptions {
+            cors_allow_origin: Some(HeaderValue::from_static("*")),
+            cors_allow_methods: Some(HeaderValue::from_static("GET, POST")),
+            ..Default::default()
+        };
+        let resp = preflight(&preflight_request(), &options);
+        let headers = resp.headers();
+
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+        assert!(!headers.contains_key(VARY));
+    }
+}
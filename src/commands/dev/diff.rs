@@ -0,0 +1,405 @@
+//! `--diff <file>` compares every response with the one recorded for the same
+//! request in a HAR file written by `--har`, turning a dev session into an
+//! approval test of the Worker's behavior. Differences are logged as they're
+//! found, and counted in a summary when the session ends. With `--diff-fail`,
+//! any difference makes `wrangler dev` exit with an error.
+//!
+//! Requests are matched by method, path and query string, whatever host and
+//! port the baseline was recorded on. A request made several times is compared
+//! with each recording in turn, and with the last once they run out. A request
+//! the baseline has no recording of counts as a difference.
+//!
+//! The status, the `Content-Type`, `Content-Encoding`, `Location` and
+//! `Cache-Control` headers and the body are compared. Bodies are compared as
+//! `--har` records them: the first 64KiB, and base64 encoded if they aren't
+//! UTF-8. Before comparing, values that change from one run to the next are
+//! replaced with placeholders: dates and timestamps, UUIDs, and hex ids of 16
+//! characters or more, like the start of a `cf-ray`.
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use hyper::body::HttpBody;
+use hyper::{Body, Response, StatusCode};
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::commands::dev::captured_body::Capture;
+use crate::terminal::message::{Message, StdErr};
+
+const COMPARED_HEADERS: &[&str] = &[
+    "content-type",
+    "content-encoding",
+    "location",
+    "cache-control",
+];
+
+/// values that differ between runs, and what they're replaced with before comparing
+static VOLATILE: Lazy<Vec<(Regex, &str)>> = Lazy::new(|| {
+    vec![
+        (
+            r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})?",
+            "<date>",
+        ),
+        (
+            r"(Mon|Tue|Wed|Thu|Fri|Sat|Sun), \d{2} [A-Z][a-z]{2} \d{4} \d{2}:\d{2}:\d{2} GMT",
+            "<date>",
+        ),
+        (
+            r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+            "<id>",
+        ),
+        (r"\b[0-9a-fA-F]{16,}\b", "<id>"),
+        (r"\b1\d{9}(\d{3})?\b", "<timestamp>"),
+    ]
+    .into_iter()
+    .map(|(pattern, placeholder)| (Regex::new(pattern).unwrap(), placeholder))
+    .collect()
+});
+
+static BASELINE: OnceCell<Baseline> = OnceCell::new();
+
+struct Baseline {
+    /// recordings by method and path, in the order they were made
+    recordings: Mutex<HashMap<String, VecDeque<Recorded>>>,
+    compared: AtomicUsize,
+    differed: AtomicUsize,
+}
+
+/// what is compared of a response
+#[derive(Debug, Clone, PartialEq)]
+struct Recorded {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// loads the HAR file responses are compared with
+pub fn init(path: &Path) -> Result<()> {
+    let har = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read {} given to --diff: {}", path.display(), e))?;
+    let har: Har = serde_json::from_str(&har).map_err(|e| {
+        anyhow!(
+            "{} given to --diff is not a HAR file: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    let mut recordings: HashMap<String, VecDeque<Recorded>> = HashMap::new();
+    for entry in har.log.entries {
+        let url = url::Url::parse(&entry.request.url)?;
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let headers = entry
+            .response
+            .headers
+            .iter()
+            .map(|header| (header.name.as_str(), header.value.as_str()));
+        let content = &entry.response.content;
+        let body = match content.encoding.as_deref() {
+            Some("base64") => content.text.clone(),
+            _ => normalize(&content.text),
+        };
+        let recorded = Recorded {
+            status: entry.response.status,
+            headers: compared_headers(headers),
+            body,
+        };
+        recordings
+            .entry(key(&entry.request.method, &path))
+            .or_default()
+            .push_back(recorded);
+    }
+
+    let baseline = Baseline {
+        recordings: Mutex::new(recordings),
+        compared: AtomicUsize::new(0),
+        differed: AtomicUsize::new(0),
+    };
+    if BASELINE.set(baseline).is_err() {
+        log::debug!("The --diff baseline was already loaded");
+    }
+    Ok(())
+}
+
+/// compares `resp` with the baseline once its body has finished streaming to the client
+pub(super) fn compare(method: &str, path: &str, resp: Response<Body>) -> Response<Body> {
+    let baseline = match BASELINE.get() {
+        Some(baseline) => baseline,
+        None => return resp,
+    };
+
+    let description = format!("{} {}", method, path);
+    let expected = {
+        let mut recordings = baseline.recordings.lock().unwrap();
+        recordings.get_mut(&key(method, path)).and_then(|queue| {
+            // the last recording stays to compare any further requests with
+            if queue.len() > 1 {
+                queue.pop_front()
+            } else {
+                queue.front().cloned()
+            }
+        })
+    };
+
+    let status = resp.status();
+    let headers = compared_headers(
+        resp.headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?))),
+    );
+    let (parts, mut body) = resp.into_parts();
+    let mut capture = Capture::new(&parts.headers);
+    let (mut sender, tee) = Body::channel();
+    tokio::spawn(async move {
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(chunk) => {
+                    capture.write(&chunk);
+                    if sender.send_data(chunk).await.is_err() {
+                        // the client went away, so there's nothing complete to compare
+                        return;
+                    }
+                }
+                Err(e) => {
+                    log::debug!("Failed to read response body: {}", e);
+                    sender.abort();
+                    return;
+                }
+            }
+        }
+
+        // the same form `--har` records bodies in
+        let captured = capture.finish().body;
+        let body = match std::str::from_utf8(&captured) {
+            Ok(text) => normalize(text),
+            Err(_) => base64::encode(&captured),
+        };
+        let actual = Recorded {
+            status: status.as_u16(),
+            headers,
+            body,
+        };
+        record(baseline, &description, expected.as_ref(), &actual);
+    });
+
+    Response::from_parts(parts, tee)
+}
+
+fn record(baseline: &Baseline, description: &str, expected: Option<&Recorded>, actual: &Recorded) {
+    baseline.compared.fetch_add(1, Ordering::SeqCst);
+    let differences = match expected {
+        Some(expected) => differences(expected, actual),
+        None => vec!["it isn't in the baseline".to_string()],
+    };
+    if !differences.is_empty() {
+        baseline.differed.fetch_add(1, Ordering::SeqCst);
+        StdErr::warn(&format!(
+            "{} differs from the baseline: {}",
+            description,
+            differences.join("; ")
+        ));
+    }
+}
+
+/// prints how many responses matched the baseline, failing with `--diff-fail` if any didn't
+pub(super) fn finish(fail: bool) -> Result<()> {
+    let baseline = match BASELINE.get() {
+        Some(baseline) => baseline,
+        None => return Ok(()),
+    };
+    let compared = baseline.compared.load(Ordering::SeqCst);
+    let differed = baseline.differed.load(Ordering::SeqCst);
+
+    if differed == 0 {
+        StdErr::success(&format!("{} responses matched the baseline", compared));
+        return Ok(());
+    }
+    let summary = format!(
+        "{} of {} responses differed from the baseline",
+        differed, compared
+    );
+    if fail {
+        anyhow::bail!(summary)
+    }
+    StdErr::warn(&summary);
+    Ok(())
+}
+
+fn differences(expected: &Recorded, actual: &Recorded) -> Vec<String> {
+    let mut differences = Vec::new();
+    if expected.status != actual.status {
+        differences.push(format!(
+            "status {} was {}",
+            status(actual.status),
+            status(expected.status)
+        ));
+    }
+    for name in COMPARED_HEADERS {
+        let value = |recorded: &Recorded| {
+            recorded
+                .headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.clone())
+        };
+        let (expected, actual) = (value(expected), value(actual));
+        if expected != actual {
+            differences.push(format!(
+                "{} {} was {}",
+                name,
+                actual.as_deref().unwrap_or("(none)"),
+                expected.as_deref().unwrap_or("(none)")
+            ));
+        }
+    }
+    if expected.body != actual.body {
+        let line = expected
+            .body
+            .lines()
+            .zip(actual.body.lines())
+            .position(|(expected, actual)| expected != actual)
+            .unwrap_or_else(|| {
+                expected
+                    .body
+                    .lines()
+                    .count()
+                    .min(actual.body.lines().count())
+            });
+        differences.push(format!("body differs from line {}", line + 1));
+    }
+    differences
+}
+
+fn status(status: u16) -> String {
+    StatusCode::from_u16(status)
+        .map(|status| status.to_string())
+        .unwrap_or_else(|_| status.to_string())
+}
+
+fn compared_headers<'a>(
+    headers: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Vec<(String, String)> {
+    let mut compared: Vec<(String, String)> = headers
+        .map(|(name, value)| (name.to_lowercase(), value))
+        .filter(|(name, _)| COMPARED_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| (name, normalize(value)))
+        .collect();
+    compared.sort();
+    compared
+}
+
+/// replaces the values in `text` that change from one run to the next with placeholders
+fn normalize(text: &str) -> String {
+    VOLATILE
+        .iter()
+        .fold(text.to_string(), |text, (volatile, placeholder)| {
+            volatile.replace_all(&text, *placeholder).into_owned()
+        })
+}
+
+fn key(method: &str, path: &str) -> String {
+    format!("{} {}", method.to_uppercase(), path)
+}
+
+/// the parts of a HAR file that are compared
+#[derive(Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Deserialize)]
+struct HarRequest {
+    method: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct HarResponse {
+    status: u16,
+    headers: Vec<HarHeader>,
+    content: HarContent,
+}
+
+#[derive(Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct HarContent {
+    #[serde(default)]
+    text: String,
+    encoding: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(status: u16, content_type: &str, body: &str) -> Recorded {
+        Recorded {
+            status,
+            headers: compared_headers(vec![("Content-Type", content_type)].into_iter()),
+            body: normalize(body),
+        }
+    }
+
+    #[test]
+    fn volatile_values_are_normalized() {
+        assert_eq!(
+            normalize(
+                r#"{"at":"2021-06-01T12:00:00.123Z","id":"8c2a4b5e-1f3d-4c6b-9a7e-0d1e2f3a4b5c"}"#
+            ),
+            r#"{"at":"<date>","id":"<id>"}"#
+        );
+        assert_eq!(
+            normalize("Tue, 01 Jun 2021 12:00:00 GMT, ray 65a1b2c3d4e5f607-SJC, at 1622548800000"),
+            "<date>, ray <id>-SJC, at <timestamp>"
+        );
+    }
+
+    #[test]
+    fn differences_are_described() {
+        let expected = recorded(
+            200,
+            "text/html",
+            "<h1>hello</h1>\n<p>2021-06-01 12:00:00</p>",
+        );
+        let same = recorded(
+            200,
+            "text/html",
+            "<h1>hello</h1>\n<p>2021-07-01 09:30:00</p>",
+        );
+        assert!(differences(&expected, &same).is_empty());
+
+        let changed = recorded(404, "text/plain", "<h1>hello</h1>\n<p>gone</p>");
+        assert_eq!(
+            differences(&expected, &changed),
+            vec![
+                "status 404 Not Found was 200 OK",
+                "content-type text/plain was text/html",
+                "body differs from line 2",
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Previews are replaced without dropping the requests still using them.
+//!
+//! Each request keeps the preview it was routed to, so a rebuild only changes
+//! where new requests go. The requests already sent to the old preview are
+//! counted here, and the watcher waits for that count to reach zero before
+//! retiring the old preview. Until then nothing is done that could break it,
+//! like deleting stale Workers Sites assets or uploading yet another change.
+//!
+//! A request that never finishes would hold the watcher up forever, so it
+//! stops waiting after `DRAIN_WINDOW`
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+/// the longest an old preview is kept for the requests still using it
+pub(super) const DRAIN_WINDOW: Duration = Duration::from_secs(10);
+
+/// requests in flight, by the preview they were routed to
+static IN_FLIGHT: Lazy<(Mutex<HashMap<String, usize>>, Condvar)> =
+    Lazy::new(|| (Mutex::new(HashMap::new()), Condvar::new()));
+
+/// counts a request as in flight to a preview until it is dropped
+pub(super) struct InFlight {
+    preview: String,
+}
+
+impl InFlight {
+    pub(super) fn start(preview: &str) -> InFlight {
+        let (counts, _) = &*IN_FLIGHT;
+        *counts
+            .lock()
+            .unwrap()
+            .entry(preview.to_string())
+            .or_default() += 1;
+        InFlight {
+            preview: preview.to_string(),
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let (counts, drained) = &*IN_FLIGHT;
+        let mut counts = counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.preview) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.preview);
+                drained.notify_all();
+            }
+        }
+    }
+}
+
+/// blocks until no request is in flight to `preview`, or until `window` has passed.
+/// Returns how many requests were still in flight, which is 0 unless it gave up
+pub(super) fn retire(preview: &str, window: Duration) -> usize {
+    let (counts, drained) = &*IN_FLIGHT;
+    let deadline = Instant::now() + window;
+    let mut counts = counts.lock().unwrap();
+    loop {
+        let remaining = counts.get(preview).copied().unwrap_or(0);
+        let now = Instant::now();
+        if remaining == 0 || now >= deadline {
+            if remaining > 0 {
+                log::warn!(
+                    "Retiring the previous preview with {} requests still using it",
+                    remaining
+                );
+            }
+            return remaining;
+        }
+        counts = drained.wait_timeout(counts, deadline - now).unwrap().0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn old_previews_are_retired_once_their_requests_finish() {
+        let request = InFlight::start("drained");
+        let finish = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            drop(request);
+        });
+
+        assert_eq!(retire("drained", Duration::from_secs(5)), 0);
+        finish.join().unwrap();
+        assert_eq!(retire("never used", Duration::from_secs(5)), 0);
+    }
+
+    #[test]
+    fn retiring_gives_up_after_the_window() {
+        let _request = InFlight::start("stuck");
+        let _other = InFlight::start("stuck");
+
+        assert_eq!(retire("stuck", Duration::from_millis(20)), 2);
+    }
+}
@@ -0,0 +1,153 @@
+//! `--echo` is a diagnostic mode, not a way to run a Worker: every request is
+//! answered by wrangler dev itself with a JSON description of the request.
+//! Nothing is built or uploaded and no network access is needed, which makes
+//! it a quick check that the local listener (and its TLS) works
+use crate::commands::dev::events::{self, Event};
+use crate::commands::dev::server_config::listener;
+use crate::commands::dev::{once, serve, shutdown, tls, Protocol, ServerConfig};
+use crate::terminal::emoji;
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime as TokioRuntime;
+
+/// serve requests by echoing them back, until the user hits Ctrl-C or the process gets SIGTERM
+pub fn dev(server_config: ServerConfig, local_protocol: Protocol) -> Result<()> {
+    server_config
+        .options
+        .banner("echo: requests are answered by wrangler dev, not your Worker");
+
+    let serve_once = server_config.options.once;
+    let https_config = server_config.dual_listen_https()?;
+    let runtime = TokioRuntime::new()?;
+    runtime.block_on(async {
+        let served = async {
+            match https_config {
+                // with --dual-listen, over https as well as http
+                Some(https_config) => tokio::try_join!(
+                    listen(server_config, local_protocol),
+                    listen(https_config, Protocol::Https)
+                )
+                .map(|_| ()),
+                None => listen(server_config, local_protocol).await,
+            }
+        };
+        tokio::select! {
+            res = served => res,
+            _ = shutdown::signal() => Ok(()),
+            _ = once::served(), if serve_once => Ok(()),
+        }
+    })
+}
+
+async fn listen(server_config: ServerConfig, local_protocol: Protocol) -> Result<()> {
+    let listening_address = server_config.listening_address;
+    let options = server_config.options.clone();
+    let max_buf_size = serve::max_buf_size(&server_config);
+    let https = local_protocol.is_https();
+
+    let make_service = make_service_fn(move |_| {
+        let server_config = server_config.to_owned();
+        async move {
+            Ok::<_, anyhow::Error>(service_fn(move |req| {
+                let server_config = server_config.to_owned();
+                async move {
+                    let host = server_config.listening_address.to_string();
+                    serve::handle(req, &server_config, &host, https, echo).await
+                }
+            }))
+        }
+    });
+
+    let scheme = if https { "https" } else { "http" };
+    let url = format!("{}://{}", scheme, listening_address);
+    let ready = || {
+        options.banner(&format!("{} Listening on {}", emoji::EAR, url));
+        events::emit(Event::ServerReady { url: url.clone() });
+    };
+
+    let served = if https {
+        tls::generate_cert(options.cert_validity_days)?;
+        let tcp = TcpListener::from_std(listener::bind(&listening_address)?)?;
+        let server = Server::builder(tls::HyperAcceptor {
+            acceptor: tls::incoming(tcp, tls::get_tls_acceptor(&options)?, options.traces_tls()),
+        })
+        .http1_max_buf_size(max_buf_size)
+        .serve(make_service);
+        ready();
+        server.await
+    } else {
+        let server = Server::from_tcp(listener::bind(&listening_address)?)?
+            .http1_max_buf_size(max_buf_size)
+            .serve(make_service);
+        ready();
+        server.await
+    };
+    served.map_err(|e| anyhow!("wrangler dev stopped serving requests: {}", e))
+}
+
+/// responds with the method, path, version, headers and body of the request
+async fn echo(req: Request<Body>) -> Result<Response<Body>> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+
+    // bodies that aren't text are echoed in base64, so nothing is lost
+    let body = match std::str::from_utf8(&body) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "base64": base64::encode(&body) }),
+    };
+
+    let echoed = json!({
+        "method": parts.method.as_str(),
+        "path": parts.uri.to_string(),
+        "version": format!("{:?}", parts.version),
+        "headers": headers(&parts.headers),
+        "body": body,
+    });
+
+    let mut resp = Response::new(Body::from(serde_json::to_string_pretty(&echoed)?));
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(resp)
+}
+
+/// every value of every header, by name, in the order they were sent
+pub(super) fn headers(headers: &HeaderMap) -> BTreeMap<&str, Vec<String>> {
+    let mut by_name: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (name, value) in headers {
+        by_name
+            .entry(name.as_str())
+            .or_default()
+            .push(String::from_utf8_lossy(value.as_bytes()).into_owned());
+    }
+    by_name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_are_echoed_as_json() {
+        let req = Request::post("/submit?draft=true")
+            .header("x-one", "a")
+            .header("x-one", "b")
+            .body(Body::from("hello"))
+            .unwrap();
+
+        let resp = echo(req).await.unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let echoed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(echoed["method"], "POST");
+        assert_eq!(echoed["path"], "/submit?draft=true");
+        assert_eq!(echoed["headers"]["x-one"], json!(["a", "b"]));
+        assert_eq!(echoed["body"], json!({ "text": "hello" }));
+    }
+}
@@ -0,0 +1,137 @@
+//! With `--echo-request`, a request for `<prefix>/echo-request/<path>` goes
+//! through everything a request for `<path>` would, from the Host rewrite and
+//! header prefixing to `--cf` and `--trace-context`, but instead of being sent
+//! to the preview service it's described in the response as JSON: the method,
+//! URL, HTTP version and headers the Worker would have been sent.
+//!
+//! ```text
+//! curl localhost:8787/__wrangler/echo-request/api/users?page=2
+//! ```
+//!
+//! The body isn't read, so it's left out. A request dev would answer itself,
+//! like one for a `--local-static` file, is answered as it would be.
+use crate::commands::dev::echo;
+use crate::commands::dev::DevOptions;
+
+use anyhow::Result;
+use futures_util::future::{self, BoxFuture, FutureExt, TryFutureExt};
+use hyper::client::connect::Connect;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Client as HyperClient, Request, Response, Uri};
+use serde_json::json;
+
+const ENDPOINT: &str = "echo-request";
+
+/// marks a request to be described rather than sent, and the response describing it
+#[derive(Clone, Copy, Debug)]
+struct Echo;
+
+/// if `endpoint` asks for a request to be echoed, points `req` at the path it names
+/// and marks it to be described instead of sent
+pub(super) fn start(endpoint: &str, req: &mut Request<Body>, options: &DevOptions) -> bool {
+    if !options.echo_request || point_at(endpoint, req).is_none() {
+        return false;
+    }
+    req.extensions_mut().insert(Echo);
+    true
+}
+
+/// points `req` at the path `endpoint` names, if it is one to echo
+fn point_at(endpoint: &str, req: &mut Request<Body>) -> Option<()> {
+    let path = match endpoint.strip_prefix(ENDPOINT)? {
+        "" => "",
+        rest => rest.strip_prefix('/')?,
+    };
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("/{}?{}", path, query),
+        None => format!("/{}", path),
+    };
+
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    *req.uri_mut() = Uri::from_parts(parts).ok()?;
+    Some(())
+}
+
+/// whether `resp` describes an echoed request, rather than coming from upstream
+pub(super) fn is_echo(resp: &Response<Body>) -> bool {
+    resp.extensions().get::<Echo>().is_some()
+}
+
+/// sends `req` to the preview service, unless it's to be echoed,
+/// in which case it's answered with a description of what would have been sent
+pub(super) fn send<C>(
+    client: &HyperClient<C>,
+    req: Request<Body>,
+) -> BoxFuture<'static, Result<Response<Body>>>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    if req.extensions().get::<Echo>().is_none() {
+        return client.request(req).err_into().boxed();
+    }
+    future::ready(describe(&req)).boxed()
+}
+
+fn describe(req: &Request<Body>) -> Result<Response<Body>> {
+    let described = json!({
+        "method": req.method().as_str(),
+        "url": req.uri().to_string(),
+        "version": format!("{:?}", req.version()),
+        "headers": echo::headers(req.headers()),
+    });
+    let mut resp = Response::new(Body::from(serde_json::to_string_pretty(&described)?));
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp.extensions_mut().insert(Echo);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> DevOptions {
+        DevOptions {
+            echo_request: true,
+            ..DevOptions::default()
+        }
+    }
+
+    #[test]
+    fn echoed_requests_are_pointed_at_the_path_they_name() {
+        let mut req = Request::get("/__wrangler/echo-request/api/users?page=2")
+            .body(Body::empty())
+            .unwrap();
+        assert!(start("echo-request/api/users", &mut req, &options()));
+        assert_eq!(req.uri(), "/api/users?page=2");
+
+        let mut req = Request::get("/__wrangler/echo-request")
+            .body(Body::empty())
+            .unwrap();
+        assert!(start("echo-request", &mut req, &options()));
+        assert_eq!(req.uri(), "/");
+
+        assert!(!start("echo-requests", &mut req, &options()));
+        assert!(!start("echo-request/", &mut req, &DevOptions::default()));
+    }
+
+    #[tokio::test]
+    async fn echoed_requests_are_described_instead_of_sent() {
+        let mut req = Request::post("/__wrangler/echo-request/submit")
+            .header("cf-ew-raw-x-one", "a")
+            .header("cf-ew-raw-x-one", "b")
+            .body(Body::from("hello"))
+            .unwrap();
+        assert!(start("echo-request/submit", &mut req, &options()));
+
+        // the client has nowhere to send it, so only a request that isn't sent succeeds
+        let resp = send(&HyperClient::new(), req).await.unwrap();
+        assert!(is_echo(&resp));
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let echoed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(echoed["method"], "POST");
+        assert_eq!(echoed["url"], "/submit");
+        assert_eq!(echoed["headers"]["cf-ew-raw-x-one"], json!(["a", "b"]));
+    }
+}
@@ -2,13 +2,17 @@ mod server;
 mod setup;
 mod watch;
 
-use setup::{upload, Session};
+use setup::{upload, upload_version, Session};
 use watch::watch_for_changes;
 
-use crate::commands::dev::{socket, Protocol, ServerConfig};
+use crate::commands::dev::{
+    once, preview_ids, rate_limit, shutdown, socket, Protocol, ServerConfig,
+};
 use crate::deploy::DeployTarget;
 use crate::settings::global_user::GlobalUser;
 use crate::settings::toml::Target;
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
 use anyhow::Result;
 
 use tokio::runtime::Runtime as TokioRuntime;
@@ -28,50 +32,115 @@ pub fn dev(
     let session = Session::new(&target, &user, &deploy_target)?;
     let mut target = target;
 
-    let preview_token = upload(
-        &mut target,
-        &deploy_target,
-        &user,
-        session.preview_token.clone(),
-        verbose,
-    )?;
-
-    let preview_token = Arc::new(Mutex::new(preview_token));
+    let preview_token = if let Some(version) = &server_config.options.preview_version {
+        let preview_token = upload_version(
+            &mut target,
+            &deploy_target,
+            &user,
+            session.preview_token.clone(),
+            version,
+        )?;
+        StdErr::info(&format!(
+            "Serving deployed version {} of {}, not your local code",
+            styles::highlight(version),
+            styles::highlight(&target.name)
+        ));
+        preview_ids::started(&preview_token, &format!("deployed version {}", version));
+        Arc::new(Mutex::new(preview_token))
+    } else if let Some(preview_token) = &server_config.options.preview_token {
+        // given by the user, so there's nothing to upload or watch
+        preview_ids::started(preview_token, "given with --preview-token");
+        Arc::new(Mutex::new(preview_token.clone()))
+    } else {
+        let (preview_token, stale) = rate_limit::retry(&server_config.options, || {
+            upload(
+                &mut target,
+                &deploy_target,
+                &user,
+                session.preview_token.clone(),
+                verbose,
+            )
+        })?;
+        // no request has been sent to an earlier preview yet
+        stale.delete(&target, &user, verbose)?;
+        preview_ids::started(&preview_token, "from your local code");
+        let preview_token = Arc::new(Mutex::new(preview_token));
 
-    {
-        let preview_token = preview_token.clone();
         let session_token = session.preview_token.clone();
-
+        let watched_token = Arc::clone(&preview_token);
+        let watched_config = server_config.clone();
         thread::spawn(move || {
             watch_for_changes(
                 target,
                 &deploy_target,
                 &user,
-                Arc::clone(&preview_token),
+                watched_token,
                 session_token,
+                &watched_config,
                 verbose,
             )
         });
-    }
 
+        preview_token
+    };
+
+    // the preview session names the host to send requests to, unless one was configured
+    let preview_host = match &server_config.options.preview_host {
+        Some(preview_host) => preview_host.clone(),
+        None => session.host.clone(),
+    };
+    server_config
+        .options
+        .banner(&format!("Previewing on {}", preview_host));
+
+    let serve_once = server_config.options.once;
+    let https_config = server_config.dual_listen_https()?;
     let runtime = TokioRuntime::new()?;
     runtime.block_on(async {
-        let devtools_listener = tokio::spawn(socket::listen(session.websocket_url));
+        let devtools_listener = tokio::spawn(socket::listen_if_uploaded(
+            session.websocket_url,
+            server_config.options.preview_token.is_none(),
+        ));
+        // with --dual-listen, serve over https as well
+        let https_server = https_config.map(|https_config| {
+            tokio::spawn(server::https(
+                https_config,
+                Arc::clone(&preview_token),
+                preview_host.clone(),
+            ))
+        });
+
         let server = match local_protocol {
             Protocol::Https => tokio::spawn(server::https(
                 server_config.clone(),
                 Arc::clone(&preview_token),
-                session.host.clone(),
+                preview_host.clone(),
             )),
             Protocol::Http => tokio::spawn(server::http(
                 server_config,
                 Arc::clone(&preview_token),
-                session.host,
+                preview_host,
                 upstream_protocol,
             )),
         };
 
-        let res = tokio::try_join!(async { devtools_listener.await? }, async { server.await? });
+        let res = tokio::select! {
+            res = async {
+                tokio::try_join!(
+                    async { devtools_listener.await? },
+                    async { server.await? },
+                    async {
+                        match https_server {
+                            Some(https_server) => https_server.await?,
+                            None => Ok(()),
+                        }
+                    }
+                )
+            } => res.map(|_| ()),
+            // stop serving cleanly on Ctrl-C or SIGTERM
+            _ = shutdown::signal() => Ok(()),
+            _ = once::served(), if serve_once => Ok(()),
+        };
         match res {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
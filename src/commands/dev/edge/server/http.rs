@@ -1,15 +1,19 @@
 use super::preview_request;
-use crate::commands::dev::utils::{get_path_as_str, rewrite_redirect};
+use crate::commands::dev::access_log::ClientAddr;
+use crate::commands::dev::drain;
+use crate::commands::dev::events::{self, Event};
+use crate::commands::dev::serve;
+use crate::commands::dev::server_config::listener;
+use crate::commands::dev::upstream;
 use crate::commands::dev::{Protocol, ServerConfig};
 use crate::terminal::emoji;
 
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
-use chrono::prelude::*;
+use anyhow::{anyhow, Result};
+use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Client as HyperClient, Request, Server};
-use hyper_rustls::HttpsConnector;
+use hyper::{Body, Client as HyperClient, Server};
 
 pub async fn http(
     server_config: ServerConfig,
@@ -18,66 +22,74 @@ pub async fn http(
     upstream_protocol: Protocol,
 ) -> Result<()> {
     // set up https client to connect to the preview service
-    let https = HttpsConnector::with_native_roots();
+    let https = upstream::connector(&server_config.options)?;
     let client = HyperClient::builder().build::<_, Body>(https);
 
     let listening_address = server_config.listening_address;
+    let options = Arc::clone(&server_config.options);
+    let max_buf_size = serve::max_buf_size(&server_config);
 
     // create a closure that hyper will use later to handle HTTP requests
-    let make_service = make_service_fn(move |_| {
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let client_addr = ClientAddr::of_tcp(conn);
         let client = client.to_owned();
         let preview_token = preview_token.to_owned();
         let host = host.to_owned();
         let server_config = server_config.to_owned();
 
         async move {
-            Ok::<_, anyhow::Error>(service_fn(move |req| {
+            Ok::<_, anyhow::Error>(service_fn(move |mut req| {
+                ClientAddr::mark(&mut req, client_addr);
                 let client = client.to_owned();
                 let preview_token = preview_token.lock().unwrap().to_owned();
+                // counted until answered, so the watcher doesn't retire it before then
+                let in_flight = drain::InFlight::start(&preview_token);
                 let host = host.to_owned();
-                let version = req.version();
-                let (parts, body) = req.into_parts();
-                let local_host = format!(
-                    "{}:{}",
-                    server_config.listening_address.ip().to_string(),
-                    server_config.listening_address.port().to_string()
-                );
-                let req_method = parts.method.to_string();
-                let now: DateTime<Local> = Local::now();
-                let path = get_path_as_str(&parts.uri);
+                let server_config = server_config.to_owned();
                 async move {
-                    let mut resp = preview_request(
-                        Request::from_parts(parts, body),
-                        client,
-                        preview_token.to_owned(),
-                        host.clone(),
-                        upstream_protocol,
-                    )
-                    .await?;
-
-                    rewrite_redirect(&mut resp, &host, &local_host, false);
-
-                    println!(
-                        "[{}] {} {}{} {:?} {}",
-                        now.format("%Y-%m-%d %H:%M:%S"),
-                        req_method,
-                        host,
-                        path,
-                        version,
-                        resp.status()
-                    );
-                    Ok::<_, anyhow::Error>(resp)
+                    let _in_flight = in_flight;
+                    let upstream = {
+                        let host = host.to_owned();
+                        move |req| {
+                            let client = client.to_owned();
+                            let preview_token = preview_token.to_owned();
+                            let host = host.to_owned();
+                            async move {
+                                let resp = preview_request(
+                                    req,
+                                    client,
+                                    preview_token,
+                                    host,
+                                    upstream_protocol,
+                                )
+                                .await?;
+                                Ok::<_, anyhow::Error>(resp)
+                            }
+                        }
+                    };
+                    serve::handle(req, &server_config, &host, false, upstream).await
                 }
             }))
         }
     });
 
-    let server = Server::bind(&listening_address).serve(make_service);
-    println!("{} Listening on http://{}", emoji::EAR, listening_address);
-
-    if let Err(e) = server.await {
-        eprintln!("{}", e);
-    }
+    let server = Server::from_tcp(listener::bind(&listening_address)?)?
+        .http1_max_buf_size(max_buf_size)
+        .serve(make_service);
+    options.banner(&format!(
+        "{} Listening on http://{}",
+        emoji::EAR,
+        listening_address
+    ));
+    events::emit(Event::ServerReady {
+        url: format!("http://{}", listening_address),
+    });
 
-    Ok(())
+    // the session ends with this error, rather than carrying on without a server
+    server.await.map_err(|e| {
+        events::emit(Event::Error {
+            message: e.to_string(),
+        });
+        anyhow!("wrangler dev stopped serving requests: {}", e)
+    })
 }
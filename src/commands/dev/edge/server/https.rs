@@ -1,118 +1,103 @@
 use super::preview_request;
-use crate::commands::dev::utils::{get_path_as_str, rewrite_redirect};
+use crate::commands::dev::access_log::ClientAddr;
+use crate::commands::dev::drain;
+use crate::commands::dev::events::{self, Event};
+use crate::commands::dev::serve;
+use crate::commands::dev::server_config::listener;
+use crate::commands::dev::upstream;
 use crate::commands::dev::{tls, Protocol, ServerConfig};
 use crate::terminal::emoji;
-use crate::terminal::message::{Message, StdOut};
+use crate::terminal::message::{Message, StdErr};
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
-use chrono::prelude::*;
-use futures_util::{stream::StreamExt, FutureExt};
+use anyhow::{anyhow, Result};
 
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Client as HyperClient, Request, Server};
-use hyper_rustls::HttpsConnector;
-use tokio::net::TcpListener;
+use hyper::{Body, Client as HyperClient, Server};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::server::TlsStream;
 
 pub async fn https(
     server_config: ServerConfig,
     preview_token: Arc<Mutex<String>>,
     host: String,
 ) -> Result<()> {
-    tls::generate_cert()?;
+    tls::generate_cert(server_config.options.cert_validity_days)?;
 
     // set up https client to connect to the preview service
-    let https = HttpsConnector::with_native_roots();
+    let https = upstream::connector(&server_config.options)?;
     let client = HyperClient::builder().build::<_, Body>(https);
 
     let listening_address = server_config.listening_address;
+    let options = Arc::clone(&server_config.options);
+    let max_buf_size = serve::max_buf_size(&server_config);
 
     // create a closure that hyper will use later to handle HTTP requests
-    let service = make_service_fn(move |_| {
+    let service = make_service_fn(move |conn: &TlsStream<TcpStream>| {
+        let client_addr = ClientAddr::of_tls(conn);
         let client = client.to_owned();
         let preview_token = preview_token.to_owned();
         let host = host.to_owned();
         let server_config = server_config.to_owned();
 
         async move {
-            Ok::<_, anyhow::Error>(service_fn(move |req| {
+            Ok::<_, anyhow::Error>(service_fn(move |mut req| {
+                ClientAddr::mark(&mut req, client_addr);
                 let client = client.to_owned();
                 let preview_token = preview_token.lock().unwrap().to_owned();
+                // counted until answered, so the watcher doesn't retire it before then
+                let in_flight = drain::InFlight::start(&preview_token);
                 let host = host.to_owned();
-                let version = req.version();
-                let (parts, body) = req.into_parts();
-                let local_host = format!(
-                    "{}:{}",
-                    server_config.listening_address.ip().to_string(),
-                    server_config.listening_address.port().to_string()
-                );
-                let req_method = parts.method.to_string();
-                let now: DateTime<Local> = Local::now();
-                let path = get_path_as_str(&parts.uri);
+                let server_config = server_config.to_owned();
                 async move {
-                    let mut resp = preview_request(
-                        Request::from_parts(parts, body),
-                        client,
-                        preview_token.to_owned(),
-                        host.clone(),
-                        Protocol::Https,
-                    )
-                    .await?;
-
-                    rewrite_redirect(&mut resp, &host, &local_host, true);
-
-                    println!(
-                        "[{}] {} {}{} {:?} {}",
-                        now.format("%Y-%m-%d %H:%M:%S"),
-                        req_method,
-                        host,
-                        path,
-                        version,
-                        resp.status()
-                    );
-                    Ok::<_, anyhow::Error>(resp)
+                    let _in_flight = in_flight;
+                    let upstream = {
+                        let host = host.to_owned();
+                        move |req| {
+                            let client = client.to_owned();
+                            let preview_token = preview_token.to_owned();
+                            let host = host.to_owned();
+                            async move {
+                                let resp = preview_request(
+                                    req,
+                                    client,
+                                    preview_token,
+                                    host,
+                                    Protocol::Https,
+                                )
+                                .await?;
+                                Ok::<_, anyhow::Error>(resp)
+                            }
+                        }
+                    };
+                    serve::handle(req, &server_config, &host, true, upstream).await
                 }
             }))
         }
     });
 
-    let tcp = TcpListener::bind(&listening_address).await?;
-    let tls_acceptor = &tls::get_tls_acceptor()?;
-    let incoming_tls_stream = async {
-        let tcp_stream = match tcp.accept().await {
-            Ok((tcp_stream, _addr)) => Ok(tcp_stream),
-            Err(e) => {
-                eprintln!("Failed to accept client {}", e);
-                Err(e)
-            }
-        };
-
-        match tcp_stream {
-            Ok(stream) => match tls_acceptor.accept(stream).await {
-                Ok(tls_stream) => Ok(tls_stream),
-                Err(e) => {
-                    eprintln!("Client connection error {}", e);
-                    StdOut::info("Make sure to use https and `--insecure` with curl");
-                    Err(e)
-                }
-            },
-            Err(e) => Err(e),
-        }
-    }
-    .into_stream()
-    .boxed();
+    let tcp = TcpListener::from_std(listener::bind(&listening_address)?)?;
+    let incoming_tls_stream =
+        tls::incoming(tcp, tls::get_tls_acceptor(&options)?, options.traces_tls());
 
     let server = Server::builder(tls::HyperAcceptor {
         acceptor: incoming_tls_stream,
     })
+    .http1_max_buf_size(max_buf_size)
     .serve(service);
 
-    println!("{} Listening on https://{}", emoji::EAR, listening_address);
-    StdOut::info("Generated certificate is not verified, browsers will give a warning and curl will require `--insecure`");
-
-    if let Err(e) = server.await {
-        eprintln!("{}", e);
-    }
+    options.banner(&format!(
+        "{} Listening on https://{}",
+        emoji::EAR,
+        listening_address
+    ));
+    events::emit(Event::ServerReady {
+        url: format!("https://{}", listening_address),
+    });
+    StdErr::info("Generated certificate is not verified, browsers will give a warning and curl will require `--insecure`");
 
-    Ok(())
+    // the session ends with this error, rather than carrying on without a server
+    server
+        .await
+        .map_err(|e| anyhow!("wrangler dev stopped serving requests: {}", e))
 }
@@ -4,25 +4,30 @@ mod https;
 pub use self::http::http;
 pub use self::https::https;
 
+use crate::commands::dev::echo_request;
+use crate::commands::dev::loop_guard;
+use crate::commands::dev::self_profile;
+use crate::commands::dev::upstream::Connector;
 use crate::commands::dev::utils::get_path_as_str;
 use crate::commands::dev::Protocol;
 
-use hyper::client::{HttpConnector, ResponseFuture};
+use anyhow::Result;
+use futures_util::future::BoxFuture;
 use hyper::header::{HeaderName, HeaderValue};
-use hyper::{Body, Client as HyperClient, Request};
-use hyper_rustls::HttpsConnector;
+use hyper::{Body, Client as HyperClient, Request, Response};
 
 fn preview_request(
     req: Request<Body>,
-    client: HyperClient<HttpsConnector<HttpConnector>>,
+    client: HyperClient<Connector>,
     preview_token: String,
     host: String,
     protocol: Protocol,
-) -> ResponseFuture {
+) -> BoxFuture<'static, Result<Response<Body>>> {
     let (mut parts, body) = req.into_parts();
 
     let path = get_path_as_str(&parts.uri);
 
+    loop_guard::mark(&mut parts);
     parts.headers.insert(
         HeaderName::from_static("host"),
         HeaderValue::from_str(&host).expect("Could not create host header"),
@@ -42,5 +47,5 @@ fn preview_request(
 
     let req = Request::from_parts(parts, body);
 
-    client.request(req)
+    self_profile::waiting(echo_request::send(&client, req))
 }
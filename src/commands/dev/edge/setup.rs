@@ -1,25 +1,51 @@
 use std::path::Path;
 
 use crate::deploy::DeployTarget;
+use crate::http::RateLimited;
 use crate::kv::bulk;
 use crate::settings::global_user::GlobalUser;
-use crate::settings::toml::Target;
+use crate::settings::toml::{Target, UsageModel};
 use crate::sites::{add_namespace, sync};
-use crate::terminal::message::{Message, StdOut};
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
 use crate::upload;
 
 use anyhow::{anyhow, Result};
-use reqwest::Url;
+use reqwest::blocking::multipart::{Form, Part};
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+/// Workers Sites assets the previous preview used and the new one doesn't,
+/// which are only deleted once nothing uses the previous preview
+pub(super) struct StaleAssets {
+    namespace_id: Option<String>,
+    keys: Vec<String>,
+}
+
+impl StaleAssets {
+    pub(super) fn delete(self, target: &Target, user: &GlobalUser, verbose: bool) -> Result<()> {
+        if let Some(namespace_id) = &self.namespace_id {
+            if !self.keys.is_empty() {
+                if verbose {
+                    StdErr::info("Deleting stale files...");
+                }
+
+                bulk::delete(target, user, namespace_id, self.keys, &None)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// uploads the Worker to the preview service, leaving its stale assets for the caller to delete
 pub(super) fn upload(
     target: &mut Target,
     deploy_target: &DeployTarget,
     user: &GlobalUser,
     session_token: String,
     verbose: bool,
-) -> Result<String> {
+) -> Result<(String, StaleAssets)> {
     let client = crate::http::legacy_auth_client(&user);
 
     let (to_delete, asset_manifest, site_namespace_id) = if let Some(site_config) =
@@ -31,7 +57,7 @@ pub(super) fn upload(
 
         // First, upload all existing files in given directory
         if verbose {
-            StdOut::info("Uploading updated files...");
+            StdErr::info("Uploading updated files...");
         }
 
         bulk::put(target, user, &site_namespace.id, to_upload, &None)?;
@@ -49,24 +75,126 @@ pub(super) fn upload(
         .post(&address)
         .header("cf-preview-upload-config-token", session_token)
         .multipart(script_upload_form)
-        .send()?
-        .error_for_status()?;
-
-    if !to_delete.is_empty() {
-        if verbose {
-            StdOut::info("Deleting stale files...");
-        }
-
-        bulk::delete(target, user, &site_namespace_id.unwrap(), to_delete, &None)?;
+        .send()?;
+    if let Some(rate_limited) = RateLimited::from_response(&response) {
+        return Err(rate_limited.into());
     }
+    let response = response.error_for_status()?;
 
     let text = &response.text()?;
 
     // TODO: use cloudflare-rs for this :)
     let response: PreviewV4ApiResponse = serde_json::from_str(text)?;
+    let stale = StaleAssets {
+        namespace_id: site_namespace_id,
+        keys: to_delete,
+    };
+    Ok((response.result.preview_token, stale))
+}
+
+/// uploads an already deployed version of the Worker to the preview service
+/// along with its bindings and usage model, in place of a local build
+pub(super) fn upload_version(
+    target: &mut Target,
+    deploy_target: &DeployTarget,
+    user: &GlobalUser,
+    session_token: String,
+    version: &str,
+) -> Result<String> {
+    let client = crate::http::legacy_auth_client(&user);
+    let script_address = format!(
+        "https://api.cloudflare.com/client/v4/accounts/{}/workers/scripts/{}",
+        target.account_id.load()?,
+        target.name
+    );
+
+    let response = client
+        .get(&format!("{}/versions/{}", script_address, version))
+        .send()?;
+    if response.status() == StatusCode::NOT_FOUND {
+        anyhow::bail!(
+            "{} has no deployed version {}, check the version id and try again",
+            target.name,
+            version
+        )
+    }
+    let text = &response.error_for_status()?.text()?;
+    let deployed: VersionV4ApiResponse = serde_json::from_str(text)?;
+    let metadata = version_metadata(&deployed.result)?;
+
+    let script = client
+        .get(&format!(
+            "{}/content/v2?version={}",
+            script_address, version
+        ))
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    // the metadata part must come first, see `upload::form::service_worker`
+    let form = Form::new()
+        .part(
+            "metadata",
+            Part::text(metadata.to_string())
+                .file_name("metadata.json")
+                .mime_str("application/json")?,
+        )
+        .part(
+            "script",
+            Part::bytes(script.to_vec())
+                .file_name("script.js")
+                .mime_str("application/javascript")?,
+        )
+        .part(
+            "wrangler-session-config",
+            Part::text(get_session_config(deploy_target).to_string())
+                .file_name("")
+                .mime_str("application/json")?,
+        );
+
+    let response = client
+        .post(&get_upload_address(target)?)
+        .header("cf-preview-upload-config-token", session_token)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+
+    let text = &response.text()?;
+    let response: PreviewV4ApiResponse = serde_json::from_str(text)?;
     Ok(response.result.preview_token)
 }
 
+/// the metadata of the form a deployed version is uploaded with. Only a service
+/// worker is a single script, which is all that is fetched of the version, so a
+/// modules Worker and bindings to parts of their own, Wasm modules and text
+/// blobs, can't be previewed
+fn version_metadata(version: &Version) -> Result<serde_json::Value> {
+    let flag = styles::highlight("--preview-version");
+    if let Some(main_module) = &version.resources.script.main_module {
+        anyhow::bail!(
+            "{} can only preview service workers, but this version is a modules Worker with the main module {}",
+            flag,
+            main_module
+        )
+    }
+    for binding in &version.resources.bindings {
+        let kind = binding["type"].as_str().unwrap_or_default();
+        if kind == "wasm_module" || kind == "text_blob" {
+            anyhow::bail!(
+                "{} can't preview versions with {} bindings, like {}",
+                flag,
+                kind,
+                binding["name"].as_str().unwrap_or_default()
+            )
+        }
+    }
+    Ok(json!({
+        "body_part": "script",
+        "bindings": version.resources.bindings,
+        "usage_model": version.resources.script_runtime.usage_model,
+    }))
+}
+
 #[derive(Debug, Clone)]
 pub struct Session {
     pub host: String,
@@ -189,3 +317,74 @@ struct Preview {
 struct PreviewV4ApiResponse {
     pub result: Preview,
 }
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionScript {
+    /// only set for a modules Worker
+    pub main_module: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionScriptRuntime {
+    pub usage_model: Option<UsageModel>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionResources {
+    #[serde(default)]
+    pub bindings: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub script: VersionScript,
+    #[serde(default)]
+    pub script_runtime: VersionScriptRuntime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Version {
+    pub resources: VersionResources,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionV4ApiResponse {
+    pub result: Version,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(resources: serde_json::Value) -> Version {
+        serde_json::from_value(json!({ "resources": resources })).unwrap()
+    }
+
+    #[test]
+    fn service_workers_keep_their_bindings_and_usage_model() {
+        let metadata = version_metadata(&version(json!({
+            "bindings": [{ "type": "kv_namespace", "name": "CACHE", "namespace_id": "abc" }],
+            "script_runtime": { "usage_model": "unbound" },
+        })))
+        .unwrap();
+        assert_eq!(
+            metadata,
+            json!({
+                "body_part": "script",
+                "bindings": [{ "type": "kv_namespace", "name": "CACHE", "namespace_id": "abc" }],
+                "usage_model": "unbound",
+            })
+        );
+    }
+
+    #[test]
+    fn versions_that_need_more_than_a_script_are_refused() {
+        assert!(version_metadata(&version(json!({
+            "script": { "main_module": "index.mjs" },
+        })))
+        .is_err());
+        for kind in &["wasm_module", "text_blob"] {
+            assert!(version_metadata(&version(json!({
+                "bindings": [{ "type": kind, "name": "BLOB", "part": "BLOB" }],
+            })))
+            .is_err());
+        }
+    }
+}
@@ -1,6 +1,14 @@
+use std::mem;
 use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
 
-use crate::commands::dev::edge::setup;
+use crate::build::build_target;
+use crate::commands::dev::config_reload;
+use crate::commands::dev::drain::{self, DRAIN_WINDOW};
+use crate::commands::dev::edge::setup::{self, StaleAssets};
+use crate::commands::dev::events::{self, Event};
+use crate::commands::dev::rebuild::{self, Rebuild, Rebuilt};
+use crate::commands::dev::server_config::ServerConfig;
 use crate::deploy::DeployTarget;
 use crate::settings::global_user::GlobalUser;
 use crate::settings::toml::Target;
@@ -9,33 +17,86 @@ use crate::watch::watch_and_build;
 use anyhow::Result;
 
 pub fn watch_for_changes(
-    target: Target,
+    mut target: Target,
     deploy_target: &DeployTarget,
     user: &GlobalUser,
     preview_token: Arc<Mutex<String>>,
     session_token: String,
+    server_config: &ServerConfig,
     verbose: bool,
 ) -> Result<()> {
     let (sender, receiver) = mpsc::channel();
-    watch_and_build(&target, Some(sender))?;
+    rebuild::listen(sender.clone());
+    watch_and_build(&target, Some(sender), server_config.watch_scope.clone())?;
 
     while receiver.recv().is_ok() {
+        // with --reload-on-config-change, a changed wrangler.toml forces a rebuild
+        if let Some(reloaded) = config_reload::take(&target) {
+            target = reloaded;
+        }
         let user = user.clone();
         let target = target.clone();
         let deploy_target = deploy_target.clone();
         let session_token = session_token.clone();
         let mut target = target;
+        let started = Instant::now();
 
-        // acquire the lock so incoming requests are halted
-        // until the new script is ready for them
-        let mut preview_token = preview_token.lock().unwrap();
+        // a change has already been built by the watcher, a forced rebuild hasn't
+        if rebuild::is_forced() {
+            if let Err(e) = build_target(&target) {
+                rebuild::finished(Err(e.to_string()));
+                continue;
+            }
+        }
 
-        // while holding the lock, assign a new preview id
-        //
-        // this allows the server to route subsequent requests
-        // to the proper script
-        *preview_token = setup::upload(&mut target, &deploy_target, &user, session_token, verbose)?;
+        let (old_token, new_token, stale) = if server_config.options.rebuild_placeholder {
+            // requests get a placeholder until the new script is ready,
+            // so the lock is only held to swap it in
+            let _rebuild = Rebuild::start();
+            let (new_token, stale) =
+                upload(&mut target, &deploy_target, &user, session_token, verbose)?;
+            let old_token = mem::replace(&mut *preview_token.lock().unwrap(), new_token.clone());
+            (old_token, new_token, stale)
+        } else {
+            // acquire the lock so incoming requests are halted
+            // until the new script is ready for them
+            let mut preview_token = preview_token.lock().unwrap();
+
+            // while holding the lock, assign a new preview id
+            //
+            // this allows the server to route subsequent requests
+            // to the proper script
+            let (new_token, stale) =
+                upload(&mut target, &deploy_target, &user, session_token, verbose)?;
+            let old_token = mem::replace(&mut *preview_token, new_token.clone());
+            (old_token, new_token, stale)
+        };
+
+        rebuild::finished(Ok(Rebuilt {
+            preview: new_token,
+            duration: started.elapsed(),
+        }));
+        events::emit(Event::Rebuild);
+
+        // requests already sent to the old preview finish there, assets and all,
+        // before another change can replace it
+        drain::retire(&old_token, DRAIN_WINDOW);
+        stale.delete(&target, &user, verbose)?;
     }
 
     Ok(())
 }
+
+/// uploads the rebuilt Worker, letting anyone waiting on a forced rebuild know if that failed
+fn upload(
+    target: &mut Target,
+    deploy_target: &DeployTarget,
+    user: &GlobalUser,
+    session_token: String,
+    verbose: bool,
+) -> Result<(String, StaleAssets)> {
+    setup::upload(target, deploy_target, user, session_token, verbose).map_err(|e| {
+        rebuild::finished(Err(e.to_string()));
+        e
+    })
+}
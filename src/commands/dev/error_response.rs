@@ -0,0 +1,106 @@
+//! Every error response wrangler dev makes up itself, rather than passing on
+//! from the Worker, is built here, like the `504` for a request that timed out
+//! or the `413` for a body too large to buffer. They're plain text by default,
+//! or the page shown during a rebuild for the `503` it answers with.
+//!
+//! With `--json-errors` they're JSON instead, for API clients and tooling that
+//! expect it, naming the error after the status and saying what happened:
+//!
+//! ```json
+//! {"error":"gateway_timeout","detail":"The preview service took longer than 5s to respond, the limit set by --read-timeout"}
+//! ```
+//!
+//! Error responses from the Worker are passed on as they are.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+use serde_json::json;
+
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+pub(super) fn init() {
+    JSON_ERRORS.store(true, Ordering::Relaxed);
+}
+
+/// whether error responses are JSON, with --json-errors
+pub(super) fn is_json() -> bool {
+    JSON_ERRORS.load(Ordering::Relaxed)
+}
+
+/// an error response with `status`, saying what happened with `detail`
+pub(super) fn build(status: StatusCode, detail: impl Into<String>) -> Response<Body> {
+    render(status, &detail.into(), is_json())
+}
+
+fn render(status: StatusCode, detail: &str, json: bool) -> Response<Body> {
+    let detail = detail.trim_end();
+    let (body, content_type) = if json {
+        let body = json!({ "error": error_name(status), "detail": detail });
+        (body.to_string(), "application/json")
+    } else {
+        (format!("{}\n", detail), "text/plain; charset=utf-8")
+    };
+
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = status;
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    resp
+}
+
+/// the status's reason in snake case, like `gateway_timeout`
+fn error_name(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => reason
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join("_"),
+        None => status.as_str().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_named_after_their_status() {
+        assert_eq!(error_name(StatusCode::GATEWAY_TIMEOUT), "gateway_timeout");
+        assert_eq!(
+            error_name(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            "request_header_fields_too_large"
+        );
+        assert_eq!(error_name(StatusCode::IM_A_TEAPOT), "i_m_a_teapot");
+        assert_eq!(error_name(StatusCode::from_u16(599).unwrap()), "599");
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_plain_text_or_json() {
+        let resp = render(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "The body is too large\n",
+            false,
+        );
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(resp.headers()[CONTENT_TYPE], "text/plain; charset=utf-8");
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        assert_eq!(body, "The body is too large\n");
+
+        let resp = render(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "The body is too large\n",
+            true,
+        );
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(resp.headers()[CONTENT_TYPE], "application/json");
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            error,
+            json!({ "error": "payload_too_large", "detail": "The body is too large" })
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! `--events-socket` writes a machine-readable stream of the same things
+//! `wrangler dev` prints to the terminal, so that editors can follow a dev
+//! session without scraping stdout.
+//!
+//! Each event is a single line of JSON with an `event` field naming its type
+//! and a `timestamp` in RFC 3339 format. The remaining fields depend on the type:
+//!
+//! | event          | fields                                           |
+//! |----------------|--------------------------------------------------|
+//! | `server_ready` | `url`                                            |
+//! | `request`      | `id`, `method`, `url`                            |
+//! | `response`     | `id`, `status`, `duration_ms`                    |
+//! | `rebuild`      |                                                  |
+//! | `error`        | `message`                                        |
+//! | `shutdown`     |                                                  |
+//!
+//! The `id` of a `response` matches the `id` of the `request` it answers.
+use super::tui;
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+static SINK: OnceCell<Mutex<Box<dyn Write + Send>>> = OnceCell::new();
+static REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ServerReady {
+        url: String,
+    },
+    Request {
+        id: u64,
+        method: String,
+        url: String,
+    },
+    Response {
+        id: u64,
+        status: u16,
+        duration_ms: u64,
+    },
+    Rebuild,
+    Error {
+        message: String,
+    },
+    Shutdown,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// open the events socket, after which every call to `emit` writes to it
+pub fn init(path: &Path) -> Result<()> {
+    let sink = open(path)
+        .map_err(|e| anyhow!("Could not open events socket {}: {}", path.display(), e))?;
+    SINK.set(Mutex::new(sink))
+        .map_err(|_| anyhow!("The events socket has already been opened"))
+}
+
+/// write an event to the events socket, if there is one
+///
+/// events are best effort, a failure to write one is logged
+/// and never interrupts the dev session
+pub fn emit(event: Event) {
+    tui::observe(&event);
+    if let Some(sink) = SINK.get() {
+        let envelope = Envelope {
+            timestamp: Local::now().to_rfc3339(),
+            event: &event,
+        };
+        match serde_json::to_string(&envelope) {
+            Ok(line) => {
+                let mut sink = sink.lock().unwrap();
+                if let Err(e) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+                    log::debug!("Failed to write to events socket: {}", e);
+                }
+            }
+            Err(e) => log::debug!("Failed to serialize event {:?}: {}", event, e),
+        }
+    }
+}
+
+/// returns a new id to correlate a `request` event with its `response`
+pub fn next_request_id() -> u64 {
+    REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let is_socket = path
+        .metadata()
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+
+    if is_socket {
+        Ok(Box::new(UnixStream::connect(path)?))
+    } else {
+        Ok(Box::new(open_file(path)?))
+    }
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(open_file(path)?))
+}
+
+fn open_file(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_tagged_with_their_type() {
+        let event = Event::Response {
+            id: 4,
+            status: 200,
+            duration_ms: 12,
+        };
+        let envelope = Envelope {
+            timestamp: "2020-04-20T15:25:54+00:00".to_string(),
+            event: &event,
+        };
+        let json = serde_json::to_value(&envelope).unwrap();
+
+        assert_eq!(json["event"], "response");
+        assert_eq!(json["timestamp"], "2020-04-20T15:25:54+00:00");
+        assert_eq!(json["id"], 4);
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["duration_ms"], 12);
+    }
+
+    #[test]
+    fn unit_events_only_have_a_type() {
+        let envelope = Envelope {
+            timestamp: "2020-04-20T15:25:54+00:00".to_string(),
+            event: &Event::Shutdown,
+        };
+        let json = serde_json::to_value(&envelope).unwrap();
+
+        assert_eq!(json["event"], "shutdown");
+        assert_eq!(json.as_object().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,94 @@
+//! `--fail-path <pattern>=<status>` answers requests for matching paths with
+//! `status` without sending them to the preview service, to see how a client
+//! copes with one endpoint being down while the rest of the app works:
+//!
+//! ```text
+//! wrangler dev --fail-path '/api/payments/*=503' --fail-path /health=500
+//! ```
+//!
+//! Patterns are globs matched against the path, without its query string, like
+//! `--route-filter`'s: `*` matches any characters including `/`, `?` matches one
+//! character, and `{a,b}` matches either alternative. When several patterns
+//! match, the first given wins. Patterns are checked before anything else
+//! decides what happens to a request, other than wrangler's own endpoints.
+use crate::commands::dev::error_response;
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobMatcher};
+use hyper::{Body, Response, StatusCode};
+
+#[derive(Debug, Clone)]
+pub struct FailPath {
+    pattern: GlobMatcher,
+    status: StatusCode,
+}
+
+/// a `--fail-path` given as pattern=status
+pub(super) fn parse(fail_path: &str) -> Result<FailPath> {
+    let (pattern, status) = fail_path.rsplit_once('=').ok_or_else(|| {
+        anyhow!(
+            "Expected pattern=status, like /api/*=503, got {}",
+            fail_path
+        )
+    })?;
+    if pattern.is_empty() {
+        anyhow::bail!("Expected a pattern before the = in {}", fail_path)
+    }
+    let status = status
+        .parse::<StatusCode>()
+        .map_err(|_| anyhow!("{} is not a status code", status))?;
+    let pattern = Glob::new(pattern)
+        .map_err(|e| anyhow!("Invalid --fail-path pattern {}: {}", pattern, e))?
+        .compile_matcher();
+    Ok(FailPath { pattern, status })
+}
+
+/// the response to a request for `path`, if it matches a `--fail-path` pattern
+pub(super) fn respond(fail_paths: &[FailPath], path: &str) -> Option<Response<Body>> {
+    let path = path.split('?').next().unwrap_or("");
+    let fail_path = fail_paths
+        .iter()
+        .find(|fail_path| fail_path.pattern.is_match(path))?;
+
+    Some(error_response::build(
+        fail_path.status,
+        format!(
+            "{} for {}, which matches --fail-path {}",
+            fail_path.status,
+            path,
+            fail_path.pattern.glob()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_matching_pattern_wins() {
+        let fail_paths = vec![
+            parse("/api/payments/*=503").unwrap(),
+            parse("/api/*=500").unwrap(),
+        ];
+
+        let status = |path| respond(&fail_paths, path).map(|resp| resp.status());
+        assert_eq!(
+            status("/api/payments/42?retry=1"),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        assert_eq!(
+            status("/api/users"),
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(status("/"), None);
+    }
+
+    #[test]
+    fn bad_fail_paths_are_rejected() {
+        assert!(parse("/api/*").is_err());
+        assert!(parse("=503").is_err());
+        assert!(parse("/api/*=down").is_err());
+        assert!(parse("/api/[=503").is_err());
+    }
+}
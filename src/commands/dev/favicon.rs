@@ -0,0 +1,103 @@
+//! Browsers ask for `/favicon.ico` on every page load, which fills the request
+//! log and costs a trip to the preview service for Workers that don't serve one.
+//! `--favicon none` answers those requests with a `204` that isn't logged, and
+//! `--favicon <path>` serves that file instead. Without it, they reach the Worker
+//! like any other request.
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use super::local_static::{self, MimeOverride};
+
+const FAVICON_PATH: &str = "/favicon.ico";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Favicon {
+    None,
+    File(PathBuf),
+}
+
+/// parses `--favicon`, either `none` or the path of an existing file
+pub fn parse(favicon: &str) -> Result<Favicon> {
+    if favicon == "none" {
+        return Ok(Favicon::None);
+    }
+
+    let path = PathBuf::from(favicon);
+    if !path.is_file() {
+        return Err(anyhow!("{} is not a file, or none", favicon));
+    }
+    Ok(Favicon::File(path))
+}
+
+/// whether the request is a browser asking for the site's icon
+pub(super) fn is_favicon(req: &Request<Body>) -> bool {
+    let method = req.method();
+    (method == Method::GET || method == Method::HEAD) && req.uri().path() == FAVICON_PATH
+}
+
+/// the local answer to a favicon request, reading the file each time so changes to it show up
+pub(super) fn serve(
+    favicon: &Favicon,
+    req: &Request<Body>,
+    mime: &[MimeOverride],
+) -> Result<Response<Body>> {
+    let path = match favicon {
+        Favicon::None => {
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = StatusCode::NO_CONTENT;
+            return Ok(resp);
+        }
+        Favicon::File(path) => path,
+    };
+
+    let icon = fs::read(path)?;
+    let mut resp = Response::new(Body::empty());
+    let headers = resp.headers_mut();
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(icon.len()));
+    headers.insert(CONTENT_TYPE, local_static::content_type(path, mime));
+    if req.method() != Method::HEAD {
+        *resp.body_mut() = Body::from(icon);
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_reads_of_the_favicon_are_intercepted() {
+        let get = Request::get("/favicon.ico").body(Body::empty()).unwrap();
+        assert!(is_favicon(&get));
+
+        let post = Request::post("/favicon.ico").body(Body::empty()).unwrap();
+        assert!(!is_favicon(&post));
+
+        let nested = Request::get("/img/favicon.ico")
+            .body(Body::empty())
+            .unwrap();
+        assert!(!is_favicon(&nested));
+    }
+
+    #[test]
+    fn favicons_are_none_or_an_existing_file() {
+        assert_eq!(parse("none").unwrap(), Favicon::None);
+        assert!(parse("/no/such/favicon.ico").is_err());
+
+        let icon = std::env::temp_dir().join("wrangler-dev-favicon-test.png");
+        fs::write(&icon, [0x89, b'P', b'N', b'G']).unwrap();
+        assert_eq!(
+            parse(icon.to_str().unwrap()).unwrap(),
+            Favicon::File(icon.clone())
+        );
+
+        let req = Request::get("/favicon.ico").body(Body::empty()).unwrap();
+        let resp = serve(&Favicon::File(icon), &req, &[]).unwrap();
+        assert_eq!(resp.headers()[CONTENT_TYPE], "image/png");
+        assert_eq!(resp.headers()[CONTENT_LENGTH], "4");
+    }
+}
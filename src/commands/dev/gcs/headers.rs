@@ -12,21 +12,40 @@ const HEADER_PREFIX: &str = "cf-ew-raw-";
 /// must have the prefix stripped
 use std::str::FromStr;
 
+use crate::commands::dev::cf_headers;
+use crate::terminal::message::{Message, StdErr};
+
 use anyhow::Result;
-use hyper::header::{HeaderMap, HeaderName};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING};
 use hyper::http::request::Parts as RequestParts;
 use hyper::http::response::Parts as ResponseParts;
 use hyper::http::status::StatusCode;
 
+/// whether the length of a response's body can be told from its headers
+#[derive(Debug, PartialEq)]
+pub enum Framing {
+    Unambiguous,
+    /// the headers disagree on where the body ends, as described
+    Ambiguous(String),
+}
+
 /// modify an incoming request before sending it to the preview service
 pub fn structure_request(parts: &mut RequestParts) {
     prepend_request_headers_prefix(parts)
 }
 
-/// modify a response from the preview service before returning it to the user
-pub fn destructure_response(parts: &mut ResponseParts) -> Result<()> {
+/// modify a response from the preview service before returning it to the user,
+/// adding the headers Cloudflare would in production with `emulate_cf_headers`
+pub fn destructure_response(
+    parts: &mut ResponseParts,
+    emulate_cf_headers: bool,
+) -> Result<Framing> {
     set_response_status(parts)?;
-    strip_response_headers_prefix(parts)
+    strip_response_headers_prefix(parts)?;
+    if emulate_cf_headers {
+        cf_headers::emulate(&mut parts.headers);
+    }
+    Ok(normalize_framing(&mut parts.headers))
 }
 
 /// every header sent to `wrangler dev` must be prefixed
@@ -67,6 +86,46 @@ fn strip_response_headers_prefix(parts: &mut ResponseParts) -> Result<()> {
     Ok(())
 }
 
+/// a Worker can set framing headers that contradict each other, which isn't
+/// passed on, as clients and proxies could each take the body to end somewhere
+/// else. With both `Transfer-Encoding` and `Content-Length`, the encoding
+/// decides, so the length is dropped. Repeats of the same `Content-Length`
+/// are collapsed into one, but ones that differ can't be settled
+fn normalize_framing(headers: &mut HeaderMap) -> Framing {
+    if !headers.contains_key(CONTENT_LENGTH) {
+        return Framing::Unambiguous;
+    }
+
+    if headers.contains_key(TRANSFER_ENCODING) {
+        StdErr::warn(
+            "The Worker responded with both Transfer-Encoding and Content-Length, so Content-Length was dropped",
+        );
+        headers.remove(CONTENT_LENGTH);
+        return Framing::Unambiguous;
+    }
+
+    // a repeated header can also be sent as one with a list of values
+    let lengths: Vec<&str> = headers
+        .get_all(CONTENT_LENGTH)
+        .iter()
+        .flat_map(|value| value.as_bytes().split(|b| *b == b','))
+        .map(|length| std::str::from_utf8(length).unwrap_or_default().trim())
+        .collect();
+    let length = match lengths[0].parse::<u64>() {
+        Ok(length) if lengths.iter().all(|other| *other == lengths[0]) => length,
+        _ => {
+            return Framing::Ambiguous(format!(
+                "The Worker responded with conflicting Content-Length headers: {}",
+                lengths.join(", ")
+            ))
+        }
+    };
+    if lengths.len() > 1 {
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+    }
+    Framing::Unambiguous
+}
+
 /// parse the response status from headers sent by the preview service
 /// and apply the parsed result to mutable ResponseParts
 fn set_response_status(parts: &mut ResponseParts) -> Result<()> {
@@ -106,4 +165,57 @@ mod tests {
         assert_eq!(&second_cookie, iter.next().unwrap());
         assert!(iter.next().is_none());
     }
+
+    fn destructured(headers: &[(&str, &str)]) -> (Framing, HeaderMap) {
+        let mut response = Response::builder().header("cf-ew-status", "200 OK");
+        for (name, value) in headers {
+            response = response.header(format!("{}{}", HEADER_PREFIX, name), *value);
+        }
+        let (mut parts, _) = response.body(()).unwrap().into_parts();
+        let framing = destructure_response(&mut parts, false).unwrap();
+        (framing, parts.headers)
+    }
+
+    #[test]
+    fn transfer_encoding_overrides_content_length() {
+        let (framing, headers) =
+            destructured(&[("Content-Length", "12"), ("Transfer-Encoding", "chunked")]);
+        assert_eq!(framing, Framing::Unambiguous);
+        assert!(!headers.contains_key(CONTENT_LENGTH));
+        assert_eq!(headers[TRANSFER_ENCODING], "chunked");
+    }
+
+    #[test]
+    fn repeated_content_lengths_are_collapsed() {
+        let (framing, headers) =
+            destructured(&[("Content-Length", "12"), ("Content-Length", "12")]);
+        assert_eq!(framing, Framing::Unambiguous);
+        assert_eq!(headers.get_all(CONTENT_LENGTH).iter().count(), 1);
+        assert_eq!(headers[CONTENT_LENGTH], "12");
+
+        let (framing, headers) = destructured(&[("Content-Length", "12, 12")]);
+        assert_eq!(framing, Framing::Unambiguous);
+        assert_eq!(headers[CONTENT_LENGTH], "12");
+
+        let (framing, headers) = destructured(&[("Content-Length", "12")]);
+        assert_eq!(framing, Framing::Unambiguous);
+        assert_eq!(headers[CONTENT_LENGTH], "12");
+    }
+
+    #[test]
+    fn conflicting_content_lengths_are_ambiguous() {
+        let (framing, _) = destructured(&[("Content-Length", "12"), ("Content-Length", "13")]);
+        assert_eq!(
+            framing,
+            Framing::Ambiguous(
+                "The Worker responded with conflicting Content-Length headers: 12, 13".to_string()
+            )
+        );
+
+        let (framing, _) = destructured(&[("Content-Length", "12, 13")]);
+        assert!(matches!(framing, Framing::Ambiguous(_)));
+
+        let (framing, _) = destructured(&[("Content-Length", "twelve")]);
+        assert!(matches!(framing, Framing::Ambiguous(_)));
+    }
 }
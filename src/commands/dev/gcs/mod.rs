@@ -1,4 +1,5 @@
 mod headers;
+mod reuse;
 mod server;
 mod setup;
 mod watch;
@@ -6,7 +7,9 @@ mod watch;
 use setup::{get_preview_id, get_session_id};
 use watch::watch_for_changes;
 
-use crate::commands::dev::{socket, Protocol, ServerConfig};
+use crate::commands::dev::{
+    once, preview_ids, rate_limit, shutdown, socket, Protocol, ServerConfig,
+};
 use crate::settings::toml::Target;
 
 use anyhow::Result;
@@ -23,20 +26,37 @@ pub fn dev(
     local_protocol: Protocol,
     verbose: bool,
 ) -> Result<()> {
-    println!("unauthenticated");
+    server_config.options.banner("unauthenticated");
+    server_config.options.banner(&format!(
+        "Previewing on {}",
+        server::preview_host(&server_config.options)
+    ));
 
     // setup the session
     let session_id = get_session_id()?;
 
-    // upload the initial script
-    let preview_id = get_preview_id(
-        target.clone(),
-        // there is no user for unauthenticated dev
-        None,
-        &server_config,
-        &session_id,
-        verbose,
-    )?;
+    // upload the initial script, unless a preview was given with --preview-token
+    let preview_token = server_config.options.preview_token.clone();
+    let preview_id = match &preview_token {
+        Some(preview_token) => preview_token.clone(),
+        None => rate_limit::retry(&server_config.options, || {
+            get_preview_id(
+                target.clone(),
+                // there is no user for unauthenticated dev
+                None,
+                &server_config,
+                &session_id,
+                verbose,
+            )
+        })?,
+    };
+    preview_ids::started(
+        &preview_id,
+        match &preview_token {
+            Some(_) => "given with --preview-token",
+            None => "from your local code",
+        },
+    );
 
     // the local server needs the preview ID to properly route
     // HTTP requests
@@ -49,7 +69,8 @@ pub fn dev(
     // file watcher to release the lock before routing a request
     let preview_id = Arc::new(Mutex::new(preview_id));
     // a new scope is created to satisfy the borrow checker
-    {
+    // and a preview from --preview-token is never replaced, so it isn't watched
+    if preview_token.is_none() {
         // we must clone each of these variables in order to
         // safely use them in another thread
         let session_id = session_id.clone();
@@ -68,13 +89,19 @@ pub fn dev(
 
     let socket_url = get_socket_url(&session_id)?;
 
+    let serve_once = server_config.options.once;
+    let https_config = server_config.dual_listen_https()?;
+
     // in order to spawn futures we must create a tokio runtime
     let runtime = TokioRuntime::new()?;
 
     // and we must block the main thread on the completion of
     // said futures
     runtime.block_on(async {
-        let devtools_listener = tokio::spawn(socket::listen(socket_url.clone()));
+        let devtools_listener = tokio::spawn(socket::listen_if_uploaded(
+            socket_url.clone(),
+            preview_token.is_none(),
+        ));
 
         let server = match local_protocol {
             Protocol::Https => tokio::spawn(server::https(
@@ -86,7 +113,27 @@ pub fn dev(
             }
         };
 
-        let res = tokio::try_join!(async { devtools_listener.await? }, async { server.await? });
+        // with --dual-listen, serve over https as well
+        let https_server = https_config
+            .map(|https_config| tokio::spawn(server::https(https_config, Arc::clone(&preview_id))));
+
+        let res = tokio::select! {
+            res = async {
+                tokio::try_join!(
+                    async { devtools_listener.await? },
+                    async { server.await? },
+                    async {
+                        match https_server {
+                            Some(https_server) => https_server.await?,
+                            None => Ok(()),
+                        }
+                    }
+                )
+            } => res.map(|_| ()),
+            // stop serving cleanly on Ctrl-C or SIGTERM
+            _ = shutdown::signal() => Ok(()),
+            _ = once::served(), if serve_once => Ok(()),
+        };
         match res {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
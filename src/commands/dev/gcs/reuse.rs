@@ -0,0 +1,109 @@
+//! `--reuse-preview` remembers the script id of the last upload to the preview
+//! service, along with a hash of the bundle that was uploaded. When
+//! `wrangler dev` starts again with the same bundle, the script is reused
+//! instead of being uploaded again.
+//!
+//! The cache lives at `~/.wrangler/dev/preview-cache.json`, one entry per Worker.
+use super::server;
+use crate::commands::dev::{bundle, DevOptions};
+use crate::settings::get_wrangler_home_dir;
+use crate::settings::toml::Target;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    previews: HashMap<String, CachedPreview>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedPreview {
+    bundle_hash: String,
+    script_id: String,
+}
+
+/// the script id of a previous upload of this exact bundle, if it is still live
+/// for the session, whose preview id for a script is given by `preview_id`
+pub(super) fn reuse(
+    target: &Target,
+    options: &DevOptions,
+    preview_id: impl Fn(&str) -> String,
+) -> Option<String> {
+    let bundle_hash = match bundle::hash(target) {
+        Ok(bundle_hash) => bundle_hash,
+        Err(e) => {
+            log::debug!("Could not hash the bundle to reuse a preview: {}", e);
+            return None;
+        }
+    };
+
+    let cache = load().ok()?;
+    let cached = cache.previews.get(&target.name)?;
+    if cached.bundle_hash != bundle_hash {
+        return None;
+    }
+
+    // previews expire, so make sure the preview service still has the script
+    if is_live(
+        &preview_id(&cached.script_id),
+        server::preview_host(options),
+    ) {
+        Some(cached.script_id.clone())
+    } else {
+        log::debug!("Cached preview {} has expired", cached.script_id);
+        None
+    }
+}
+
+/// whether the preview service still runs a Worker for `preview_id`, asked the
+/// way requests are routed to it. Only a response from a Worker has its status
+/// in `cf-ew-status`, anything else comes from the preview service itself
+fn is_live(preview_id: &str, preview_host: &str) -> bool {
+    let address = format!("https://{}/", preview_host);
+    match crate::http::client()
+        .get(&address)
+        .header("cf-ew-preview", preview_id)
+        .send()
+    {
+        Ok(response) => response.headers().contains_key("cf-ew-status"),
+        Err(e) => {
+            log::debug!("Could not reach the preview service: {}", e);
+            false
+        }
+    }
+}
+
+/// remember the script id the current bundle was uploaded as
+pub(super) fn remember(target: &Target, script_id: &str) -> Result<()> {
+    let mut cache = load().unwrap_or_default();
+    cache.previews.insert(
+        target.name.clone(),
+        CachedPreview {
+            bundle_hash: bundle::hash(target)?,
+            script_id: script_id.to_string(),
+        },
+    );
+
+    let path = cache_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&cache)?)?;
+    Ok(())
+}
+
+fn cache_path() -> Result<PathBuf> {
+    Ok(get_wrangler_home_dir()?
+        .join("dev")
+        .join("preview-cache.json"))
+}
+
+fn load() -> Result<Cache> {
+    let cache = fs::read_to_string(cache_path()?)?;
+    Ok(serde_json::from_str(&cache)?)
+}
@@ -4,36 +4,85 @@ mod https;
 pub use self::http::http;
 pub use self::https::https;
 
-use crate::commands::dev::gcs::headers::structure_request;
+use crate::commands::dev::echo_request;
+use crate::commands::dev::error_response;
+use crate::commands::dev::gcs::headers::{destructure_response, structure_request, Framing};
+use crate::commands::dev::loop_guard;
+use crate::commands::dev::self_profile::{self, Span};
+use crate::commands::dev::upstream::Connector;
 use crate::commands::dev::utils::get_path_as_str;
+use crate::commands::dev::DevOptions;
 
-use hyper::client::{HttpConnector, ResponseFuture};
-use hyper::header::{HeaderName, HeaderValue};
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use hyper::header::{HeaderName, HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING};
 use hyper::http::uri::InvalidUri;
-use hyper::{Body, Client as HyperClient, Request, Uri};
-use hyper_rustls::HttpsConnector;
+use hyper::{Body, Client as HyperClient, Request, Response, StatusCode, Uri};
 
 const PREVIEW_HOST: &str = "rawhttp.cloudflareworkers.com";
 
-fn get_preview_url(path_string: &str) -> Result<Uri, InvalidUri> {
-    format!("https://{}{}", PREVIEW_HOST, path_string).parse()
+/// the preview service requests are sent to, which can be changed with --preview-host
+pub(in crate::commands::dev::gcs) fn preview_host(options: &DevOptions) -> &str {
+    options.preview_host.as_deref().unwrap_or(PREVIEW_HOST)
+}
+
+fn get_preview_url(preview_host: &str, path_string: &str) -> Result<Uri, InvalidUri> {
+    format!("https://{}{}", preview_host, path_string).parse()
 }
 
 pub fn preview_request(
     req: Request<Body>,
-    client: HyperClient<HttpsConnector<HttpConnector>>,
+    client: HyperClient<Connector>,
     preview_id: String,
-) -> ResponseFuture {
+    preview_host: &str,
+) -> BoxFuture<'static, Result<Response<Body>>> {
+    let req = build_request(req, &preview_id, preview_host);
+    self_profile::waiting(echo_request::send(&client, req))
+}
+
+/// format a response from the preview service for the user, answering one whose
+/// framing the Worker left ambiguous with a 502 instead
+fn destructure(resp: Response<Body>, emulate_cf_headers: bool) -> Result<Response<Body>> {
+    // an echoed request was answered by dev, not the preview service
+    if echo_request::is_echo(&resp) {
+        return Ok(resp);
+    }
+    let (mut parts, body) = resp.into_parts();
+    let _span = Span::enter(self_profile::DESTRUCTURE_RESPONSE);
+    match destructure_response(&mut parts, emulate_cf_headers)? {
+        Framing::Unambiguous => Ok(Response::from_parts(parts, body)),
+        Framing::Ambiguous(detail) => Ok(error_response::build(StatusCode::BAD_GATEWAY, detail)),
+    }
+}
+
+fn build_request(req: Request<Body>, preview_id: &str, preview_host: &str) -> Request<Body> {
     let (mut parts, body) = req.into_parts();
 
     let path = get_path_as_str(&parts.uri);
-    let preview_id = &preview_id;
 
-    structure_request(&mut parts);
+    // the client's framing headers are about to be prefixed for the Worker, which would
+    // leave hyper to frame the body itself, chunking any it doesn't know the length of
+    let content_length = match parts.headers.get(TRANSFER_ENCODING) {
+        Some(_) => None,
+        None => parts.headers.get(CONTENT_LENGTH).cloned(),
+    };
+
+    {
+        let _span = Span::enter(self_profile::STRUCTURE_REQUEST);
+        structure_request(&mut parts);
+    }
+    // added after the Worker's headers are prefixed, so a dev server looped back to sees it
+    loop_guard::mark(&mut parts);
+
+    // so the preview service gets the body framed as the client sent it: with the same
+    // Content-Length, chunked, or with no framing headers at all when there is no body
+    if let Some(content_length) = content_length {
+        parts.headers.insert(CONTENT_LENGTH, content_length);
+    }
 
     parts.headers.insert(
         HeaderName::from_static("host"),
-        HeaderValue::from_static(PREVIEW_HOST),
+        HeaderValue::from_str(preview_host).expect("Could not create host header"),
     );
 
     parts.headers.insert(
@@ -41,9 +90,84 @@ pub fn preview_request(
         HeaderValue::from_str(preview_id).expect("Could not create header for preview id"),
     );
 
-    parts.uri = get_preview_url(&path).expect("Could not get preview url");
+    parts.uri = get_preview_url(preview_host, &path).expect("Could not get preview url");
+
+    Request::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Response;
+
+    /// the framing headers of a request as the preview service receives it
+    async fn framing_upstream(req: Request<Body>) -> String {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(service_fn(|req: Request<Body>| async move {
+                let header = |name| {
+                    req.headers().get(name).map_or("none".to_string(), |value| {
+                        value.to_str().unwrap().to_string()
+                    })
+                };
+                let framing = format!(
+                    "content-length: {}, transfer-encoding: {}",
+                    header(CONTENT_LENGTH),
+                    header(TRANSFER_ENCODING)
+                );
+                Ok::<_, hyper::Error>(Response::new(Body::from(framing)))
+            }))
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let mut req = build_request(req, "abc123", PREVIEW_HOST);
+        // the test server only speaks plain http
+        *req.uri_mut() = format!("http://{}{}", addr, req.uri().path())
+            .parse()
+            .unwrap();
+        let resp = hyper::Client::new().request(req).await.unwrap();
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_body_have_no_framing() {
+        let get = Request::get("/").body(Body::empty()).unwrap();
+        assert_eq!(
+            framing_upstream(get).await,
+            "content-length: none, transfer-encoding: none"
+        );
+
+        let post = Request::post("/").body(Body::empty()).unwrap();
+        assert_eq!(
+            framing_upstream(post).await,
+            "content-length: none, transfer-encoding: none"
+        );
+    }
 
-    let req = Request::from_parts(parts, body);
+    #[tokio::test]
+    async fn a_content_length_is_kept() {
+        let post = Request::post("/")
+            .header(CONTENT_LENGTH, "0")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            framing_upstream(post).await,
+            "content-length: 0, transfer-encoding: none"
+        );
 
-    client.request(req)
+        // even once the body is streamed through dev, as with a body read timeout
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move { sender.send_data("hello".into()).await });
+        let post = Request::post("/")
+            .header(CONTENT_LENGTH, "5")
+            .body(body)
+            .unwrap();
+        assert_eq!(
+            framing_upstream(post).await,
+            "content-length: 5, transfer-encoding: none"
+        );
+    }
 }
@@ -1,4 +1,5 @@
-use crate::commands::dev::ServerConfig;
+use super::reuse;
+use crate::commands::dev::{tui, ServerConfig};
 use crate::preview::upload;
 use crate::settings::global_user::GlobalUser;
 use crate::settings::toml::Target;
@@ -17,6 +18,9 @@ pub(super) fn get_session_id() -> Result<String> {
 ///
 /// this is used when sending requests to the Workers Runtime
 /// so it executes the correct Worker
+///
+/// with `--reuse-preview`, an earlier upload of the same bundle is used
+/// instead when the preview service still has it
 pub fn get_preview_id(
     mut target: Target,
     user: Option<GlobalUser>,
@@ -28,12 +32,34 @@ pub fn get_preview_id(
     // directing the user to open the browser to view the output
     // this message makes sense for `wrangler preview` but not `wrangler dev`
     let sites_preview = false;
-    let script_id = upload(&mut target, user.as_ref(), sites_preview, verbose)?;
-    Ok(format!(
-        "{}{}{}{}",
-        &script_id,
-        session_id,
-        server_config.host.is_https() as u8,
-        server_config.host
-    ))
+    let reuse_preview = server_config.options.reuse_preview;
+    let preview_id = |script_id: &str| {
+        format!(
+            "{}{}{}{}",
+            script_id,
+            session_id,
+            server_config.host.is_https() as u8,
+            server_config.host
+        )
+    };
+    let reused = reuse_preview
+        .then(|| reuse::reuse(&target, &server_config.options, &preview_id))
+        .flatten();
+    let script_id = match reused {
+        Some(script_id) => {
+            log::info!("Reusing preview {} as the bundle hasn't changed", script_id);
+            script_id
+        }
+        None => {
+            let script_id = upload(&mut target, user.as_ref(), sites_preview, verbose)?;
+            if reuse_preview {
+                if let Err(e) = reuse::remember(&target, &script_id) {
+                    log::warn!("Could not cache the preview for --reuse-preview: {}", e);
+                }
+            }
+            script_id
+        }
+    };
+    tui::set_preview(&script_id);
+    Ok(preview_id(&script_id))
 }
@@ -1,6 +1,13 @@
+use std::mem;
 use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
 
+use crate::build::build_target;
+use crate::commands::dev::config_reload;
+use crate::commands::dev::drain::{self, DRAIN_WINDOW};
+use crate::commands::dev::events::{self, Event};
 use crate::commands::dev::gcs::setup::get_preview_id;
+use crate::commands::dev::rebuild::{self, Rebuild, Rebuilt};
 use crate::commands::dev::server_config::ServerConfig;
 
 use crate::settings::toml::Target;
@@ -9,28 +16,76 @@ use crate::watch::watch_and_build;
 use anyhow::Result;
 
 pub fn watch_for_changes(
-    target: Target,
+    mut target: Target,
     server_config: &ServerConfig,
     preview_id: Arc<Mutex<String>>,
     session_id: &str,
     verbose: bool,
 ) -> Result<()> {
     let (sender, receiver) = mpsc::channel();
-    watch_and_build(&target, Some(sender))?;
+    rebuild::listen(sender.clone());
+    watch_and_build(&target, Some(sender), server_config.watch_scope.clone())?;
 
     while receiver.recv().is_ok() {
+        // with --reload-on-config-change, a changed wrangler.toml forces a rebuild
+        if let Some(reloaded) = config_reload::take(&target) {
+            target = reloaded;
+        }
         let target = target.clone();
+        let started = Instant::now();
+
+        // a change has already been built by the watcher, a forced rebuild hasn't
+        if rebuild::is_forced() {
+            if let Err(e) = build_target(&target) {
+                rebuild::finished(Err(e.to_string()));
+                continue;
+            }
+        }
+
+        let (old_id, new_id) = if server_config.options.rebuild_placeholder {
+            // requests get a placeholder until the new script is ready,
+            // so the lock is only held to swap it in
+            let _rebuild = Rebuild::start();
+            let new_id = upload(target, server_config, session_id, verbose)?;
+            let old_id = mem::replace(&mut *preview_id.lock().unwrap(), new_id.clone());
+            (old_id, new_id)
+        } else {
+            // acquire the lock so incoming requests are halted
+            // until the new script is ready for them
+            let mut preview_id = preview_id.lock().unwrap();
 
-        // acquire the lock so incoming requests are halted
-        // until the new script is ready for them
-        let mut preview_id = preview_id.lock().unwrap();
+            // while holding the lock, assign a new preview id
+            //
+            // this allows the server to route subsequent requests
+            // to the proper script
+            let new_id = upload(target, server_config, session_id, verbose)?;
+            let old_id = mem::replace(&mut *preview_id, new_id.clone());
+            (old_id, new_id)
+        };
 
-        // while holding the lock, assign a new preview id
-        //
-        // this allows the server to route subsequent requests
-        // to the proper script
-        *preview_id = get_preview_id(target, None, server_config, session_id, verbose)?;
+        rebuild::finished(Ok(Rebuilt {
+            preview: new_id,
+            duration: started.elapsed(),
+        }));
+        events::emit(Event::Rebuild);
+
+        // requests already sent to the old preview finish there
+        // before another change can replace it
+        drain::retire(&old_id, DRAIN_WINDOW);
     }
 
     Ok(())
 }
+
+/// uploads the rebuilt Worker, letting anyone waiting on a forced rebuild know if that failed
+fn upload(
+    target: Target,
+    server_config: &ServerConfig,
+    session_id: &str,
+    verbose: bool,
+) -> Result<String> {
+    get_preview_id(target, None, server_config, session_id, verbose).map_err(|e| {
+        rebuild::finished(Err(e.to_string()));
+        e
+    })
+}
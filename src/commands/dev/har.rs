@@ -0,0 +1,490 @@
+//! `--har <file>` records every request made to `wrangler dev`, and the
+//! response it got back, into a HAR 1.2 file that is written out when the
+//! session ends. HAR files can be imported into browser devtools and most
+//! HTTP analysis tools, which makes a dev session easy to share.
+//!
+//! Only the first `BODY_LIMIT` bytes of each body are recorded, a gzipped
+//! response body decoded, and the values of headers that carry credentials are
+//! replaced with `[redacted]`.
+use crate::commands::dev::captured_body::{Capture, Captured};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, CONTENT_TYPE, LOCATION};
+use hyper::{Body, Request, Response};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json::json;
+
+pub(super) const BODY_LIMIT: usize = 64 * 1024;
+const REDACTED: &str = "[redacted]";
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+];
+
+static RECORDER: OnceCell<Recorder> = OnceCell::new();
+
+struct Recorder {
+    path: PathBuf,
+    entries: Mutex<Vec<Entry>>,
+}
+
+/// start recording requests, to be written to `path` by `save`
+pub fn init(path: &Path) {
+    let recorder = Recorder {
+        path: path.to_path_buf(),
+        entries: Mutex::new(Vec::new()),
+    };
+    if RECORDER.set(recorder).is_err() {
+        log::debug!("HAR recording was already started");
+    }
+}
+
+/// write every recorded request to the HAR file, if recording
+///
+/// returns the number of entries written
+pub fn save() -> Result<Option<usize>> {
+    if let Some(recorder) = RECORDER.get() {
+        let entries = recorder.entries.lock().unwrap();
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": "wrangler",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": &*entries,
+            }
+        });
+        fs::write(&recorder.path, serde_json::to_string_pretty(&har)?)?;
+        Ok(Some(entries.len()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// a request that is being recorded
+///
+/// both bodies are recorded as they stream through, so recording
+/// doesn't hold up sending a large upload to the preview service
+pub(super) struct Recording {
+    started_date_time: DateTime<Local>,
+    start: Instant,
+    request: HarRequest,
+    request_body: Arc<Mutex<RequestBody>>,
+}
+
+/// the part of the request body recorded so far
+#[derive(Default)]
+struct RequestBody {
+    captured: Vec<u8>,
+    size: usize,
+    mime_type: String,
+    /// when the whole body had been sent upstream
+    sent: Option<Instant>,
+}
+
+impl RequestBody {
+    fn capture(&mut self, chunk: &[u8]) {
+        self.size += chunk.len();
+        let remaining = BODY_LIMIT.saturating_sub(self.captured.len());
+        self.captured
+            .extend_from_slice(&chunk[..remaining.min(chunk.len())]);
+    }
+
+    fn post_data(&self) -> Option<PostData> {
+        if self.size == 0 {
+            None
+        } else {
+            Some(PostData {
+                mime_type: self.mime_type.clone(),
+                text: String::from_utf8_lossy(&self.captured).into_owned(),
+            })
+        }
+    }
+}
+
+impl Recording {
+    pub(super) fn start(
+        req: Request<Body>,
+        url: String,
+        started_date_time: DateTime<Local>,
+    ) -> (Request<Body>, Recording) {
+        let start = Instant::now();
+        let (parts, mut body) = req.into_parts();
+
+        let query_string = parts
+            .uri
+            .query()
+            .map(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .map(|(name, value)| NameValue {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let request = HarRequest {
+            method: parts.method.to_string(),
+            url,
+            http_version: format!("{:?}", parts.version),
+            cookies: Vec::new(),
+            headers: har_headers(&parts.headers),
+            query_string,
+            post_data: None,
+            headers_size: -1,
+            body_size: 0,
+        };
+
+        let request_body = Arc::new(Mutex::new(RequestBody {
+            mime_type: mime_type(&parts.headers),
+            ..Default::default()
+        }));
+
+        // a request without a body is left as it is, so it can still be told apart
+        let body = if body.is_end_stream() {
+            request_body.lock().unwrap().sent = Some(Instant::now());
+            body
+        } else {
+            let (mut sender, tee) = Body::channel();
+            let request_body = Arc::clone(&request_body);
+            tokio::spawn(async move {
+                while let Some(chunk) = body.data().await {
+                    match chunk {
+                        Ok(chunk) => {
+                            request_body.lock().unwrap().capture(&chunk);
+                            if sender.send_data(chunk).await.is_err() {
+                                // the request to the preview service was dropped
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            log::debug!("Failed to read request body: {}", e);
+                            sender.abort();
+                            break;
+                        }
+                    }
+                }
+                request_body.lock().unwrap().sent = Some(Instant::now());
+            });
+            tee
+        };
+
+        let recording = Recording {
+            started_date_time,
+            start,
+            request,
+            request_body,
+        };
+
+        (Request::from_parts(parts, body), recording)
+    }
+
+    /// record the response, the entry is saved once its body
+    /// has finished streaming to the client
+    pub(super) fn finish(self, resp: Response<Body>) -> Response<Body> {
+        let received = Instant::now();
+        let (parts, mut body) = resp.into_parts();
+
+        let status = parts.status.as_u16();
+        let status_text = parts.status.canonical_reason().unwrap_or("").to_string();
+        let http_version = format!("{:?}", parts.version);
+        let headers = har_headers(&parts.headers);
+        let mime_type = mime_type(&parts.headers);
+        let redirect_url = parts
+            .headers
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+
+        let mut capture = Capture::new(&parts.headers);
+        let (mut sender, tee) = Body::channel();
+        tokio::spawn(async move {
+            while let Some(chunk) = body.data().await {
+                match chunk {
+                    Ok(chunk) => {
+                        capture.write(&chunk);
+                        if sender.send_data(chunk).await.is_err() {
+                            // the client went away
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        log::debug!("Failed to read response body: {}", e);
+                        sender.abort();
+                        break;
+                    }
+                }
+            }
+
+            let captured = capture.finish();
+            let body_size = captured.sent as i64;
+            let content = Content::new(captured, mime_type);
+            let response = HarResponse {
+                status,
+                status_text,
+                http_version,
+                cookies: Vec::new(),
+                headers,
+                content,
+                redirect_url,
+                headers_size: -1,
+                body_size,
+            };
+
+            // the request body has been sent by now, unless the Worker
+            // responded without reading all of it
+            let mut request = self.request;
+            let sent = {
+                let request_body = self.request_body.lock().unwrap();
+                request.post_data = request_body.post_data();
+                request.body_size = request_body.size as i64;
+                request_body.sent.unwrap_or(received)
+            };
+
+            let timings = Timings {
+                send: millis(sent.saturating_duration_since(self.start)),
+                wait: millis(received.saturating_duration_since(sent)),
+                receive: millis(received.elapsed()),
+            };
+
+            let entry = Entry {
+                started_date_time: self.started_date_time.to_rfc3339(),
+                time: millis(self.start.elapsed()),
+                request,
+                response,
+                cache: json!({}),
+                timings,
+            };
+
+            if let Some(recorder) = RECORDER.get() {
+                recorder.entries.lock().unwrap().push(entry);
+            }
+        });
+
+        Response::from_parts(parts, tee)
+    }
+}
+
+fn har_headers(headers: &HeaderMap) -> Vec<NameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| NameValue {
+            name: name.to_string(),
+            value: if REDACTED_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            },
+        })
+        .collect()
+}
+
+fn mime_type(headers: &HeaderMap) -> String {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: String,
+    time: f64,
+    request: HarRequest,
+    response: HarResponse,
+    cache: serde_json::Value,
+    timings: Timings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    http_version: String,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    query_string: Vec<NameValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    content: Content,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+struct NameValue {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    mime_type: String,
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    size: usize,
+    mime_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+impl Content {
+    /// the content of a body, as far as it was `captured`
+    fn new(captured: Captured, mime_type: String) -> Content {
+        let Captured { body, size, sent } = captured;
+        let comment = if body.len() < size {
+            Some(format!("truncated to the first {} bytes", body.len()))
+        } else {
+            None
+        };
+        // the bytes saved by sending the body compressed
+        let compression = if sent == size {
+            None
+        } else {
+            Some(size as i64 - sent as i64)
+        };
+
+        let (text, encoding) = match std::str::from_utf8(&body) {
+            Ok(text) => (text.to_string(), None),
+            Err(_) => (base64::encode(&body), Some("base64".to_string())),
+        };
+
+        Content {
+            size,
+            compression,
+            mime_type,
+            text,
+            encoding,
+            comment,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Timings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Bytes;
+
+    /// a body of `size` bytes sent as it is, of which `body` was captured
+    fn captured(body: impl Into<Bytes>, size: usize) -> Captured {
+        Captured {
+            body: body.into(),
+            size,
+            sent: size,
+        }
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer hunter2".parse().unwrap());
+        headers.insert("accept", "text/html".parse().unwrap());
+
+        let headers = har_headers(&headers);
+        let authorization = headers.iter().find(|h| h.name == "authorization").unwrap();
+        let accept = headers.iter().find(|h| h.name == "accept").unwrap();
+
+        assert_eq!(authorization.value, REDACTED);
+        assert_eq!(accept.value, "text/html");
+    }
+
+    #[test]
+    fn request_bodies_are_captured_up_to_the_limit() {
+        let mut request_body = RequestBody::default();
+        request_body.capture(&vec![b'a'; BODY_LIMIT - 1]);
+        request_body.capture(b"bc");
+
+        assert_eq!(request_body.size, BODY_LIMIT + 1);
+        assert_eq!(request_body.captured.len(), BODY_LIMIT);
+        assert!(request_body.post_data().unwrap().text.ends_with("ab"));
+    }
+
+    #[test]
+    fn truncated_bodies_are_noted() {
+        let content = Content::new(captured("hello", 11), "text/plain".to_string());
+
+        assert_eq!(content.size, 11);
+        assert_eq!(content.text, "hello");
+        assert!(content.encoding.is_none());
+        assert!(content.comment.is_some());
+    }
+
+    #[test]
+    fn compressed_bodies_note_the_bytes_saved() {
+        let content = Content::new(
+            Captured {
+                body: Bytes::from("hello hello hello"),
+                size: 17,
+                sent: 12,
+            },
+            "text/plain".to_string(),
+        );
+
+        assert_eq!(content.text, "hello hello hello");
+        assert_eq!(content.compression, Some(5));
+        assert!(content.comment.is_none());
+    }
+
+    #[test]
+    fn binary_bodies_are_base64_encoded() {
+        let content = Content::new(
+            captured(vec![0xff, 0xfe], 2),
+            "application/octet-stream".to_string(),
+        );
+
+        assert_eq!(content.text, "//4=");
+        assert_eq!(content.encoding, Some("base64".to_string()));
+        assert!(content.comment.is_none());
+    }
+}
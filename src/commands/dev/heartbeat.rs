@@ -0,0 +1,136 @@
+//! A Server-Sent Events stream can go quiet for minutes between events, and
+//! whatever sits between the client and dev, like a corporate proxy, a NAT or a
+//! load balancer in front of a tunnel, may close a connection that has been idle
+//! for a while, ending the stream.
+//!
+//! `--keepalive-interval <secs>` keeps `text/event-stream` responses busy by
+//! sending an SSE comment, `: ping`, whenever the Worker has sent nothing for
+//! that long. Clients ignore comments, so the Worker's events arrive unchanged.
+//! A ping is only sent between events, never in the middle of one still being
+//! written. Other responses are left alone, as anything added to them would
+//! change what the client receives. It's off by default.
+use std::time::Duration;
+
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Response};
+
+const PING: &[u8] = b": ping\n\n";
+
+/// sends a ping on an event stream whenever it's been idle for `interval`
+pub(super) fn inject(resp: Response<Body>, interval: Duration) -> Response<Body> {
+    if !is_event_stream(&resp) || resp.body().is_end_stream() {
+        return resp;
+    }
+
+    let (mut parts, mut body) = resp.into_parts();
+    // the pings make the body longer than the Worker said it would be
+    parts.headers.remove(CONTENT_LENGTH);
+    let (mut sender, pinged) = Body::channel();
+    tokio::spawn(async move {
+        let mut between_events = true;
+        loop {
+            match tokio::time::timeout(interval, body.data()).await {
+                Ok(Some(Ok(chunk))) => {
+                    if !chunk.is_empty() {
+                        between_events = ends_event(&chunk);
+                    }
+                    if sender.send_data(chunk).await.is_err() {
+                        // the client closed the stream
+                        break;
+                    }
+                }
+                Ok(Some(Err(e))) => {
+                    log::debug!("Failed to read event stream: {}", e);
+                    sender.abort();
+                    break;
+                }
+                Ok(None) => break,
+                Err(_) if between_events => {
+                    if sender.send_data(Bytes::from_static(PING)).await.is_err() {
+                        break;
+                    }
+                }
+                // an event is part way through being sent, so wait for the rest of it
+                Err(_) => {}
+            }
+        }
+    });
+    Response::from_parts(parts, pinged)
+}
+
+fn is_event_stream(resp: &Response<Body>) -> bool {
+    resp.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, |content_type| {
+            content_type.to_lowercase().starts_with("text/event-stream")
+        })
+}
+
+/// whether `chunk` finishes with the blank line that ends an event
+fn ends_event(chunk: &[u8]) -> bool {
+    chunk.ends_with(b"\n\n") || chunk.ends_with(b"\r\n\r\n") || chunk.ends_with(b"\r\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Sender;
+
+    fn event_stream() -> (Sender, Response<Body>) {
+        let (sender, body) = Body::channel();
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .body(body)
+            .unwrap();
+        (sender, resp)
+    }
+
+    async fn read_all(resp: Response<Body>) -> String {
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn idle_event_streams_are_pinged_between_events() {
+        let (mut sender, resp) = event_stream();
+        let resp = inject(resp, Duration::from_millis(20));
+        tokio::spawn(async move {
+            sender.send_data("data: one\n\n".into()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(70)).await;
+            sender.send_data("data: tw".into()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(70)).await;
+            sender.send_data("o\n\n".into()).await.unwrap();
+        });
+
+        let body = read_all(resp).await;
+        assert!(body.starts_with("data: one\n\n: ping\n\n"));
+        assert!(body.ends_with("data: two\n\n"));
+        assert!(!body.contains("tw: ping"));
+    }
+
+    #[tokio::test]
+    async fn other_responses_are_left_alone() {
+        let (mut sender, body) = Body::channel();
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+        let resp = inject(resp, Duration::from_millis(10));
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            sender.send_data("{}".into()).await.unwrap();
+        });
+
+        assert_eq!(read_all(resp).await, "{}");
+    }
+
+    #[test]
+    fn events_end_with_a_blank_line() {
+        assert!(ends_event(b"data: a\n\n"));
+        assert!(ends_event(b"data: a\r\n\r\n"));
+        assert!(!ends_event(b"data: a\n"));
+        assert!(!ends_event(b"data: a"));
+    }
+}
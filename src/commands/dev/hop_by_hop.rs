@@ -0,0 +1,70 @@
+//! Hop-by-hop headers describe a single connection, so the ones on a response
+//! from the preview service are about dev's connection to it, not the client's
+//! connection to dev. They're taken off before the response is passed on, and
+//! hyper decides how the client's connection is kept alive or closed.
+//!
+//! That covers `Connection`, the headers it names, `Keep-Alive` and
+//! `Proxy-Connection`. `Transfer-Encoding` is left to hyper, which frames the
+//! body for the client itself, and `Upgrade` stays on a `101 Switching
+//! Protocols`, which needs it.
+use hyper::header::{HeaderMap, HeaderName, CONNECTION, UPGRADE};
+use hyper::StatusCode;
+
+const HOP_BY_HOP: &[&str] = &["keep-alive", "proxy-connection"];
+
+/// removes the hop-by-hop headers of a response from upstream
+pub(super) fn strip(status: StatusCode, headers: &mut HeaderMap) {
+    let named: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    let switching = status == StatusCode::SWITCHING_PROTOCOLS;
+
+    for name in named {
+        if !(switching && name == UPGRADE) {
+            headers.remove(name);
+        }
+    }
+    if !switching {
+        headers.remove(CONNECTION);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{HeaderValue, CONTENT_TYPE};
+
+    #[test]
+    fn connection_headers_are_removed() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONNECTION,
+            HeaderValue::from_static("close, x-upstream-hint"),
+        );
+        headers.insert("x-upstream-hint", HeaderValue::from_static("1"));
+        headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+
+        strip(StatusCode::OK, &mut headers);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[CONTENT_TYPE], "text/plain");
+    }
+
+    #[test]
+    fn upgrades_keep_what_they_need() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+
+        strip(StatusCode::SWITCHING_PROTOCOLS, &mut headers);
+        assert_eq!(headers[CONNECTION], "Upgrade");
+        assert_eq!(headers[UPGRADE], "websocket");
+    }
+}
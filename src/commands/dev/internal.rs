@@ -0,0 +1,132 @@
+//! `wrangler dev` reserves a path prefix, `/__wrangler` unless changed with
+//! `--internal-prefix`, for endpoints that are answered by wrangler itself
+//! rather than by the Worker.
+//!
+//! Requests under the prefix never reach the preview service:
+//!
+//! - `<prefix>/health` responds `200` with `{"status":"ok"}`
+//! - `<prefix>/status` responds with how many requests were made to each path
+//!   and how long they took on average, most requested first, along with
+//!   histograms of latency and of request and response sizes
+//! - `POST <prefix>/rebuild` builds and uploads the Worker again, as if a file
+//!   had changed, and responds with the start of the new preview's id and how
+//!   long that took in milliseconds
+//! - with `--echo-request`, `<prefix>/echo-request/<path>` responds with what
+//!   a request for `<path>` would send to the Worker, see `echo_request`
+//! - any other path under the prefix responds `404`
+use crate::commands::dev::rebuild;
+use crate::commands::dev::stats;
+
+use anyhow::Result;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+
+pub const DEFAULT_PREFIX: &str = "/__wrangler";
+
+/// normalizes a user provided prefix so it always has
+/// a leading slash and never has a trailing one
+pub fn parse_prefix(prefix: &str) -> Result<String> {
+    let prefix = format!("/{}", prefix.trim_matches('/'));
+    if prefix == "/" {
+        anyhow::bail!(
+            "The internal prefix cannot be the root path, as every request would match it"
+        )
+    }
+    Ok(prefix)
+}
+
+/// if `path` is under `prefix`, returns the endpoint it names
+pub(super) fn endpoint<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('/')
+    }
+}
+
+/// answers a request for one of wrangler's own endpoints
+pub(super) async fn handle_internal(endpoint: &str, req: Request<Body>) -> Result<Response<Body>> {
+    match endpoint {
+        "health" => json_response(StatusCode::OK, json!({ "status": "ok" })),
+        "status" => json_response(
+            StatusCode::OK,
+            json!({
+                "status": "ok",
+                "paths": stats::paths(),
+                "histograms": stats::histograms(),
+            }),
+        ),
+        "rebuild" if req.method() == Method::POST => force_rebuild().await,
+        "rebuild" => json_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            json!({ "error": "rebuilds are forced with a POST" }),
+        ),
+        _ => json_response(
+            StatusCode::NOT_FOUND,
+            json!({ "error": format!("wrangler has no endpoint named {:?}", endpoint) }),
+        ),
+    }
+}
+
+async fn force_rebuild() -> Result<Response<Body>> {
+    let rebuilt = match rebuild::force() {
+        Some(rebuilt) => rebuilt.await,
+        None => {
+            let error = "wrangler dev isn't watching this Worker, so there's nothing to rebuild";
+            return json_response(StatusCode::CONFLICT, json!({ "error": error }));
+        }
+    };
+
+    match rebuilt {
+        Ok(Ok(rebuilt)) => json_response(
+            StatusCode::OK,
+            json!({
+                "status": "ok",
+                "preview": rebuilt.short_preview(),
+                "duration_ms": rebuilt.duration.as_millis() as u64,
+            }),
+        ),
+        Ok(Err(message)) => json_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({ "error": message }),
+        ),
+        // the watcher stopped before the rebuild was done
+        Err(_) => json_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({ "error": "the rebuild was abandoned" }),
+        ),
+    }
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Result<Response<Body>> {
+    let mut resp = Response::new(Body::from(serde_json::to_string(&body)?));
+    *resp.status_mut() = status;
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_are_normalized() {
+        assert_eq!(parse_prefix("__dev/").unwrap(), "/__dev");
+        assert_eq!(parse_prefix("/__dev").unwrap(), "/__dev");
+        assert!(parse_prefix("/").is_err());
+    }
+
+    #[test]
+    fn endpoints_are_found_under_the_prefix() {
+        assert_eq!(
+            endpoint("/__wrangler/health", DEFAULT_PREFIX),
+            Some("health")
+        );
+        assert_eq!(endpoint("/__wrangler", DEFAULT_PREFIX), Some(""));
+        assert_eq!(endpoint("/__wranglerish", DEFAULT_PREFIX), None);
+        assert_eq!(endpoint("/health", DEFAULT_PREFIX), None);
+    }
+}
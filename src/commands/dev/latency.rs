@@ -0,0 +1,86 @@
+//! `--chaos-ttfb` and `--chaos-latency` slow responses down like a slow network
+//! would, to see how a frontend copes with it.
+//!
+//! `--chaos-ttfb <ms>` holds back the response headers, so nothing arrives until
+//! that long after the Worker responded, and then lets the body stream as it
+//! arrives. It is what a spinner waiting on the first byte reacts to.
+//!
+//! `--chaos-latency <ms>` holds back the whole response. Its body is read in full,
+//! and headers and body are sent together once that long has passed since the
+//! Worker responded, or once the body has been read if that took longer.
+//!
+//! Given both, nothing is sent before either has passed, so the larger one wins
+//! and the response arrives all at once. Both count from when the Worker
+//! responded, so time held back by `--simulate-cpu` counts towards them
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use hyper::{Body, Response};
+
+/// holds `resp` back as `--chaos-ttfb` and `--chaos-latency` say, counting from
+/// `responded`, when the Worker responded. Returns a note for the request log
+pub(super) async fn inject(
+    resp: Response<Body>,
+    ttfb: Option<u64>,
+    latency: Option<u64>,
+    responded: Instant,
+) -> Result<(Response<Body>, Option<String>)> {
+    let ttfb = ttfb.map(Duration::from_millis);
+    let latency = latency.map(Duration::from_millis);
+
+    match (ttfb, latency) {
+        (ttfb, Some(latency)) => {
+            let (parts, body) = resp.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let hold = latency.max(ttfb.unwrap_or_default());
+            tokio::time::sleep_until((responded + hold).into()).await;
+            let note = format!("+{}ms latency", hold.as_millis());
+            Ok((Response::from_parts(parts, Body::from(body)), Some(note)))
+        }
+        (Some(ttfb), None) => {
+            tokio::time::sleep_until((responded + ttfb).into()).await;
+            let note = format!("+{}ms to first byte", ttfb.as_millis());
+            Ok((resp, Some(note)))
+        }
+        (None, None) => Ok((resp, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::HttpBody;
+
+    fn streamed() -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                sender.send_data("chunk".into()).await.ok();
+            }
+        });
+        Response::new(body)
+    }
+
+    #[tokio::test]
+    async fn ttfb_only_holds_the_headers() {
+        let responded = Instant::now();
+        let (resp, note) = inject(streamed(), Some(50), None, responded).await.unwrap();
+
+        assert!(responded.elapsed() >= Duration::from_millis(50));
+        assert_eq!(note.unwrap(), "+50ms to first byte");
+        // the body is still streamed, its length isn't known up front
+        assert_eq!(resp.body().size_hint().exact(), None);
+    }
+
+    #[tokio::test]
+    async fn latency_holds_the_whole_response() {
+        let responded = Instant::now();
+        let (resp, note) = inject(streamed(), Some(20), Some(50), responded)
+            .await
+            .unwrap();
+
+        assert!(responded.elapsed() >= Duration::from_millis(50));
+        assert_eq!(note.unwrap(), "+50ms latency");
+        assert_eq!(resp.body().size_hint().exact(), Some(15));
+    }
+}
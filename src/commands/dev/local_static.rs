@@ -0,0 +1,315 @@
+//! `--local-static` serves files from the Workers Sites bucket straight from
+//! disk, so asset changes show up without waiting for an upload. Requests that
+//! don't name a file in the bucket are sent to the Worker as usual.
+//!
+//! `HEAD` requests get the same headers as a `GET` would, without a body.
+//!
+//! Like production, every file gets an `ETag` and `Last-Modified` header, and
+//! conditional requests for a file that hasn't changed get a `304 Not Modified`.
+//! Unlike production, files are sent with `Cache-Control: no-cache` so the
+//! browser revalidates them on every load and an edit shows up straight away.
+//! `--static-max-age <secs>` sends `max-age=<secs>` instead, to see how the
+//! site behaves with the caching it'll have once deployed.
+//!
+//! The `Content-Type` comes from the file's extension. `--mime ext=type` sets it
+//! for extensions the built-in table gets wrong or doesn't know, which are
+//! otherwise served as `application/octet-stream`.
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use hyper::header::{
+    HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LAST_MODIFIED,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// an extension and the `Content-Type` to serve it with, from `--mime`
+pub type MimeOverride = (String, HeaderValue);
+
+/// parses `ext=type` given to `--mime`, where the extension may start with a dot
+pub fn parse_mime(mime: &str) -> Result<MimeOverride> {
+    let (extension, content_type) = mime
+        .split_once('=')
+        .map(|(extension, content_type)| {
+            (
+                extension.trim().trim_start_matches('.'),
+                content_type.trim(),
+            )
+        })
+        .filter(|(extension, content_type)| !extension.is_empty() && content_type.contains('/'))
+        .ok_or_else(|| anyhow!("Expected ext=type, like wasm=application/wasm"))?;
+    let content_type = HeaderValue::from_str(content_type)
+        .map_err(|_| anyhow!("{} is not a valid Content-Type", content_type))?;
+    Ok((extension.to_lowercase(), content_type))
+}
+
+/// responds with the file in `root` that the request names, if there is one
+pub(super) fn serve(
+    root: &Path,
+    req: &Request<Body>,
+    mime: &[MimeOverride],
+    max_age: Option<u64>,
+) -> Result<Option<Response<Body>>> {
+    let head = req.method() == Method::HEAD;
+    if req.method() != Method::GET && !head {
+        return Ok(None);
+    }
+
+    let file = match resolve(root, req.uri().path()) {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+    let metadata = match fs::metadata(&file) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Ok(None),
+    };
+
+    let modified: DateTime<Utc> = metadata.modified().unwrap_or(UNIX_EPOCH).into();
+    let etag = etag(metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len());
+
+    let mut resp = if is_not_modified(req.headers(), &etag, modified) {
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::NOT_MODIFIED;
+        resp
+    } else {
+        // a HEAD gets the same headers as a GET, without reading the file
+        let mut resp = Response::new(Body::empty());
+        resp.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(metadata.len()));
+        resp.headers_mut()
+            .insert(CONTENT_TYPE, content_type(&file, mime));
+        if !head {
+            *resp.body_mut() = Body::from(fs::read(&file)?);
+        }
+        resp
+    };
+
+    let headers = resp.headers_mut();
+    headers.insert(ETAG, HeaderValue::from_str(&etag)?);
+    headers.insert(
+        LAST_MODIFIED,
+        HeaderValue::from_str(&modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string())?,
+    );
+    headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_str(&cache_control(max_age))?,
+    );
+
+    Ok(Some(resp))
+}
+
+/// the `Cache-Control` for files, which a 304 repeats so the browser's copy is
+/// stored with the same freshness
+fn cache_control(max_age: Option<u64>) -> String {
+    match max_age {
+        Some(secs) => format!("max-age={}", secs),
+        None => "no-cache".to_string(),
+    }
+}
+
+/// maps a request path onto a file in `root`, the way Workers Sites does,
+/// refusing any path that would escape `root`
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let mut file = root.join(relative);
+    if path.ends_with('/') {
+        file.push("index.html");
+    }
+    Some(file)
+}
+
+fn etag(modified: SystemTime, len: u64) -> String {
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|modified| modified.as_secs())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", modified, len)
+}
+
+/// `If-None-Match` takes precedence over `If-Modified-Since`, as in RFC 7232
+fn is_not_modified(headers: &HeaderMap, etag: &str, modified: DateTime<Utc>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+        return if_none_match.to_str().map_or(false, |if_none_match| {
+            if_none_match
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag)
+        });
+    }
+
+    headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        .map_or(false, |since| modified.timestamp() <= since.timestamp())
+}
+
+/// the `Content-Type` for a file, from `--mime` if it names the file's extension
+pub(super) fn content_type(file: &Path, mime: &[MimeOverride]) -> HeaderValue {
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    let extension = extension.as_deref();
+
+    // the last --mime for an extension wins
+    if let Some((_, content_type)) = mime
+        .iter()
+        .rev()
+        .find(|(overridden, _)| Some(overridden.as_str()) == extension)
+    {
+        return content_type.clone();
+    }
+
+    HeaderValue::from_static(match extension {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "application/javascript",
+        Some("json") | Some("map") => "application/json",
+        Some("webmanifest") => "application/manifest+json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        _ => {
+            log::debug!(
+                "No Content-Type is known for {}, serving it as application/octet-stream",
+                file.display()
+            );
+            "application/octet-stream"
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::HttpBody;
+
+    fn modified() -> DateTime<Utc> {
+        Utc.ymd(2021, 6, 1).and_hms(12, 0, 0)
+    }
+
+    #[test]
+    fn matching_etags_are_not_modified() {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, "\"a\", \"b\"".parse().unwrap());
+
+        assert!(is_not_modified(&headers, "\"b\"", modified()));
+        assert!(!is_not_modified(&headers, "\"c\"", modified()));
+    }
+
+    #[test]
+    fn files_unchanged_since_are_not_modified() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            "Tue, 01 Jun 2021 12:00:00 GMT".parse().unwrap(),
+        );
+        assert!(is_not_modified(&headers, "\"a\"", modified()));
+
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            "Mon, 31 May 2021 12:00:00 GMT".parse().unwrap(),
+        );
+        assert!(!is_not_modified(&headers, "\"a\"", modified()));
+    }
+
+    #[test]
+    fn head_and_get_have_the_same_headers() {
+        let root = std::env::temp_dir().join("wrangler-dev-local-static-test");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("index.html"), "<h1>hello</h1>").unwrap();
+
+        let get = Request::get("/").body(Body::empty()).unwrap();
+        let head = Request::head("/").body(Body::empty()).unwrap();
+        let get = serve(&root, &get, &[], None).unwrap().unwrap();
+        let head = serve(&root, &head, &[], None).unwrap().unwrap();
+
+        assert_eq!(get.headers(), head.headers());
+        assert_eq!(head.headers()[CONTENT_LENGTH], "14");
+        assert!(head.body().is_end_stream());
+        assert!(!get.body().is_end_stream());
+    }
+
+    #[test]
+    fn wasm_is_served_as_wasm() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("module.wasm"), [0, 0x61, 0x73, 0x6d]).unwrap();
+        fs::write(dir.path().join("app.webmanifest"), "{}").unwrap();
+
+        let req = Request::get("/module.wasm").body(Body::empty()).unwrap();
+        let resp = serve(dir.path(), &req, &[], None).unwrap().unwrap();
+        assert_eq!(resp.headers()[CONTENT_TYPE], "application/wasm");
+
+        let mime = vec![parse_mime(".WebManifest=application/json").unwrap()];
+        let req = Request::get("/app.webmanifest")
+            .body(Body::empty())
+            .unwrap();
+        let resp = serve(dir.path(), &req, &mime, None).unwrap().unwrap();
+        assert_eq!(resp.headers()[CONTENT_TYPE], "application/json");
+
+        assert_eq!(
+            content_type(Path::new("data.unknown"), &mime),
+            "application/octet-stream"
+        );
+        assert!(parse_mime("wasm").is_err());
+        assert!(parse_mime("wasm=wasm").is_err());
+    }
+
+    #[test]
+    fn files_are_not_cached_unless_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.js"), "console.log(1)").unwrap();
+
+        let req = Request::get("/app.js").body(Body::empty()).unwrap();
+        let resp = serve(dir.path(), &req, &[], None).unwrap().unwrap();
+        assert_eq!(resp.headers()[CACHE_CONTROL], "no-cache");
+
+        let resp = serve(dir.path(), &req, &[], Some(3600)).unwrap().unwrap();
+        assert_eq!(resp.headers()[CACHE_CONTROL], "max-age=3600");
+
+        let etag = resp.headers()[ETAG].clone();
+        let req = Request::get("/app.js")
+            .header(IF_NONE_MATCH, etag)
+            .body(Body::empty())
+            .unwrap();
+        let resp = serve(dir.path(), &req, &[], Some(0)).unwrap().unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[CACHE_CONTROL], "max-age=0");
+    }
+
+    #[test]
+    fn paths_cannot_escape_the_bucket() {
+        let root = Path::new("public");
+
+        assert_eq!(resolve(root, "/"), Some(PathBuf::from("public/index.html")));
+        assert_eq!(
+            resolve(root, "/css/main.css"),
+            Some(PathBuf::from("public/css/main.css"))
+        );
+        assert_eq!(resolve(root, "/../secrets"), None);
+    }
+}
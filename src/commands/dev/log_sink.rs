@@ -0,0 +1,173 @@
+//! `--log-sink <url>` sends every request log line, as JSON, somewhere other
+//! than the terminal as well, so a team can collect logs from dev sessions:
+//!
+//! - `http://` and `https://` URLs are sent a `POST` per entry
+//! - `syslog://host:port` is sent an RFC 5424 message per entry over UDP
+//!
+//! Each entry looks like:
+//!
+//! ```json
+//! {
+//!   "timestamp": "2020-04-20T15:25:54+00:00",
+//!   "method": "GET",
+//!   "url": "example.com/",
+//!   "version": "HTTP/1.1",
+//!   "status": 200,
+//!   "notes": ["route example.com/*"]
+//! }
+//! ```
+//!
+//! Delivery is at most once and best effort. Entries wait in a buffer of
+//! `BUFFER_SIZE` for a background thread to send them, and are dropped when it
+//! is full, so a slow or unreachable sink never holds up a request. A dropped
+//! or undeliverable entry is warned about the first time, and entries still
+//! in the buffer when the session ends are lost
+use crate::terminal::message::{Message, StdErr};
+
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use url::Url;
+
+const BUFFER_SIZE: usize = 1024;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SINK: OnceCell<SyncSender<Entry>> = OnceCell::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static DROP_WARNING: Once = Once::new();
+static DELIVERY_WARNING: Once = Once::new();
+
+#[derive(Debug, Serialize)]
+pub(super) struct Entry {
+    pub timestamp: String,
+    pub method: String,
+    pub url: String,
+    pub version: String,
+    pub status: u16,
+    pub notes: Vec<String>,
+}
+
+/// parses the URL given to `--log-sink`
+pub fn parse(sink: &str) -> Result<Url> {
+    let url = Url::parse(sink).map_err(|e| anyhow!("{} is not a URL: {}", sink, e))?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        "syslog" if url.host_str().is_some() && url.port().is_some() => Ok(url),
+        "syslog" => anyhow::bail!("Expected a syslog URL like syslog://localhost:514"),
+        scheme => anyhow::bail!(
+            "{} log sinks aren't supported, use an http, https or syslog URL",
+            scheme
+        ),
+    }
+}
+
+/// start sending entries to `url` in the background
+pub(super) fn init(url: &Url) -> Result<()> {
+    let mut deliver: Box<dyn FnMut(&Entry) -> Result<()> + Send> = if url.scheme() == "syslog" {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let addr = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port().unwrap_or(514)
+        );
+        Box::new(move |entry| {
+            socket.send_to(syslog_message(entry)?.as_bytes(), &addr)?;
+            Ok(())
+        })
+    } else {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()?;
+        let url = url.clone();
+        Box::new(move |entry| {
+            client
+                .post(url.clone())
+                .json(entry)
+                .send()?
+                .error_for_status()?;
+            Ok(())
+        })
+    };
+
+    let (sender, receiver) = mpsc::sync_channel(BUFFER_SIZE);
+    SINK.set(sender)
+        .map_err(|_| anyhow!("The log sink has already been started"))?;
+
+    let sink = url.to_string();
+    thread::spawn(move || {
+        for entry in receiver {
+            if let Err(e) = deliver(&entry) {
+                DELIVERY_WARNING.call_once(|| {
+                    StdErr::warn(&format!(
+                        "Could not send a log entry to {}, entries that can't be delivered are dropped: {}",
+                        sink, e
+                    ))
+                });
+                log::debug!("Failed to send a log entry: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// queues an entry for the log sink, if there is one, without waiting
+pub(super) fn send(entry: Entry) {
+    if let Some(sink) = SINK.get() {
+        if let Err(TrySendError::Full(_)) = sink.try_send(entry) {
+            let dropped = DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+            DROP_WARNING.call_once(|| {
+                StdErr::warn(&format!(
+                    "The log sink is falling behind, dropping log entries until it catches up ({} so far)",
+                    dropped
+                ))
+            });
+        }
+    }
+}
+
+/// `<14>` is the user facility at the informational severity
+fn syslog_message(entry: &Entry) -> Result<String> {
+    Ok(format!(
+        "<14>1 {} - wrangler-dev {} - - {}",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        std::process::id(),
+        serde_json::to_string(entry)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sinks_must_be_http_or_syslog() {
+        assert!(parse("https://logs.example.com/ingest").is_ok());
+        assert!(parse("syslog://localhost:514").is_ok());
+        assert!(parse("syslog://localhost").is_err());
+        assert!(parse("ftp://logs.example.com").is_err());
+    }
+
+    #[test]
+    fn syslog_messages_carry_the_entry_as_json() {
+        let entry = Entry {
+            timestamp: "2020-04-20T15:25:54+00:00".to_string(),
+            method: "GET".to_string(),
+            url: "example.com/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            notes: vec![],
+        };
+        let message = syslog_message(&entry).unwrap();
+
+        assert!(message.starts_with("<14>1 "));
+        assert!(message.ends_with(&serde_json::to_string(&entry).unwrap()));
+    }
+}
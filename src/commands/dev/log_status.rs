@@ -0,0 +1,141 @@
+//! `--log-status <spec>` only prints the request log lines of responses with
+//! the statuses it lists, to watch for failures without the noise of every
+//! request that went fine. The spec is a comma separated list of status codes
+//! and classes, so `--log-status 4xx,5xx` shows every error and
+//! `--log-status 500,404` only those two. Every request is still served, and
+//! still sent to `--log-sink`, the dashboard and the stats as usual.
+//!
+//! `--quiet` prints no request log lines at all, whatever `--log-status` says.
+use crate::commands::dev::DevOptions;
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use hyper::StatusCode;
+
+/// the statuses given to `--log-status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusFilter(Vec<Status>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    /// every status starting with this digit, like 5xx
+    Class(u16),
+    Code(u16),
+}
+
+impl FromStr for StatusFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(|status| {
+                parse_status(status.trim()).ok_or_else(|| {
+                    anyhow!(
+                        "Expected status codes like 404 or classes like 5xx, separated by commas, got {}",
+                        s
+                    )
+                })
+            })
+            .collect::<Result<Vec<Status>>>()
+            .map(StatusFilter)
+    }
+}
+
+/// a status code like 404, or a class like 5xx
+fn parse_status(status: &str) -> Option<Status> {
+    let class = status
+        .strip_suffix("xx")
+        .or_else(|| status.strip_suffix("XX"));
+    let (digits, status) = match class {
+        Some(class) => (class, Status::Class(class.parse().ok()?)),
+        None => (status, Status::Code(status.parse().ok()?)),
+    };
+    // as the standard library takes a leading + as well
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match status {
+        Status::Class(class) if digits.len() == 1 && (1..=5).contains(&class) => Some(status),
+        Status::Code(code) if digits.len() == 3 && (100..=599).contains(&code) => Some(status),
+        _ => None,
+    }
+}
+
+impl StatusFilter {
+    fn matches(&self, status: StatusCode) -> bool {
+        let code = status.as_u16();
+        self.0.iter().any(|pattern| match *pattern {
+            Status::Class(class) => code / 100 == class,
+            Status::Code(wanted) => code == wanted,
+        })
+    }
+}
+
+/// whether a response with `status` gets a line in the request log
+pub(super) fn is_shown(options: &DevOptions, status: StatusCode) -> bool {
+    if options.quiet {
+        return false;
+    }
+    options
+        .log_status
+        .as_ref()
+        .map_or(true, |filter| filter.matches(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(spec: &str) -> DevOptions {
+        DevOptions {
+            log_status: Some(spec.parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn specs_list_classes_and_codes() {
+        assert_eq!(
+            "4xx, 5XX,404".parse::<StatusFilter>().unwrap(),
+            StatusFilter(vec![Status::Class(4), Status::Class(5), Status::Code(404)])
+        );
+        for spec in &[
+            "", "4xx,", "6xx", "0xx", "44xx", "x", "40", "4040", "600", "099", "+404", "abc",
+        ] {
+            assert!(
+                spec.parse::<StatusFilter>().is_err(),
+                "{} was accepted",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn only_listed_statuses_are_shown() {
+        let options = options("5xx,404");
+        assert!(is_shown(&options, StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_shown(&options, StatusCode::BAD_GATEWAY));
+        assert!(is_shown(&options, StatusCode::NOT_FOUND));
+        assert!(!is_shown(&options, StatusCode::OK));
+        assert!(!is_shown(&options, StatusCode::FORBIDDEN));
+
+        assert!(is_shown(&DevOptions::default(), StatusCode::OK));
+    }
+
+    #[test]
+    fn quiet_shows_nothing() {
+        let options = DevOptions {
+            quiet: true,
+            ..options("5xx")
+        };
+        assert!(!is_shown(&options, StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_shown(
+            &DevOptions {
+                quiet: true,
+                ..Default::default()
+            },
+            StatusCode::OK
+        ));
+    }
+}
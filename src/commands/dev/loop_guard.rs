@@ -0,0 +1,80 @@
+//! Catches a dev server that has been pointed back at itself, with `--host` or
+//! `--resolve`, before requests loop forever. Every request sent upstream carries
+//! the number of dev servers it has passed through, and a request that has passed
+//! through too many is answered with a 508
+use crate::commands::dev::error_response;
+use crate::commands::dev::utils::get_path_as_str;
+use crate::terminal::message::{Message, StdErr};
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::http::request::Parts as RequestParts;
+use hyper::{Body, Request, Response, StatusCode};
+
+pub(super) const HOPS_HEADER: &str = "x-wrangler-dev-hops";
+
+/// more dev servers than anyone chains on purpose
+const MAX_HOPS: u32 = 5;
+
+/// how many dev servers a request had passed through when it arrived at this one
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hops(u32);
+
+/// takes the hop count off an incoming request, so the Worker never sees it,
+/// or answers with a 508 if the request has been through too many dev servers
+pub(super) fn guard(req: &mut Request<Body>) -> Option<Response<Body>> {
+    let hops = req
+        .headers_mut()
+        .remove(HOPS_HEADER)
+        .and_then(|hops| hops.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+
+    if hops < MAX_HOPS {
+        req.extensions_mut().insert(Hops(hops));
+        return None;
+    }
+
+    StdErr::warn(&format!(
+        "Rejected {} {} with a 508, it has already been through {} dev servers. Check that --host or --resolve don't point back at wrangler dev itself",
+        req.method(),
+        get_path_as_str(req.uri()),
+        hops
+    ));
+
+    Some(error_response::build(
+        StatusCode::LOOP_DETECTED,
+        "Loop detected: this request was sent back to wrangler dev by its own upstream",
+    ))
+}
+
+/// counts this dev server on a request about to be sent to the preview service
+pub(super) fn mark(parts: &mut RequestParts) {
+    let hops = parts.extensions.get::<Hops>().map_or(0, |hops| hops.0);
+    parts.headers.insert(
+        HeaderName::from_static(HOPS_HEADER),
+        HeaderValue::from(hops + 1),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(req: Request<Body>) -> Request<Body> {
+        let (mut parts, body) = req.into_parts();
+        mark(&mut parts);
+        Request::from_parts(parts, body)
+    }
+
+    #[test]
+    fn requests_that_keep_coming_back_are_rejected() {
+        let mut req = Request::get("/").body(Body::empty()).unwrap();
+        for hop in 1..=MAX_HOPS {
+            assert!(guard(&mut req).is_none());
+            req = forwarded(req);
+            assert_eq!(req.headers()[HOPS_HEADER], hop.to_string());
+        }
+
+        let resp = guard(&mut req).unwrap();
+        assert_eq!(resp.status(), StatusCode::LOOP_DETECTED);
+    }
+}
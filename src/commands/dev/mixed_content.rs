@@ -0,0 +1,91 @@
+//! `--warn-mixed-content` looks for `http://` subresources in HTML served over
+//! https, which browsers block without saying much. It only warns: the body is
+//! passed on unchanged, and links and form targets aren't subresources so
+//! they're left alone. Like `--rewrite-host`, the HTML is matched as text rather
+//! than parsed, so URLs added by scripts at runtime are missed.
+use anyhow::Result;
+use hyper::{Body, Response};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::commands::dev::replace;
+use crate::terminal::message::{Message, StdErr};
+
+/// an `http://` URL in an attribute a browser loads as a subresource
+static INSECURE_SUBRESOURCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)<(?:script|img|link|iframe|audio|video|source|track|embed|object|input)\b[^>]*?\b(?:src|href|data|poster|srcset)\s*=\s*["']?\s*(http://[^"'\s>]+)"#,
+    )
+    .unwrap()
+});
+
+/// the most URLs listed in one warning
+const MAX_LISTED: usize = 10;
+
+/// warns about the insecure subresources of an HTML response, which means reading the
+/// whole body first. Other responses are left as they are
+pub(super) async fn warn(resp: Response<Body>, description: &str) -> Result<Response<Body>> {
+    if !replace::is_html(resp.headers()) {
+        return Ok(resp);
+    }
+
+    let (parts, body) = resp.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    if let Ok(html) = std::str::from_utf8(&body) {
+        let urls = insecure_subresources(html);
+        if !urls.is_empty() {
+            let mut message = format!(
+                "{} loads {} over http, which browsers block on an https page:",
+                description,
+                urls.len()
+            );
+            for url in urls.iter().take(MAX_LISTED) {
+                message.push_str(&format!("\n  {}", url));
+            }
+            if urls.len() > MAX_LISTED {
+                message.push_str(&format!("\n  and {} more", urls.len() - MAX_LISTED));
+            }
+            StdErr::warn(&message);
+        }
+    }
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// every distinct insecure subresource URL, in the order they appear
+fn insecure_subresources(html: &str) -> Vec<&str> {
+    let mut urls: Vec<&str> = Vec::new();
+    for captures in INSECURE_SUBRESOURCE.captures_iter(html) {
+        let url = captures.get(1).map_or("", |url| url.as_str());
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_insecure_subresources_are_found() {
+        let html = r#"
+            <link rel="stylesheet" href="http://cdn.example.com/main.css">
+            <script src='http://cdn.example.com/app.js'></script>
+            <script src="https://cdn.example.com/safe.js"></script>
+            <IMG SRC=http://images.example.com/logo.png alt="logo">
+            <img src="http://images.example.com/logo.png">
+            <a href="http://example.com/">a link is not a subresource</a>
+            <form action="http://example.com/submit"></form>
+        "#;
+
+        assert_eq!(
+            insecure_subresources(html),
+            vec![
+                "http://cdn.example.com/main.css",
+                "http://cdn.example.com/app.js",
+                "http://images.example.com/logo.png",
+            ]
+        );
+    }
+}
@@ -1,22 +1,96 @@
+mod access_log;
+mod allowed_methods;
+mod base_path;
+mod body_timeout;
+mod bundle;
+mod captured_body;
+mod cf;
+mod cf_headers;
+mod coalesce;
+mod compress;
+mod concurrency;
+mod config_reload;
+mod cors;
+mod diff;
+mod drain;
+mod echo;
+mod echo_request;
 mod edge;
+mod error_response;
+mod events;
+mod fail_path;
+mod favicon;
 mod gcs;
+mod har;
+mod heartbeat;
+mod hop_by_hop;
+mod internal;
+mod latency;
+mod local_static;
+mod log_sink;
+mod log_status;
+mod loop_guard;
+mod mixed_content;
+mod once;
+mod options;
+mod original_host;
+mod preview_ids;
+mod rate_limit;
+mod rebuild;
+mod replace;
+mod request_body;
+mod response_body;
+mod response_headers;
+mod response_size;
+mod response_time;
+mod resume;
+mod route_filter;
+mod routes;
+mod self_profile;
+mod serve;
 mod server_config;
+mod shutdown;
 mod socket;
+mod stats;
+mod strict_host;
 mod tls;
+mod trace_context;
+mod tui;
+mod upstream;
+mod upstream_timeout;
 mod utils;
+mod variant;
+mod verbosity;
+mod worker_logs;
 
+pub use config_reload::reload_config_on_change;
+pub use options::DevOptions;
+pub use routes::Routes;
 pub use server_config::Protocol;
 pub use server_config::ServerConfig;
 
+use events::Event;
+
 use crate::build::build_target;
 use crate::deploy::{DeployTarget, DeploymentSet};
 use crate::settings::global_user::GlobalUser;
-use crate::settings::toml::Target;
-use crate::terminal::message::{Message, StdOut};
-use crate::terminal::styles;
+use crate::settings::toml::{Target, TargetType};
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::{emoji, styles};
+use crate::upload;
+use crate::watch::WatchScope;
 
 use anyhow::Result;
 
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use number_prefix::NumberPrefix;
+use tempfile::NamedTempFile;
+
 /// `wrangler dev` starts a server on a dev machine that routes incoming HTTP requests
 /// to a Cloudflare Workers runtime and returns HTTP responses
 pub fn dev(
@@ -28,8 +102,171 @@ pub fn dev(
     upstream_protocol: Protocol,
     verbose: bool,
 ) -> Result<()> {
-    // before serving requests we must first build the Worker
-    build_target(&target)?;
+    if let Some(events_socket) = &server_config.options.events_socket {
+        events::init(events_socket)?;
+    }
+    if let Some(har) = &server_config.options.har {
+        har::init(har);
+    }
+    if let Some(diff) = &server_config.options.diff {
+        diff::init(diff)?;
+    }
+    if let Some(dump_bundle) = &server_config.options.dump_bundle {
+        upload::form::dump::init(dump_bundle);
+    }
+    if let Some(log_sink) = &server_config.options.log_sink {
+        log_sink::init(log_sink)?;
+    }
+    if server_config.options.show_logs {
+        worker_logs::init();
+    }
+    if server_config.options.json_errors {
+        error_response::init();
+    }
+    if let Some(self_profile) = &server_config.options.self_profile {
+        self_profile::init(self_profile);
+    }
+    if let Some(record_preview_id) = &server_config.options.record_preview_id {
+        preview_ids::init(record_preview_id.as_deref())?;
+    }
+    if !server_config.options.latency_buckets.is_empty() {
+        stats::set_latency_buckets(&server_config.options.latency_buckets)?;
+    }
+    if server_config.options.tui && !server_config.options.no_tui {
+        tui::start()?;
+    }
+
+    let started = Instant::now();
+    let options = Arc::clone(&server_config.options);
+
+    let result = run(
+        target,
+        deployments,
+        user,
+        server_config,
+        local_protocol,
+        upstream_protocol,
+        verbose,
+    );
+    // before the summary and any error, so they aren't cleared with the dashboard
+    tui::stop();
+
+    // with --once, the session only succeeds if the Worker responded as expected
+    let result = result.and_then(|_| match options.expect_status {
+        Some(expected) => once::check(expected),
+        None => Ok(()),
+    });
+    // and with --diff-fail, only if it responded as it did when the baseline was recorded
+    let result = result.and_then(|_| diff::finish(options.diff_fail));
+
+    // requests are recorded even if the session ended with an error
+    let saved = har::save().map(|saved| {
+        if let Some(count) = saved {
+            StdErr::success(&format!("Recorded {} requests to a HAR file", count));
+        }
+    });
+
+    // and so is the profile of dev's own overhead
+    let profiled = self_profile::save().map(|summary| {
+        if let Some(summary) = summary {
+            StdErr::info(&summary);
+        }
+    });
+
+    if let Some(summary) = stats::summary(started.elapsed()) {
+        options.banner(&format!("{} {}", emoji::SPARKLES, summary));
+    }
+
+    if let Err(e) = &result {
+        events::emit(Event::Error {
+            message: e.to_string(),
+        });
+    }
+    events::emit(Event::Shutdown);
+    shutdown::flush();
+
+    result.and(saved).and(profiled)
+}
+
+fn run(
+    mut target: Target,
+    deployments: DeploymentSet,
+    user: Option<GlobalUser>,
+    mut server_config: ServerConfig,
+    local_protocol: Protocol,
+    upstream_protocol: Protocol,
+    verbose: bool,
+) -> Result<()> {
+    if server_config.options.inspect_brk {
+        // the preview service starts running a Worker as soon as it is uploaded
+        // so there is nothing for a debugger to pause, only the local runtime can
+        StdErr::warn(&format!(
+            "{} is only supported by the local runtime and will be ignored",
+            styles::highlight("--inspect-brk")
+        ));
+    }
+
+    if server_config.options.warn_mixed_content && local_protocol.is_http() {
+        StdErr::warn(&format!(
+            "{} only looks at pages served with {}",
+            styles::highlight("--warn-mixed-content"),
+            styles::highlight("--local-protocol https")
+        ));
+    }
+
+    if server_config.options.dual_listen && local_protocol.is_https() {
+        anyhow::bail!(
+            "{} serves https alongside http, so it needs {}",
+            styles::highlight("--dual-listen"),
+            styles::highlight("--local-protocol http")
+        )
+    }
+
+    // echoing requests needs no Worker, so there's nothing to check or build
+    if server_config.options.echo {
+        export_cert(&server_config, local_protocol)?;
+        return echo::dev(server_config, local_protocol);
+    }
+
+    // catch missing credentials before doing any work,
+    // rather than when the preview upload fails
+    preflight(&target, user.as_ref(), &server_config)?;
+    // and an unusable --trust-upstream-cert before uploading anything
+    upstream::connector(&server_config.options)?;
+    export_cert(&server_config, local_protocol)?;
+
+    // before serving requests we must first build the Worker,
+    // unless a deployed version, a prebuilt script or a given preview is being served instead
+    // a script piped in is kept in a temporary file until the session ends
+    let stdin_script;
+    let mut built_in = None;
+    if let Some(script) = &server_config.options.script {
+        let description = if script == Path::new("-") {
+            stdin_script = read_stdin_script()?;
+            use_script(&mut target, stdin_script.path())?;
+            "the script piped to stdin".to_string()
+        } else {
+            use_script(&mut target, script)?;
+            styles::highlight(script.display().to_string())
+        };
+        server_config.options.banner(&format!(
+            "{} Serving {} directly, without building your project",
+            emoji::INFO,
+            description
+        ));
+    } else if let Some(preview_token) = &server_config.options.preview_token {
+        server_config.options.banner(&format!(
+            "{} Serving the preview {} given with {}, without building or uploading your project",
+            emoji::INFO,
+            styles::highlight(preview_token),
+            styles::highlight("--preview-token")
+        ));
+        tui::set_preview(preview_token);
+    } else if server_config.options.preview_version.is_none() {
+        let building = Instant::now();
+        build_target(&target)?;
+        built_in = Some(building.elapsed());
+    }
 
     let deploy_target = {
         let valid_targets = deployments
@@ -53,6 +290,68 @@ pub fn dev(
         }
     };
 
+    server_config.routes = Routes::new(&deploy_target)?;
+    if server_config.options.local_static {
+        match &target.site {
+            Some(site) => server_config.static_root = Some(site.bucket.clone()),
+            None => StdErr::warn(&format!(
+                "{} only serves files from a Workers Sites bucket, and your project doesn't have one",
+                styles::highlight("--local-static")
+            )),
+        }
+    }
+    if let Some(route_filter) = &server_config.options.route_filter {
+        server_config.route_filter = Some(route_filter::RouteFilter::load(route_filter)?);
+    }
+    // a deployed version or a preview from --preview-token is never rebuilt,
+    // so there's nothing to watch
+    if server_config.options.preview_version.is_none()
+        && server_config.options.preview_token.is_none()
+    {
+        let options = &server_config.options;
+        let mut scope = WatchScope::new(&options.watch_paths, &options.ignore_paths)?;
+        if let Some(script) = &options.script {
+            scope = scope.always_watch(script);
+        }
+        // with --reload-on-config-change, wrangler.toml is watched on its own
+        if let Some(config) = config_reload::watched() {
+            scope = scope.never_watch(config);
+            config_reload::start()?;
+        }
+        if verbose {
+            StdErr::info(scope.describe());
+        }
+        server_config.watch_scope = scope;
+    } else if server_config.options.reload_on_config_change {
+        StdErr::warn(&format!(
+            "{} has nothing to reload when serving a preview that isn't rebuilt",
+            styles::highlight("--reload-on-config-change")
+        ));
+    }
+    if server_config.options.print_routes {
+        print_routes(&server_config.routes, &target);
+    }
+
+    let internal_prefix = server_config.options.internal_prefix();
+    server_config.options.banner(&format!(
+        "{} Requests under {} are reserved for wrangler and will not reach your Worker",
+        emoji::INFO,
+        styles::highlight(internal_prefix)
+    ));
+    if let DeployTarget::Zoned(zoned) = &deploy_target {
+        for route in &zoned.routes {
+            let route_path = route.pattern.find('/').map(|i| &route.pattern[i..]);
+            if route_path.map_or(false, |path| path.starts_with(internal_prefix)) {
+                StdErr::warn(&format!(
+                    "Your route {} overlaps with {}, choose another prefix with {} to reach your Worker there",
+                    route.pattern,
+                    internal_prefix,
+                    styles::highlight("--internal-prefix")
+                ));
+            }
+        }
+    }
+
     let host_str = styles::highlight("--host");
     let local_str = styles::highlight("--local-protocol");
     let upstream_str = styles::highlight("--upstream-protocol");
@@ -65,11 +364,39 @@ pub fn dev(
         )
     } else if local_protocol.is_https() && upstream_protocol.is_http() {
         anyhow::bail!("{} cannot be https if {} is http", local_str, upstream_str)
+    } else if server_config.options.dual_listen && upstream_protocol.is_http() {
+        anyhow::bail!(
+            "{} cannot serve https if {} is http",
+            styles::highlight("--dual-listen"),
+            upstream_str
+        )
+    }
+
+    if server_config.options.banner_info || verbose {
+        print_banner_info(&target, &server_config.options, built_in);
     }
 
     if let Some(user) = user {
         if server_config.host.is_default() {
             // Authenticated and no host provided, run on edge with user's zone
+            if server_config.options.emulate_cf_headers {
+                StdErr::warn(&format!(
+                    "{} has no effect in authenticated sessions, as responses come through Cloudflare's edge",
+                    styles::highlight("--emulate-cf-headers")
+                ));
+            }
+            if server_config.options.variant_param.is_some() {
+                StdErr::warn(&format!(
+                    "{} has no effect in authenticated sessions, as its variants are previews on the preview service",
+                    styles::highlight("--variant-param")
+                ));
+            }
+            if server_config.options.reuse_preview {
+                StdErr::warn(&format!(
+                    "{} has no effect in authenticated sessions, as previews on the edge last only as long as their session",
+                    styles::highlight("--reuse-preview")
+                ));
+            }
             return edge::dev(
                 target,
                 user,
@@ -82,7 +409,7 @@ pub fn dev(
         }
 
         // If user is authenticated but host is provided, use gcs with given host
-        StdOut::warn(
+        StdErr::warn(
             format!(
                 "{} provided, will run unauthenticated and upstream to provided host",
                 host_str
@@ -91,9 +418,144 @@ pub fn dev(
         );
     }
 
-    if target.durable_objects.is_some() {
-        anyhow::bail!("wrangler dev does not yet support unauthenticated sessions when using Durable Objects. Please run wrangler login or wrangler config first.")
+    gcs::dev(target, server_config, local_protocol, verbose)
+}
+
+fn print_routes(routes: &Routes, target: &Target) {
+    if routes.is_empty() {
+        StdErr::info(&format!(
+            "No routes are configured, every request is served by the workers.dev preview of {}",
+            styles::highlight(&target.name)
+        ));
+        return;
+    }
+
+    let mut message = String::from("Routes served by this session:");
+    for pattern in routes.patterns() {
+        message.push_str(&format!(
+            "\n  {} -> preview of {}",
+            styles::url(pattern),
+            styles::highlight(&target.name)
+        ));
     }
+    message.push_str("\n  anything else -> default");
+    StdErr::info(&message);
+}
 
-    gcs::dev(target, server_config, local_protocol, verbose)
+/// `--banner-info` describes what is being served, so a pasted session log says
+fn print_banner_info(target: &Target, options: &DevOptions, built_in: Option<Duration>) {
+    let mut info = vec![format!("wrangler {}", env!("CARGO_PKG_VERSION"))];
+    // nothing local is served for a deployed version or a preview from --preview-token
+    if options.preview_version.is_none() && options.preview_token.is_none() {
+        match bundle::size(target).and_then(|size| Ok((size, bundle::hash(target)?))) {
+            Ok((size, hash)) => {
+                let size = match NumberPrefix::binary(size as f64) {
+                    NumberPrefix::Standalone(bytes) => format!("{} bytes", bytes),
+                    NumberPrefix::Prefixed(prefix, n) => format!("{:.1} {}B", n, prefix),
+                };
+                info.push(format!("bundle of {} with hash {}", size, hash));
+            }
+            Err(e) => log::debug!("Could not measure the bundle: {}", e),
+        }
+    }
+    if let Some(built_in) = built_in {
+        info.push(format!("built in {}ms", built_in.as_millis()));
+    }
+    options.banner(&format!("{} {}", emoji::INFO, info.join(", ")));
+}
+
+/// `--script` serves a prebuilt file as a plain JavaScript Worker,
+/// so it is uploaded as it is and re-uploaded whenever it changes
+fn use_script(target: &mut Target, script: &Path) -> Result<()> {
+    let script_str = styles::highlight(script.display().to_string());
+    let metadata = match fs::metadata(script) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => anyhow::bail!("{} given to --script is not a file", script_str),
+    };
+    if metadata.len() == 0 {
+        anyhow::bail!("{} given to --script is empty", script_str)
+    }
+
+    target.target_type = TargetType::JavaScript;
+    target.build = None;
+    target.script_path = Some(script.to_path_buf());
+    Ok(())
+}
+
+/// `--script -` reads the script from stdin, for tools that write a bundle to stdout
+fn read_stdin_script() -> Result<NamedTempFile> {
+    if atty::is(atty::Stream::Stdin) {
+        anyhow::bail!(
+            "{} reads the Worker from stdin, but nothing was piped to it. Try `my-bundler | wrangler dev --script -`",
+            styles::highlight("--script -")
+        )
+    }
+
+    let mut script = Vec::new();
+    io::stdin().read_to_end(&mut script)?;
+    if script.is_empty() {
+        anyhow::bail!(
+            "The script piped to {} is empty",
+            styles::highlight("--script -")
+        )
+    }
+
+    let mut file = tempfile::Builder::new()
+        .prefix("wrangler-dev-")
+        .suffix(".js")
+        .tempfile()?;
+    file.write_all(&script)?;
+    file.flush()?;
+    Ok(file)
+}
+
+/// writes the certificate to `--export-cert`, generating it first if need be
+fn export_cert(server_config: &ServerConfig, local_protocol: Protocol) -> Result<()> {
+    if let Some(export_cert) = &server_config.options.export_cert {
+        if local_protocol.is_http() {
+            StdErr::warn(&format!(
+                "The certificate is only used with {}",
+                styles::highlight("--local-protocol https")
+            ));
+        }
+        tls::generate_cert(server_config.options.cert_validity_days)?;
+        tls::export_cert(export_cert)?;
+    }
+    Ok(())
+}
+
+/// make sure the session we are about to start has the credentials it needs,
+/// pointing the user at how to provide them if it does not
+fn preflight(
+    target: &Target,
+    user: Option<&GlobalUser>,
+    server_config: &ServerConfig,
+) -> Result<()> {
+    let login_str = styles::highlight("wrangler login");
+    let config_str = styles::highlight("wrangler config");
+
+    let authenticated = user.is_some() && server_config.host.is_default();
+    if authenticated {
+        if let Err(e) = target.account_id.load() {
+            anyhow::bail!(
+                "Could not determine which account to preview your Worker on: {}\nPlease add your {} to {}, you can find it by running {}",
+                e,
+                styles::highlight("account_id"),
+                styles::highlight("wrangler.toml"),
+                styles::highlight("wrangler whoami")
+            )
+        }
+    } else if server_config.options.preview_version.is_some() {
+        anyhow::bail!(
+            "{} can only fetch deployed versions in an authenticated session without {}. Please run {} or {} first.",
+            styles::highlight("--preview-version"),
+            styles::highlight("--host"),
+            login_str,
+            config_str
+        )
+    } else if target.durable_objects.is_some() {
+        anyhow::bail!("wrangler dev does not yet support unauthenticated sessions when using Durable Objects. Please run {} or {} first.", login_str, config_str)
+    }
+
+    Ok(())
 }
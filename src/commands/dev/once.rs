@@ -0,0 +1,75 @@
+//! `--once` ends the dev session after the first response from the Worker,
+//! and `--expect-status` fails the session if that response had a different status,
+//! so `wrangler dev --once --expect-status 200` works as a smoke test in CI
+use crate::commands::dev::response_size;
+
+use anyhow::Result;
+use hyper::{Body, Response, StatusCode};
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::sync::Notify;
+
+static STATUS: OnceCell<StatusCode> = OnceCell::new();
+static SERVED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// records the status of the response, and lets the session end once its body is sent
+pub(super) fn watch(resp: Response<Body>) -> Response<Body> {
+    let (parts, body) = resp.into_parts();
+    let (sender, watched) = Body::channel();
+
+    let status = parts.status;
+    tokio::spawn(async move {
+        response_size::relay(body, sender).await;
+        // only the first response counts, even if others were in flight
+        if STATUS.set(status).is_ok() {
+            SERVED.notify_one();
+        }
+    });
+
+    Response::from_parts(parts, watched)
+}
+
+/// resolves once the first response has been sent
+pub(super) async fn served() {
+    SERVED.notified().await;
+    // give the server a moment to flush the end of the response
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+}
+
+/// fails unless the response served with `--once` had the expected status
+pub(super) fn check(expected: StatusCode) -> Result<()> {
+    match STATUS.get() {
+        Some(status) => compare(expected, *status),
+        None => anyhow::bail!(
+            "Expected a response with status {}, but no request was served",
+            expected
+        ),
+    }
+}
+
+fn compare(expected: StatusCode, status: StatusCode) -> Result<()> {
+    if expected == status {
+        Ok(())
+    } else {
+        anyhow::bail!(
+            "The response did not have the expected status\n- {}\n+ {}",
+            expected,
+            status
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatched_statuses_are_shown_as_a_diff() {
+        assert!(compare(StatusCode::OK, StatusCode::OK).is_ok());
+
+        let e = compare(StatusCode::OK, StatusCode::NOT_FOUND).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "The response did not have the expected status\n- 200 OK\n+ 404 Not Found"
+        );
+    }
+}
@@ -0,0 +1,641 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Method, StatusCode};
+use rustls::{CipherSuite, ProtocolVersion};
+use serde_json::{Map, Value};
+use structopt::StructOpt;
+use url::Url;
+
+use crate::settings::toml::parse_preview_host;
+
+use super::access_log::LogFormat;
+use super::fail_path::{self, FailPath};
+use super::favicon::{self, Favicon};
+use super::local_static::{self, MimeOverride};
+use super::log_status::StatusFilter;
+use super::request_body::ChunkedBodies;
+use super::response_headers::OversizedHeaders;
+use super::variant::Variant;
+use super::verbosity::Verbosity;
+use super::{
+    allowed_methods, base_path, cf, internal, log_sink, replace, strict_host, tls, upstream,
+};
+
+const DEFAULT_MAX_HEADER_SIZE: usize = 16 * 1024;
+const DEFAULT_MAX_RESPONSE_HEADER_SIZE: usize = 64 * 1024;
+const DEFAULT_BODY_READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Flags for `wrangler dev` that tune how the dev session behaves,
+/// on top of the host/ip/port/protocol settings that make up a `ServerConfig`
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct DevOptions {
+    /// Write newline-delimited JSON events describing the dev session to this
+    /// path (a Unix socket, FIFO, or file) for editor integrations
+    #[structopt(name = "events-socket", long)]
+    pub events_socket: Option<PathBuf>,
+
+    /// Wait for a debugger to attach before serving the first request.
+    /// Only supported by the local runtime, ignored otherwise
+    #[structopt(name = "inspect-brk", long)]
+    pub inspect_brk: bool,
+
+    /// Record every request and response to this file in HAR format
+    /// when the dev session ends
+    #[structopt(long)]
+    pub har: Option<PathBuf>,
+
+    /// Measure how long wrangler dev spends on each request itself, apart from waiting on
+    /// the preview service, and write it to this file as folded stacks for a flame graph
+    /// when the dev session ends
+    #[structopt(long, value_name = "file", parse(from_os_str))]
+    pub self_profile: Option<PathBuf>,
+
+    /// Log each preview requests are sent to, and when and why it changed, to stderr
+    /// or this file, for debugging previews that change or expire unexpectedly
+    #[structopt(long, value_name = "file", parse(from_os_str))]
+    pub record_preview_id: Option<Option<PathBuf>>,
+
+    /// Compare every response with the one recorded for the same request in
+    /// this HAR file, written earlier with --har, and log any differences.
+    /// Dates and ids that change between runs are ignored
+    #[structopt(long)]
+    pub diff: Option<PathBuf>,
+
+    /// With --diff, end the dev session with an error if any response differed
+    #[structopt(name = "diff-fail", long, requires = "diff")]
+    pub diff_fail: bool,
+
+    /// Strip this path prefix from requests before sending them to the Worker, for
+    /// Workers served under a prefix in production. Requests outside it get a 404
+    #[structopt(name = "base-path", long, value_name = "prefix", parse(try_from_str = base_path::parse))]
+    pub base_path: Option<String>,
+
+    /// With --base-path, send requests outside the prefix to the Worker unchanged
+    /// rather than answering them with a 404
+    #[structopt(name = "base-path-passthrough", long, requires = "base-path")]
+    pub base_path_passthrough: bool,
+
+    /// Answer requests for paths matching this glob with this status, without
+    /// sending them to the Worker, given as pattern=status. Can be repeated
+    #[structopt(name = "fail-path", long, value_name = "pattern=status", number_of_values = 1, parse(try_from_str = fail_path::parse))]
+    pub fail_path: Vec<FailPath>,
+
+    /// Path prefix reserved for wrangler's own endpoints, defaults to /__wrangler
+    #[structopt(name = "internal-prefix", long, parse(try_from_str = internal::parse_prefix))]
+    pub internal_prefix: Option<String>,
+
+    /// Largest total size of request headers to accept, in bytes, defaults to 16384.
+    /// Requests with larger headers are rejected with a 431
+    #[structopt(name = "max-header-size", long)]
+    pub max_header_size: Option<usize>,
+
+    /// Preview this already deployed version of the Worker instead of building
+    /// your local code. Requires an authenticated session, and only previews
+    /// service workers without Wasm module or text blob bindings
+    #[structopt(name = "preview-version", long)]
+    pub preview_version: Option<String>,
+
+    /// Serve the preview with this id, obtained some other way, instead of building and
+    /// uploading your Worker. Its console output is not shown
+    #[structopt(
+        long,
+        value_name = "id",
+        parse(try_from_str = parse_preview_token),
+        conflicts_with_all = &["preview-version", "script"]
+    )]
+    pub preview_token: Option<String>,
+
+    /// Serve the previews given with --variant to requests with this query parameter,
+    /// for trying variants of your Worker side by side. Requests without it are served
+    /// by the preview for your local code
+    #[structopt(long, value_name = "name")]
+    pub variant_param: Option<String>,
+
+    /// The preview with id ID, obtained some other way, to serve to requests with
+    /// --variant-param set to NAME, given as NAME=ID. Can be repeated
+    #[structopt(
+        long,
+        value_name = "NAME=ID",
+        number_of_values = 1,
+        requires = "variant-param"
+    )]
+    pub variant: Vec<Variant>,
+
+    /// Send requests to the preview service on this host, optionally with a port,
+    /// instead of the default. Overrides preview_host in wrangler.toml
+    #[structopt(
+        name = "preview-host",
+        long,
+        value_name = "host",
+        parse(try_from_str = parse_preview_host)
+    )]
+    pub preview_host: Option<String>,
+
+    /// List the configured routes, and the preview each is served by, at startup
+    #[structopt(name = "print-routes", long)]
+    pub print_routes: bool,
+
+    /// Hold every response for this many milliseconds before returning it,
+    /// to simulate a Worker that spends that long on CPU
+    #[structopt(name = "simulate-cpu", long, value_name = "ms")]
+    pub simulate_cpu: Option<u64>,
+
+    /// Hold back the headers of every response for this many milliseconds, then stream
+    /// the body as it arrives, to simulate a slow time to first byte
+    #[structopt(long, value_name = "ms")]
+    pub chaos_ttfb: Option<u64>,
+
+    /// Hold back every response for this many milliseconds and send it all at once,
+    /// to simulate a slow network. Takes precedence over a shorter --chaos-ttfb
+    #[structopt(long, value_name = "ms")]
+    pub chaos_latency: Option<u64>,
+
+    /// Serve files in your Workers Sites bucket straight from disk,
+    /// sending only requests for other paths to the Worker
+    #[structopt(name = "local-static", long)]
+    pub local_static: bool,
+
+    /// Serve files with this extension with this Content-Type under --local-static,
+    /// given as ext=type. Can be repeated
+    #[structopt(long, value_name = "ext=type", number_of_values = 1, parse(try_from_str = local_static::parse_mime))]
+    pub mime: Vec<MimeOverride>,
+
+    /// Send files served by --local-static with Cache-Control: max-age=<secs>,
+    /// instead of no-cache
+    #[structopt(name = "static-max-age", long, value_name = "secs")]
+    pub static_max_age: Option<u64>,
+
+    /// Leave out the decorative startup output and the summary at shutdown. Request logs and your Worker's
+    /// console output go to stdout, everything else wrangler dev prints goes to stderr
+    #[structopt(name = "no-banner", long)]
+    pub no_banner: bool,
+
+    /// Print your Worker's console output as plain lines among the request log,
+    /// tagged with the ids of the requests in flight when it was logged
+    #[structopt(long)]
+    pub show_logs: bool,
+
+    /// Add the wrangler version and the size, hash and build time of your Worker's bundle
+    /// to the startup output, for pasting into bug reports. Implied by --verbose
+    #[structopt(long)]
+    pub banner_info: bool,
+
+    /// Write each part of every upload to the preview service to this directory: the
+    /// script or modules, metadata.json with the bindings, and any Wasm modules and text blobs
+    #[structopt(long, value_name = "dir")]
+    pub dump_bundle: Option<PathBuf>,
+
+    /// Send only one of several identical GET requests made at the same time
+    /// to the preview service, and answer them all with its response
+    #[structopt(long)]
+    pub coalesce: bool,
+
+    /// Send this User-Agent to the preview service in place of the client's,
+    /// or none at all if empty. Requests without one are sent with wrangler's own
+    #[structopt(name = "user-agent", long, parse(try_from_str = HeaderValue::from_str))]
+    pub user_agent: Option<HeaderValue>,
+
+    /// Seconds a client has to send the whole request body, defaults to 120.
+    /// Requests whose bodies take longer are rejected with a 408
+    #[structopt(name = "body-read-timeout", long, value_name = "secs")]
+    pub body_read_timeout: Option<u64>,
+
+    /// Seconds to wait for a connection to the preview service, including the TLS
+    /// handshake. Requests that can't connect in time get a 504
+    #[structopt(long, value_name = "secs")]
+    pub connect_timeout: Option<u64>,
+
+    /// Seconds to wait for the preview service to start responding, and then for
+    /// each chunk of the response body. Requests that don't get a response in time get a 504
+    #[structopt(long, value_name = "secs")]
+    pub read_timeout: Option<u64>,
+
+    /// Seconds a Server-Sent Events stream may go without an event before a `: ping` comment
+    /// is sent on it, so proxies and NATs that close idle connections leave it open. Off by default
+    #[structopt(long, value_name = "secs")]
+    pub keepalive_interval: Option<u64>,
+
+    /// Seconds a response body may take to stream to the client in all, before it's cut
+    /// off, so a response that never ends doesn't hold its connection. Off by default
+    #[structopt(long, value_name = "secs")]
+    pub max_response_time: Option<u64>,
+
+    /// Connect to `ip` whenever the preview service would look `host` up in DNS,
+    /// given as host:ip. TLS still uses the real hostname. Can be repeated
+    #[structopt(long, value_name = "host:ip", number_of_values = 1, parse(try_from_str = upstream::parse_resolve))]
+    pub resolve: Vec<(String, IpAddr)>,
+
+    /// Send HEAD requests to the preview service as GETs, and drop the body
+    /// from the response, for when a Worker doesn't handle HEAD itself
+    #[structopt(name = "head-as-get", long)]
+    pub head_as_get: bool,
+
+    /// Start a W3C trace context for requests that don't carry one, sending new
+    /// traceparent and tracestate headers upstream and logging the trace id
+    #[structopt(long)]
+    pub trace_context: bool,
+
+    /// Answer requests for <internal-prefix>/echo-request/<path> with the method, URL
+    /// and headers a request for <path> would be sent upstream with, without sending it
+    #[structopt(long)]
+    pub echo_request: bool,
+
+    /// Reuse the last preview uploaded by wrangler dev when the built Worker hasn't
+    /// changed, rather than uploading it again. Only applies to unauthenticated sessions
+    #[structopt(name = "reuse-preview", long)]
+    pub reuse_preview: bool,
+
+    /// Times to retry the first upload of your Worker when the Cloudflare API
+    /// rate limits it, defaults to 5
+    #[structopt(long, value_name = "n")]
+    pub upload_retries: Option<u32>,
+
+    /// Seconds to spend in all waiting out rate limits on the first upload of
+    /// your Worker, defaults to 60
+    #[structopt(long, value_name = "secs")]
+    pub upload_max_wait: Option<u64>,
+
+    /// Answer CORS preflight requests without sending them to the Worker, and add
+    /// Access-Control-Allow-Origin to responses to cross-origin requests
+    #[structopt(long)]
+    pub cors: bool,
+
+    /// The Access-Control-Allow-Origin sent with --cors, defaults to the request's origin
+    #[structopt(name = "cors-allow-origin", long, parse(try_from_str = HeaderValue::from_str))]
+    pub cors_allow_origin: Option<HeaderValue>,
+
+    /// The Access-Control-Allow-Methods sent with --cors, defaults to the requested method
+    #[structopt(name = "cors-allow-methods", long, parse(try_from_str = HeaderValue::from_str))]
+    pub cors_allow_methods: Option<HeaderValue>,
+
+    /// The Access-Control-Allow-Headers sent with --cors, defaults to the requested headers
+    #[structopt(name = "cors-allow-headers", long, parse(try_from_str = HeaderValue::from_str))]
+    pub cors_allow_headers: Option<HeaderValue>,
+
+    /// Warn about any response with a body larger than this many bytes
+    #[structopt(name = "warn-response-size", long, value_name = "bytes")]
+    pub warn_response_size: Option<u64>,
+
+    /// Replace FROM with TO in the body of text responses, given as FROM=TO.
+    /// Can be repeated, and replacements are made in the order given
+    #[structopt(long, value_name = "FROM=TO", number_of_values = 1, parse(try_from_str = replace::parse))]
+    pub replace: Vec<(String, String)>,
+
+    /// Stop after the first response from the Worker has been sent
+    #[structopt(long)]
+    pub once: bool,
+
+    /// With --once, exit with an error unless the response has this status,
+    /// to use wrangler dev as a smoke test
+    #[structopt(name = "expect-status", long, value_name = "code", requires = "once")]
+    pub expect_status: Option<StatusCode>,
+
+    /// Upload this prebuilt JavaScript file as the Worker, skipping the project's build.
+    /// Pass - to read it from stdin. Vars and bindings still come from wrangler.toml
+    #[structopt(
+        long,
+        value_name = "path",
+        parse(from_os_str),
+        conflicts_with = "preview-version"
+    )]
+    pub script: Option<PathBuf>,
+
+    /// Also trust this PEM certificate when validating the preview service's certificate
+    #[structopt(
+        name = "trust-upstream-cert",
+        long,
+        value_name = "path",
+        parse(from_os_str)
+    )]
+    pub trust_upstream_cert: Option<PathBuf>,
+
+    /// Trust only the certificate given by --trust-upstream-cert, not the system's roots
+    #[structopt(name = "upstream-roots-only", long, requires = "trust-upstream-cert")]
+    pub upstream_roots_only: bool,
+
+    /// DANGEROUS: don't check the preview service's certificate at all, for a private
+    /// preview host with a self-signed certificate. Anyone between you and it can read
+    /// and change your requests. Prefer --trust-upstream-cert
+    #[structopt(long)]
+    pub accept_invalid_upstream_certs: bool,
+
+    /// Only send requests allowed by the include and exclude patterns in this file
+    /// to the preview service, and answer the rest locally
+    #[structopt(name = "route-filter", long, value_name = "file", parse(from_os_str))]
+    pub route_filter: Option<PathBuf>,
+
+    /// While a change is being uploaded, answer requests with a 503 and a page
+    /// saying so, rather than holding them until the new preview is ready
+    #[structopt(name = "rebuild-placeholder", long)]
+    pub rebuild_placeholder: bool,
+
+    /// Watch wrangler.toml apart from your source, and upload the Worker again with
+    /// its new vars and bindings when it changes. Routes and [dev] still need a restart
+    #[structopt(name = "reload-on-config-change", long)]
+    pub reload_on_config_change: bool,
+
+    /// Diagnostic mode: answer every request with a JSON description of it, without
+    /// building, uploading or running your Worker, to check the local server works
+    #[structopt(long)]
+    pub echo: bool,
+
+    /// Answer requests with any other method with a 405, given as a comma separated list
+    /// like GET,POST. All methods are allowed by default
+    #[structopt(name = "allowed-methods", long, value_name = "methods", use_delimiter = true, parse(try_from_str = allowed_methods::parse))]
+    pub allowed_methods: Vec<Method>,
+
+    /// Echo TRACE requests back, rather than answering them with a 405 as production
+    /// servers usually do. They never reach the Worker either way
+    #[structopt(long)]
+    pub allow_trace: bool,
+
+    /// Answer requests for hosts other than the upstream host, dev's own address and those
+    /// given with --allowed-host with a 421, rather than sending them to the Worker
+    #[structopt(long)]
+    pub strict_host: bool,
+
+    /// A host, with an optional port, that --strict-host accepts requests for. Can be repeated
+    #[structopt(
+        long,
+        value_name = "host",
+        number_of_values = 1,
+        requires = "strict-host",
+        parse(try_from_str = strict_host::parse_allowed_host)
+    )]
+    pub allowed_host: Vec<String>,
+
+    /// Answer with JSON like {"error":"gateway_timeout","detail":"..."} when wrangler dev answers
+    /// a request with an error itself, rather than with plain text. Responses from your Worker are
+    /// passed on as they are
+    #[structopt(long)]
+    pub json_errors: bool,
+
+    /// Remove this header from every response before it's passed on, like X-Powered-By,
+    /// matched case-insensitively. Can be repeated
+    #[structopt(long, value_name = "name", number_of_values = 1)]
+    pub strip_response_header: Vec<HeaderName>,
+
+    /// Write the certificate used for --local-protocol https to this path as PEM,
+    /// to add it to your system or browser's trust store
+    #[structopt(name = "export-cert", long, value_name = "path", parse(from_os_str))]
+    pub export_cert: Option<PathBuf>,
+
+    /// Rewrite absolute URLs to the origin FROM into URLs to the origin TO in HTML and
+    /// JSON responses, given as FROM=TO like https://example.com=http://localhost:8787.
+    /// Bodies are matched as plain text. Can be repeated
+    #[structopt(name = "rewrite-host", long, value_name = "FROM=TO", number_of_values = 1, parse(try_from_str = replace::parse_rewrite_host))]
+    pub rewrite_host: Vec<(String, String)>,
+
+    /// Warn about http:// scripts, stylesheets, images and other subresources in HTML
+    /// served over https, which browsers block. Responses are not changed
+    #[structopt(long)]
+    pub warn_mixed_content: bool,
+
+    /// Gzip text responses for clients that accept it, as Cloudflare does in production,
+    /// unless the Worker already encoded them or sent Cache-Control: no-transform
+    #[structopt(long)]
+    pub compress: bool,
+
+    /// Properties of request.cf for dev requests to appear to have, as a JSON object like
+    /// '{"country": "US", "colo": "SFO"}'. They are sent in the x-wrangler-dev-cf header,
+    /// as the preview service doesn't let request.cf be overridden
+    #[structopt(long, value_name = "json", parse(try_from_str = cf::parse))]
+    pub cf: Option<Map<String, Value>>,
+
+    /// Shortcut for the country property of --cf
+    #[structopt(long, value_name = "code", parse(try_from_str = cf::parse_country))]
+    pub cf_country: Option<String>,
+
+    /// Shortcut for the colo property of --cf
+    #[structopt(long, value_name = "colo", parse(try_from_str = cf::parse_colo))]
+    pub cf_colo: Option<String>,
+
+    /// Show more of each request: -v its headers and those of its response, -vv the start
+    /// of their text bodies too, and -vvv TLS handshakes as well, like --verbose-tls
+    #[structopt(short = "v", parse(from_occurrences = Verbosity::from_occurrences))]
+    pub verbosity: Verbosity,
+
+    /// Describe each TLS handshake with the local https server: what the client offered, what
+    /// was negotiated and why a failed handshake was rejected
+    #[structopt(long)]
+    pub verbose_tls: bool,
+
+    /// The oldest TLS version the https server accepts, 1.2 or 1.3. Both are accepted by default
+    #[structopt(long, value_name = "version", parse(try_from_str = tls::parse_version))]
+    pub tls_min_version: Option<ProtocolVersion>,
+
+    /// The newest TLS version the https server accepts, 1.2 or 1.3
+    #[structopt(long, value_name = "version", parse(try_from_str = tls::parse_version))]
+    pub tls_max_version: Option<ProtocolVersion>,
+
+    /// Only negotiate these cipher suites, given as a comma separated list of names like
+    /// TLS13_AES_128_GCM_SHA256. TLS 1.2 suites must suit the certificate's key
+    #[structopt(long, value_name = "suites", use_delimiter = true, parse(try_from_str = tls::parse_cipher_suite))]
+    pub tls_cipher_suites: Vec<CipherSuite>,
+
+    /// The most requests to send to the preview service at once, with the rest waiting
+    /// their turn. Unlimited by default
+    #[structopt(long, value_name = "requests")]
+    pub upstream_concurrency: Option<usize>,
+
+    /// Answer requests that have waited this long for --upstream-concurrency
+    /// with a 503, rather than waiting as long as it takes
+    #[structopt(long, value_name = "ms", requires = "upstream-concurrency")]
+    pub upstream_queue_timeout: Option<u64>,
+
+    /// Note the address of the preview service each request was sent to in the request log
+    #[structopt(long)]
+    pub log_upstream_ip: bool,
+
+    /// Add a Server-Timing header to responses with how long the preview service took to
+    /// respond, which browsers show in their network panel
+    #[structopt(long)]
+    pub server_timing: bool,
+
+    /// Upper bounds in milliseconds of the latency histogram on the status endpoint,
+    /// given as a comma separated list like 1,10,100,1000
+    #[structopt(long, value_name = "ms", use_delimiter = true)]
+    pub latency_buckets: Vec<f64>,
+
+    /// Keep the Host header each request was sent with in x-wrangler-dev-original-host, and
+    /// warn that Host is rewritten, which the preview service needs to route requests
+    #[structopt(long)]
+    pub no_preview_header_overwrite: bool,
+
+    /// Show a live dashboard of requests instead of logging them, when stdout is a terminal
+    #[structopt(long)]
+    pub tui: bool,
+
+    /// Log requests as usual, even if --tui is also given
+    #[structopt(long)]
+    pub no_tui: bool,
+
+    /// How many days a newly generated certificate for the https server is valid for,
+    /// up to 825. Defaults to 365
+    #[structopt(long, value_name = "days", parse(try_from_str = tls::parse_validity_days))]
+    pub cert_validity_days: Option<u32>,
+
+    /// Also send each request log line as JSON to this http(s) URL, which is POSTed to,
+    /// or syslog://host:port. Delivery is best effort and never slows down requests
+    #[structopt(long, value_name = "url", parse(try_from_str = log_sink::parse))]
+    pub log_sink: Option<Url>,
+
+    /// Send every response with Cache-Control: no-store, so the browser never shows
+    /// a response cached from before the Worker was changed
+    #[structopt(long)]
+    pub no_cache_responses: bool,
+
+    /// How to send request bodies of unknown length upstream: stream them as they arrive,
+    /// or buffer them and send them with a Content-Length
+    #[structopt(long, value_name = "stream|buffer", default_value = "stream")]
+    pub chunked_request_bodies: ChunkedBodies,
+
+    /// How to print the request log: pretty, or common or combined for the Common and
+    /// Combined Log Formats that log analysers like GoAccess read
+    #[structopt(long, value_name = "pretty|common|combined", default_value = "pretty")]
+    pub log_format: LogFormat,
+
+    /// Only print the request log lines of responses with these statuses, given as a comma
+    /// separated list of codes and classes like 4xx,5xx or 500,404
+    #[structopt(long, value_name = "spec")]
+    pub log_status: Option<StatusFilter>,
+
+    /// Don't print the request log at all, whatever --log-status allows
+    #[structopt(long)]
+    pub quiet: bool,
+
+    /// Add the headers Cloudflare puts on responses in production, like cf-ray and
+    /// server: cloudflare, with made up values, unless the Worker set them itself
+    #[structopt(long)]
+    pub emulate_cf_headers: bool,
+
+    /// Read every request body in full before sending it upstream with a Content-Length,
+    /// rather than streaming it. Bodies are held in memory while they're read, so large
+    /// uploads are better streamed
+    #[structopt(long)]
+    pub buffer_request_body: bool,
+
+    /// Answer requests for /favicon.ico without sending them to the Worker: with this
+    /// icon file, or with none, a 204 that is left out of the request log
+    #[structopt(long, value_name = "path|none", parse(try_from_str = favicon::parse))]
+    pub favicon: Option<Favicon>,
+
+    /// Largest total size of response headers to pass on from the Worker, in bytes,
+    /// defaults to 65536. Catches headers too large for browsers to accept
+    #[structopt(long, value_name = "bytes")]
+    pub max_response_header_size: Option<usize>,
+
+    /// What to do with responses whose headers are over --max-response-header-size:
+    /// reject them with a 502, or truncate them by dropping their largest headers
+    #[structopt(long, value_name = "reject|truncate", default_value = "reject")]
+    pub oversized_response_headers: OversizedHeaders,
+
+    /// Only rebuild for changes to files matching these globs, given as a comma separated
+    /// list like src/**,lib/**. Replaces the default of everything not ignored
+    #[structopt(long, value_name = "globs", use_delimiter = true)]
+    pub watch_paths: Vec<String>,
+
+    /// Never rebuild for changes to files matching these globs, given as a comma separated list.
+    /// node_modules, dist, .git and anything in .gitignore are ignored by default
+    #[structopt(long, value_name = "globs", use_delimiter = true)]
+    pub ignore_paths: Vec<String>,
+
+    /// Serve over http and https at once: http on --port, and https on --https-port or
+    /// the port after it. Needs --local-protocol http and an https upstream
+    #[structopt(long)]
+    pub dual_listen: bool,
+
+    /// The port the https server listens on with --dual-listen
+    #[structopt(
+        name = "https-port",
+        long,
+        value_name = "port",
+        requires = "dual-listen"
+    )]
+    pub https_port: Option<u16>,
+}
+
+/// a preview id given to `--preview-token`, which is sent upstream in a header
+fn parse_preview_token(token: &str) -> Result<String> {
+    let token = token.trim();
+    if token.is_empty() {
+        anyhow::bail!("--preview-token can't be empty")
+    }
+    HeaderValue::from_str(token).map_err(|_| anyhow!("{} is not a valid preview id", token))?;
+    Ok(token.to_string())
+}
+
+impl DevOptions {
+    /// the path prefix under which requests are answered by wrangler, not the Worker
+    pub fn internal_prefix(&self) -> &str {
+        self.internal_prefix
+            .as_deref()
+            .unwrap_or(internal::DEFAULT_PREFIX)
+    }
+
+    /// the largest total size of request headers, in bytes, that is sent upstream
+    pub fn max_header_size(&self) -> usize {
+        self.max_header_size.unwrap_or(DEFAULT_MAX_HEADER_SIZE)
+    }
+
+    /// the largest total size of response headers, in bytes, that is passed on to the client
+    pub fn max_response_header_size(&self) -> usize {
+        self.max_response_header_size
+            .unwrap_or(DEFAULT_MAX_RESPONSE_HEADER_SIZE)
+    }
+
+    /// how long a client has to send the whole request body
+    pub fn body_read_timeout(&self) -> Duration {
+        self.body_read_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_BODY_READ_TIMEOUT)
+    }
+
+    /// how long connecting to the preview service may take, if limited
+    pub(super) fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout.map(Duration::from_secs)
+    }
+
+    /// how long the preview service may take to respond, if limited
+    pub(super) fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout.map(Duration::from_secs)
+    }
+
+    /// how long a response body may take to stream, if limited
+    pub(super) fn max_response_time(&self) -> Option<Duration> {
+        self.max_response_time.map(Duration::from_secs)
+    }
+
+    /// how long an event stream may be idle before it's pinged, if it is
+    pub(super) fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval.map(Duration::from_secs)
+    }
+
+    /// whether TLS handshakes are described, with --verbose-tls or -vvv
+    pub(super) fn traces_tls(&self) -> bool {
+        self.verbose_tls || self.verbosity >= Verbosity::Connections
+    }
+
+    /// print decorative startup and shutdown output, to stderr so it stays out of the request logs
+    pub(super) fn banner(&self, msg: &str) {
+        if !self.no_banner {
+            eprintln!("{}", msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_tokens_must_fit_in_a_header() {
+        assert_eq!(parse_preview_token(" abc123 ").unwrap(), "abc123");
+        assert!(parse_preview_token("").is_err());
+        assert!(parse_preview_token("abc\n123").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! The preview service routes requests by their Host header, so it is always
+//! rewritten to the upstream host on the way there. With
+//! `--no-preview-header-overwrite` the host the client sent is kept in a
+//! header of its own, for Workers that look at it, and the rewrite is
+//! pointed out the first time it happens
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
+
+use std::sync::Once;
+
+use hyper::header::{HeaderName, HeaderValue, HOST};
+use hyper::{Body, Request};
+
+pub(super) const ORIGINAL_HOST_HEADER: &str = "x-wrangler-dev-original-host";
+
+static WARNING: Once = Once::new();
+
+/// copies the host `req` was sent to into the sidecar header,
+/// replacing any the client sent itself
+pub(super) fn preserve(req: &mut Request<Body>, upstream_host: &str) {
+    let original = match req.headers().get(HOST) {
+        Some(host) => Some(host.clone()),
+        // HTTP/2 requests carry the host in the URI instead
+        None => req
+            .uri()
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok()),
+    };
+
+    let name = HeaderName::from_static(ORIGINAL_HOST_HEADER);
+    match original {
+        Some(original) => {
+            WARNING.call_once(|| {
+                StdErr::warn(&format!(
+                    "The Host header of requests is rewritten to {} so the preview service can route them. The host they were sent to is in the {} header",
+                    styles::highlight(upstream_host),
+                    styles::highlight(ORIGINAL_HOST_HEADER)
+                ))
+            });
+            req.headers_mut().insert(name, original);
+        }
+        None => {
+            req.headers_mut().remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_original_host_is_kept() {
+        let mut req = Request::get("/")
+            .header(HOST, "localhost:8787")
+            .header(ORIGINAL_HOST_HEADER, "spoofed.example.com")
+            .body(Body::empty())
+            .unwrap();
+        preserve(&mut req, "example.com");
+        assert_eq!(req.headers()[ORIGINAL_HOST_HEADER], "localhost:8787");
+
+        let mut req = Request::get("https://localhost:8787/")
+            .body(Body::empty())
+            .unwrap();
+        preserve(&mut req, "example.com");
+        assert_eq!(req.headers()[ORIGINAL_HOST_HEADER], "localhost:8787");
+    }
+}
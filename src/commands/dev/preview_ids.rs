@@ -0,0 +1,205 @@
+//! `--record-preview-id` keeps a timeline of the previews dev sends requests
+//! to, for chasing a preview that changes or expires when it shouldn't. A line
+//! is written when the session starts serving a preview, when a rebuild swaps
+//! in a new one, and when a rebuild fails and leaves the old one in place:
+//!
+//! ```text
+//! 2020-04-20T15:25:54+00:00 serving 0a1b2c…e7f8, from your local code
+//! 2020-04-20T15:27:10+00:00 serving 9f8e7d…5a4b, rebuilt in 2.31s, replacing 0a1b2c…e7f8
+//! 2020-04-20T15:29:02+00:00 still serving 9f8e7d…5a4b, the rebuild failed: <error>
+//! ```
+//!
+//! Lines go to stderr, or are appended to the file given with the flag. Ids
+//! are shortened to their first and last few characters, enough to tell them
+//! apart without putting a whole preview token in a log.
+use crate::commands::dev::rebuild::Rebuilt;
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use once_cell::sync::OnceCell;
+
+const SHOWN_START: usize = 6;
+const SHOWN_END: usize = 4;
+
+static RECORDER: OnceCell<Recorder> = OnceCell::new();
+
+struct Recorder {
+    /// stderr if there isn't one
+    file: Option<Mutex<File>>,
+    serving: Mutex<Option<String>>,
+}
+
+/// a change to the preview requests are sent to
+enum Change<'a> {
+    /// the session started serving `preview`, got `how`
+    Started {
+        preview: &'a str,
+        how: &'a str,
+    },
+    Rebuilt(&'a Rebuilt),
+    Failed(&'a str),
+}
+
+/// start recording preview ids, to `path` or to stderr
+pub fn init(path: Option<&Path>) -> Result<()> {
+    let file = match path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| {
+                    anyhow!(
+                        "Could not open {} to record preview ids in: {}",
+                        path.display(),
+                        e
+                    )
+                })?;
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+    let recorder = Recorder {
+        file,
+        serving: Mutex::new(None),
+    };
+    RECORDER
+        .set(recorder)
+        .map_err(|_| anyhow!("Preview ids are already being recorded"))
+}
+
+/// records the preview the session starts out serving, and how it was got, if recording
+pub(super) fn started(preview: &str, how: &str) {
+    record(Change::Started { preview, how });
+}
+
+/// records the outcome of a rebuild, if recording
+pub(super) fn rebuilt(result: &Result<Rebuilt, String>) {
+    match result {
+        Ok(rebuilt) => record(Change::Rebuilt(rebuilt)),
+        Err(e) => record(Change::Failed(e)),
+    }
+}
+
+fn record(change: Change) {
+    let recorder = match RECORDER.get() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    let mut serving = recorder.serving.lock().unwrap();
+    let line = format!(
+        "{} {}",
+        Local::now().to_rfc3339(),
+        describe(&change, serving.as_deref())
+    );
+    match change {
+        Change::Started { preview, .. } => *serving = Some(preview.to_string()),
+        Change::Rebuilt(rebuilt) => *serving = Some(rebuilt.preview.clone()),
+        Change::Failed(_) => {}
+    }
+    drop(serving);
+
+    match &recorder.file {
+        Some(file) => {
+            if let Err(e) = writeln!(file.lock().unwrap(), "{}", line) {
+                log::debug!("Failed to record a preview id: {}", e);
+            }
+        }
+        None => eprintln!("{}", line),
+    }
+}
+
+fn describe(change: &Change, serving: Option<&str>) -> String {
+    match change {
+        Change::Started { preview, how } => format!("serving {}, {}", shorten(preview), how),
+        Change::Rebuilt(rebuilt) => {
+            let took = rebuilt.duration.as_secs_f64();
+            match serving {
+                Some(old) if old == rebuilt.preview => format!(
+                    "still serving {}, rebuilt in {:.2}s to the same preview",
+                    shorten(old),
+                    took
+                ),
+                Some(old) => format!(
+                    "serving {}, rebuilt in {:.2}s, replacing {}",
+                    shorten(&rebuilt.preview),
+                    took,
+                    shorten(old)
+                ),
+                None => format!(
+                    "serving {}, rebuilt in {:.2}s",
+                    shorten(&rebuilt.preview),
+                    took
+                ),
+            }
+        }
+        Change::Failed(e) => match serving {
+            Some(old) => format!("still serving {}, the rebuild failed: {}", shorten(old), e),
+            None => format!("the rebuild failed: {}", e),
+        },
+    }
+}
+
+/// the start and end of a preview id, as the whole thing might be a token
+fn shorten(id: &str) -> String {
+    let chars: Vec<char> = id.chars().collect();
+    if chars.len() <= SHOWN_START + SHOWN_END {
+        return id.to_string();
+    }
+    let start: String = chars[..SHOWN_START].iter().collect();
+    let end: String = chars[chars.len() - SHOWN_END..].iter().collect();
+    format!("{}…{}", start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn rebuilt(preview: &str) -> Rebuilt {
+        Rebuilt {
+            preview: preview.to_string(),
+            duration: Duration::from_millis(2310),
+        }
+    }
+
+    #[test]
+    fn ids_are_shortened() {
+        assert_eq!(shorten("0a1b2c3d4e5f6a7b8c9de7f8"), "0a1b2c…e7f8");
+        assert_eq!(shorten("0a1b2c3d4e"), "0a1b2c3d4e");
+        assert_eq!(shorten(""), "");
+    }
+
+    #[test]
+    fn changes_say_which_preview_is_served() {
+        let first = "0a1b2c3d4e5f6a7b8c9de7f8";
+        let second = "9f8e7d6c5b4a3f2e1d0c5a4b";
+        assert_eq!(
+            describe(
+                &Change::Started {
+                    preview: first,
+                    how: "from your local code"
+                },
+                None
+            ),
+            "serving 0a1b2c…e7f8, from your local code"
+        );
+        assert_eq!(
+            describe(&Change::Rebuilt(&rebuilt(second)), Some(first)),
+            "serving 9f8e7d…5a4b, rebuilt in 2.31s, replacing 0a1b2c…e7f8"
+        );
+        assert_eq!(
+            describe(&Change::Rebuilt(&rebuilt(second)), Some(second)),
+            "still serving 9f8e7d…5a4b, rebuilt in 2.31s to the same preview"
+        );
+        assert_eq!(
+            describe(&Change::Failed("upload failed"), Some(second)),
+            "still serving 9f8e7d…5a4b, the rebuild failed: upload failed"
+        );
+    }
+}
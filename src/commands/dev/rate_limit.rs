@@ -0,0 +1,149 @@
+//! The first upload of a Worker to the preview service can be turned away with a
+//! `429 Too Many Requests` when the API is under pressure, and `wrangler dev`
+//! has nothing to serve until it succeeds. Instead of failing straight away,
+//! the upload is tried again after the wait the API asks for in `Retry-After`,
+//! or after a backoff that doubles from a second when it doesn't say.
+//!
+//! `--upload-retries` sets how many times the upload is retried, 5 by default,
+//! and `--upload-max-wait` how many seconds are spent waiting in all, 60 by
+//! default. An upload that fails for any other reason, like bad credentials or
+//! a network error, isn't retried.
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::commands::dev::DevOptions;
+use crate::http::RateLimited;
+use crate::terminal::message::{Message, StdErr};
+
+const DEFAULT_RETRIES: u32 = 5;
+const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(60);
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// runs `upload`, retrying it while it's rate limited within the limits in `options`
+pub(super) fn retry<T>(options: &DevOptions, upload: impl FnMut() -> Result<T>) -> Result<T> {
+    let retries = options.upload_retries.unwrap_or(DEFAULT_RETRIES);
+    let max_wait = options
+        .upload_max_wait
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MAX_WAIT);
+    retry_with(retries, max_wait, upload, thread::sleep)
+}
+
+fn retry_with<T>(
+    retries: u32,
+    max_wait: Duration,
+    mut upload: impl FnMut() -> Result<T>,
+    mut sleep: impl FnMut(Duration),
+) -> Result<T> {
+    let mut backoff = FIRST_BACKOFF;
+    let mut waited = Duration::from_secs(0);
+    let mut attempts = 0;
+    loop {
+        let e = match upload() {
+            Ok(uploaded) => return Ok(uploaded),
+            Err(e) => e,
+        };
+        attempts += 1;
+        let retry_after = match e.downcast_ref::<RateLimited>() {
+            Some(rate_limited) => rate_limited.retry_after.unwrap_or(backoff),
+            None => return Err(e),
+        };
+
+        if attempts > retries || waited + retry_after > max_wait {
+            anyhow::bail!(
+                "{}, and the upload was still turned away after {} attempts over {}s. \
+                This isn't a problem with your credentials or network, try again in a minute, \
+                or allow more with --upload-retries and --upload-max-wait",
+                e,
+                attempts,
+                waited.as_secs()
+            )
+        }
+
+        StdErr::working(&format!(
+            "The Cloudflare API is rate limiting uploads, waiting {}s before trying again...",
+            retry_after.as_secs()
+        ));
+        sleep(retry_after);
+        waited += retry_after;
+        backoff *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn rate_limited(retry_after: Option<u64>) -> anyhow::Error {
+        RateLimited {
+            retry_after: retry_after.map(Duration::from_secs),
+        }
+        .into()
+    }
+
+    #[test]
+    fn rate_limited_uploads_are_retried_after_the_wait_asked_for() {
+        let mut responses = vec![
+            Err(rate_limited(Some(7))),
+            Err(rate_limited(None)),
+            Err(rate_limited(None)),
+            Ok("preview"),
+        ]
+        .into_iter();
+        let mut slept = Vec::new();
+
+        let uploaded = retry_with(
+            5,
+            Duration::from_secs(60),
+            || responses.next().unwrap(),
+            |wait| slept.push(wait.as_secs()),
+        );
+        assert_eq!(uploaded.unwrap(), "preview");
+        // the backoff doubles on every attempt, whether or not the API said how long to wait
+        assert_eq!(slept, vec![7, 2, 4]);
+    }
+
+    #[test]
+    fn retries_stop_at_the_limits() {
+        let mut slept = Vec::new();
+        let e = retry_with(
+            2,
+            Duration::from_secs(60),
+            || Err::<(), _>(rate_limited(Some(1))),
+            |wait| slept.push(wait),
+        )
+        .unwrap_err();
+        assert_eq!(slept.len(), 2);
+        assert!(e.to_string().contains("after 3 attempts"));
+
+        let mut slept = Vec::new();
+        retry_with(
+            5,
+            Duration::from_secs(10),
+            || Err::<(), _>(rate_limited(Some(30))),
+            |wait| slept.push(wait),
+        )
+        .unwrap_err();
+        assert!(slept.is_empty());
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut attempts = 0;
+        let e = retry_with(
+            5,
+            Duration::from_secs(60),
+            || {
+                attempts += 1;
+                Err::<(), _>(anyhow!("Authentication error"))
+            },
+            |_| panic!("should not wait"),
+        )
+        .unwrap_err();
+        assert_eq!(attempts, 1);
+        assert_eq!(e.to_string(), "Authentication error");
+    }
+}
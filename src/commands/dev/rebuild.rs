@@ -0,0 +1,160 @@
+//! `--rebuild-placeholder` answers requests made while a change is being uploaded
+//! with a 503 and a page saying so, rather than holding them until the new
+//! preview is ready
+//!
+//! A rebuild can also be forced without changing a file, with
+//! `POST <prefix>/rebuild` or `r` in the `--tui` dashboard. Requests to force
+//! one while another is still pending are answered by the same rebuild
+use crate::commands::dev::error_response;
+use crate::commands::dev::preview_ids;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE, RETRY_AFTER};
+use hyper::{Body, Response, StatusCode};
+use once_cell::sync::Lazy;
+use tokio::sync::oneshot;
+
+/// how much of a preview id is shown, which is enough to tell previews apart
+const SHORT_PREVIEW_LEN: usize = 8;
+
+static REBUILDING: AtomicBool = AtomicBool::new(false);
+static FORCED: Lazy<Mutex<Forced>> = Lazy::new(|| Mutex::new(Forced::default()));
+
+#[derive(Default)]
+struct Forced {
+    /// starts a rebuild, like a change to a watched file does
+    trigger: Option<mpsc::Sender<()>>,
+    waiting: Vec<oneshot::Sender<Result<Rebuilt, String>>>,
+}
+
+/// a new preview, and how long it took to build and upload
+#[derive(Debug, Clone)]
+pub(super) struct Rebuilt {
+    pub(super) preview: String,
+    pub(super) duration: Duration,
+}
+
+impl Rebuilt {
+    /// the start of the preview id, as the whole thing is too long to be useful
+    pub(super) fn short_preview(&self) -> &str {
+        self.preview
+            .get(..SHORT_PREVIEW_LEN)
+            .unwrap_or(&self.preview)
+    }
+}
+
+const PLACEHOLDER: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <meta http-equiv="refresh" content="1">
+    <title>Rebuilding</title>
+  </head>
+  <body>
+    <p>wrangler dev is uploading your latest changes, this page will reload when they're ready.</p>
+  </body>
+</html>
+"#;
+
+/// marks a rebuild as in progress until it is dropped,
+/// which is once the new preview has been swapped in or the upload failed
+pub(super) struct Rebuild;
+
+impl Rebuild {
+    pub(super) fn start() -> Rebuild {
+        REBUILDING.store(true, Ordering::SeqCst);
+        Rebuild
+    }
+}
+
+impl Drop for Rebuild {
+    fn drop(&mut self) {
+        REBUILDING.store(false, Ordering::SeqCst);
+    }
+}
+
+pub(super) fn in_progress() -> bool {
+    REBUILDING.load(Ordering::SeqCst)
+}
+
+/// lets rebuilds be forced by sending on `trigger`, for as long as the project is watched
+pub(super) fn listen(trigger: mpsc::Sender<()>) {
+    FORCED.lock().unwrap().trigger = Some(trigger);
+}
+
+/// starts a rebuild unless one is already pending, resolving once it is done.
+/// There's nothing to rebuild if the project isn't watched
+pub(super) fn force() -> Option<oneshot::Receiver<Result<Rebuilt, String>>> {
+    let mut forced = FORCED.lock().unwrap();
+    let trigger = forced.trigger.as_ref()?;
+    if forced.waiting.is_empty() && trigger.send(()).is_err() {
+        return None;
+    }
+
+    let (sender, receiver) = oneshot::channel();
+    forced.waiting.push(sender);
+    Some(receiver)
+}
+
+/// whether the rebuild about to start was forced, so the project has to be built first
+pub(super) fn is_forced() -> bool {
+    !FORCED.lock().unwrap().waiting.is_empty()
+}
+
+/// answers everything waiting on a forced rebuild, and records how the rebuild went
+pub(super) fn finished(result: Result<Rebuilt, String>) {
+    preview_ids::rebuilt(&result);
+    for waiting in FORCED.lock().unwrap().waiting.drain(..) {
+        waiting.send(result.clone()).ok();
+    }
+}
+
+/// the response to a request made during a rebuild
+pub(super) fn placeholder() -> Response<Body> {
+    let mut resp = if error_response::is_json() {
+        error_response::build(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "wrangler dev is rebuilding your Worker, try again shortly",
+        )
+    } else {
+        let mut resp = Response::new(Body::from(PLACEHOLDER));
+        *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        resp.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        resp
+    };
+    let headers = resp.headers_mut();
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("1"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pending_rebuilds_are_shared() {
+        assert!(force().is_none());
+
+        let (trigger, triggered) = mpsc::channel();
+        listen(trigger);
+        let first = force().unwrap();
+        let second = force().unwrap();
+        assert!(is_forced());
+        assert_eq!(triggered.try_iter().count(), 1);
+
+        finished(Ok(Rebuilt {
+            preview: "0123456789abcdef".to_string(),
+            duration: Duration::from_millis(10),
+        }));
+        assert_eq!(first.await.unwrap().unwrap().short_preview(), "01234567");
+        assert_eq!(second.await.unwrap().unwrap().preview, "0123456789abcdef");
+        assert!(!is_forced());
+    }
+}
@@ -0,0 +1,245 @@
+//! `--replace FROM=TO` swaps placeholders in text responses for dev values,
+//! so a Worker's frontend doesn't need dev URLs hardcoded into it.
+//!
+//! `--rewrite-host FROM=TO` points absolute URLs in HTML and JSON responses at
+//! another origin, usually the dev server. Bodies are matched as plain text, not
+//! parsed, so an origin is rewritten wherever it appears, including as the start
+//! of a longer hostname, and URLs built up by scripts at runtime are missed.
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderMap, CONTENT_ENCODING, CONTENT_TYPE};
+use hyper::{Body, Response};
+use url::Url;
+
+use crate::commands::dev::response_body;
+
+/// parses a `FROM=TO` pair, where `TO` may be empty but `FROM` may not
+pub fn parse(replace: &str) -> Result<(String, String)> {
+    replace
+        .split_once('=')
+        .filter(|(from, _)| !from.is_empty())
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .ok_or_else(|| anyhow!("Expected FROM=TO, like __API_URL__=http://localhost:8787"))
+}
+
+/// parses a `FROM=TO` pair of origins, like https://example.com=http://localhost:8787
+pub fn parse_rewrite_host(rewrite: &str) -> Result<(String, String)> {
+    let (from, to) = rewrite.split_once('=').ok_or_else(|| {
+        anyhow!("Expected FROM=TO, like https://example.com=http://localhost:8787")
+    })?;
+    Ok((origin(from)?, origin(to)?))
+}
+
+fn origin(origin: &str) -> Result<String> {
+    let url = Url::parse(origin).map_err(|e| anyhow!("{} is not an origin: {}", origin, e))?;
+    if url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+        anyhow::bail!(
+            "{} is not an origin, it should only have a scheme, host and port",
+            origin
+        )
+    }
+    Ok(url.origin().ascii_serialization())
+}
+
+/// makes each replacement in turn in the body of a text response,
+/// which means reading the whole body first. Other responses are left as they are
+pub(super) async fn apply(
+    resp: Response<Body>,
+    replacements: &[(String, String)],
+) -> Result<Response<Body>> {
+    if replacements.is_empty() || !is_text(resp.headers()) {
+        return Ok(resp);
+    }
+    replace_body(resp, replacements).await
+}
+
+/// rewrites origins in the body of an HTML or JSON response,
+/// including in the escaped form JSON may use for them
+pub(super) async fn rewrite_hosts(
+    resp: Response<Body>,
+    rewrites: &[(String, String)],
+) -> Result<Response<Body>> {
+    if rewrites.is_empty() || !is_html_or_json(resp.headers()) {
+        return Ok(resp);
+    }
+
+    let escape = |origin: &str| origin.replace('/', "\\/");
+    let replacements: Vec<(String, String)> = rewrites
+        .iter()
+        .flat_map(|(from, to)| vec![(from.clone(), to.clone()), (escape(from), escape(to))])
+        .collect();
+    replace_body(resp, &replacements).await
+}
+
+async fn replace_body(
+    resp: Response<Body>,
+    replacements: &[(String, String)],
+) -> Result<Response<Body>> {
+    let (parts, body) = resp.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let text = match std::str::from_utf8(&body) {
+        Ok(text) => replace_all(text, replacements),
+        // labelled as text but isn't, so it can't be safely changed
+        Err(_) => return Ok(Response::from_parts(parts, Body::from(body))),
+    };
+    Ok(response_body::replace(parts, text))
+}
+
+fn replace_all(text: &str, replacements: &[(String, String)]) -> String {
+    replacements
+        .iter()
+        .fold(text.to_string(), |text, (from, to)| text.replace(from, to))
+}
+
+/// only uncompressed text can have its placeholders replaced
+pub(super) fn is_text(headers: &HeaderMap) -> bool {
+    mime_type(headers).map_or(false, |mime| {
+        mime.starts_with("text/")
+            || mime.ends_with("+json")
+            || mime.ends_with("+xml")
+            || matches!(
+                mime.as_str(),
+                "application/json" | "application/javascript" | "application/xml"
+            )
+    })
+}
+
+/// whether the body is uncompressed HTML
+pub(super) fn is_html(headers: &HeaderMap) -> bool {
+    mime_type(headers).map_or(false, |mime| mime == "text/html")
+}
+
+fn is_html_or_json(headers: &HeaderMap) -> bool {
+    mime_type(headers).map_or(false, |mime| {
+        mime == "text/html" || mime == "application/json" || mime.ends_with("+json")
+    })
+}
+
+/// the lowercased mime type of an uncompressed body
+fn mime_type(headers: &HeaderMap) -> Option<String> {
+    let encoded = headers
+        .get(CONTENT_ENCODING)
+        .map_or(false, |encoding| encoding != "identity");
+    if encoded {
+        return None;
+    }
+
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?.to_lowercase();
+    Some(
+        content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::CONTENT_LENGTH;
+
+    fn replacements(pairs: &[&str]) -> Vec<(String, String)> {
+        pairs.iter().map(|pair| parse(pair).unwrap()).collect()
+    }
+
+    #[test]
+    fn pairs_are_parsed() {
+        assert_eq!(
+            parse("__API_URL__=http://localhost:8787?a=b").unwrap(),
+            (
+                "__API_URL__".to_string(),
+                "http://localhost:8787?a=b".to_string()
+            )
+        );
+        assert_eq!(parse("__DEBUG__=").unwrap().1, "");
+        assert!(parse("=value").is_err());
+        assert!(parse("__API_URL__").is_err());
+    }
+
+    #[test]
+    fn replacements_are_made_in_order() {
+        let text = "fetch('__API_URL__/users')";
+        assert_eq!(
+            replace_all(text, &replacements(&["__API_URL__=http://localhost:8787"])),
+            "fetch('http://localhost:8787/users')"
+        );
+        assert_eq!(
+            replace_all(
+                text,
+                &replacements(&["__API_URL__=__HOST__", "__HOST__=dev"])
+            ),
+            "fetch('dev/users')"
+        );
+    }
+
+    #[tokio::test]
+    async fn content_length_is_recomputed() {
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(CONTENT_LENGTH, "14")
+            .body(Body::from("<a>__URL__</a>"))
+            .unwrap();
+
+        let resp = apply(resp, &replacements(&["__URL__=http://localhost"]))
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[CONTENT_LENGTH], "23");
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "<a>http://localhost</a>");
+    }
+
+    #[test]
+    fn origins_are_parsed() {
+        assert_eq!(
+            parse_rewrite_host("https://Prod.example.com=http://localhost:8787/").unwrap(),
+            (
+                "https://prod.example.com".to_string(),
+                "http://localhost:8787".to_string()
+            )
+        );
+        assert!(parse_rewrite_host("https://prod.example.com/app=http://localhost").is_err());
+        assert!(parse_rewrite_host("prod.example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn hosts_are_rewritten_in_json_only() {
+        let rewrites =
+            vec![parse_rewrite_host("https://prod.example.com=http://localhost:8787").unwrap()];
+
+        let json = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"a":"https://prod.example.com/x","b":"https:\/\/prod.example.com\/y"}"#,
+            ))
+            .unwrap();
+        let json = rewrite_hosts(json, &rewrites).await.unwrap();
+        let body = hyper::body::to_bytes(json.into_body()).await.unwrap();
+        assert_eq!(
+            body,
+            r#"{"a":"http://localhost:8787/x","b":"http:\/\/localhost:8787\/y"}"#
+        );
+
+        let css = Response::builder()
+            .header(CONTENT_TYPE, "text/css")
+            .body(Body::from("url(https://prod.example.com/a.png)"))
+            .unwrap();
+        let css = rewrite_hosts(css, &rewrites).await.unwrap();
+        let body = hyper::body::to_bytes(css.into_body()).await.unwrap();
+        assert_eq!(body, "url(https://prod.example.com/a.png)");
+    }
+
+    #[tokio::test]
+    async fn binary_responses_are_untouched() {
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "image/png")
+            .body(Body::from("__URL__"))
+            .unwrap();
+
+        let resp = apply(resp, &replacements(&["__URL__=http://localhost"]))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "__URL__");
+    }
+}
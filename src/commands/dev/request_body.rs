@@ -0,0 +1,140 @@
+//! Clients that stream an upload without knowing its length send it with
+//! `Transfer-Encoding: chunked`. That framing only describes the connection to
+//! wrangler dev, so it is taken off before the request goes upstream, where
+//! hyper frames the body again for its own connection. Forwarded as it was,
+//! the preview service would pass the header along to the Worker as if it
+//! still applied to a body that has already been de-chunked.
+//!
+//! Bodies are re-chunked and streamed upstream by default. With
+//! `--chunked-request-bodies buffer` they are read in full first and sent with a
+//! `Content-Length`, for Workers and upstreams that need to know the length up front.
+//! `--buffer-request-body` does the same for every request body, even ones the
+//! client sent with a `Content-Length`, so the Worker never sees a body still
+//! arriving. Buffered bodies are held in memory until they're sent, up to the
+//! 100MiB the Workers runtime accepts, so large uploads are better streamed
+use crate::commands::dev::error_response;
+use crate::terminal::message::{Message, StdErr};
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING};
+use hyper::{Body, Request, Response, StatusCode};
+
+/// the largest request body the Workers runtime accepts
+pub(super) const MAX_BUFFERED: usize = 100 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkedBodies {
+    Stream,
+    Buffer,
+}
+
+impl Default for ChunkedBodies {
+    fn default() -> Self {
+        ChunkedBodies::Stream
+    }
+}
+
+impl FromStr for ChunkedBodies {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stream" => Ok(ChunkedBodies::Stream),
+            "buffer" => Ok(ChunkedBodies::Buffer),
+            _ => anyhow::bail!("Expected stream or buffer, got {}", s),
+        }
+    }
+}
+
+/// the request has a body of unknown length, however the client framed it
+pub(super) fn is_chunked(req: &Request<Body>) -> bool {
+    let chunked = req
+        .headers()
+        .get_all(TRANSFER_ENCODING)
+        .iter()
+        .any(|encoding| encoding.as_bytes().eq_ignore_ascii_case(b"chunked"));
+    chunked || (!req.headers().contains_key(CONTENT_LENGTH) && !req.body().is_end_stream())
+}
+
+/// takes off the framing of the client's connection, leaving hyper to frame the body
+pub(super) fn strip_framing(req: &mut Request<Body>) {
+    req.headers_mut().remove(TRANSFER_ENCODING);
+}
+
+pub(super) enum Buffered {
+    Complete(Request<Body>),
+    TooLarge(Response<Body>),
+}
+
+/// reads the whole body so it can be sent with a `Content-Length`,
+/// answering with a 413 if it grows past `limit`
+pub(super) async fn buffer(req: Request<Body>, limit: usize) -> Result<Buffered> {
+    let (mut parts, mut body) = req.into_parts();
+    let mut buffered = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buffered.len() + chunk.len() > limit {
+            return Ok(Buffered::TooLarge(too_large(limit)));
+        }
+        buffered.extend_from_slice(&chunk);
+    }
+
+    parts.headers.remove(TRANSFER_ENCODING);
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(buffered.len()));
+    Ok(Buffered::Complete(Request::from_parts(
+        parts,
+        Body::from(buffered),
+    )))
+}
+
+fn too_large(limit: usize) -> Response<Body> {
+    StdErr::warn(&format!(
+        "Rejected a request with a 413, its body was over the {} byte limit for buffering it",
+        limit
+    ));
+    error_response::build(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!(
+            "The request body is over the {} byte limit of wrangler dev",
+            limit
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_without_a_length_are_chunked() {
+        let req = Request::post("/")
+            .header(TRANSFER_ENCODING, "chunked")
+            .body(Body::from("a"))
+            .unwrap();
+        assert!(is_chunked(&req));
+
+        let (_sender, body) = Body::channel();
+        assert!(is_chunked(&Request::post("/").body(body).unwrap()));
+
+        let req = Request::post("/")
+            .header(CONTENT_LENGTH, "1")
+            .body(Body::from("a"))
+            .unwrap();
+        assert!(!is_chunked(&req));
+        assert!(!is_chunked(&Request::get("/").body(Body::empty()).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn buffered_bodies_over_the_limit_are_rejected() {
+        let req = Request::post("/").body(Body::from("abcd")).unwrap();
+        match buffer(req, 3).await.unwrap() {
+            Buffered::TooLarge(resp) => assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE),
+            Buffered::Complete(_) => panic!("a body over the limit was buffered"),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Every feature that changes the body of a response, like `--replace`,
+//! `--rewrite-host` and `--compress`, hands the new body to `replace`, so the
+//! framing headers sent to the client describe the bytes actually sent. A stale
+//! `Content-Length` left over from the Worker's body would make the client cut
+//! the new body short, or wait for bytes that never come.
+//!
+//! Some bodies are never rewritten, which `can_replace` says: the empty body of
+//! a response to a HEAD request stands in for one that wasn't sent, so its
+//! `Content-Length` is the real length, and a byte range would no longer match
+//! its `Content-Range` once changed.
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, TRANSFER_ENCODING};
+use hyper::http::response::Parts;
+use hyper::{Body, Response, StatusCode};
+
+/// the response with `body` in place of its old one, framed with the new body's length
+pub(super) fn replace(mut parts: Parts, body: impl Into<Bytes>) -> Response<Body> {
+    let body = body.into();
+    // the Worker's framing no longer applies, and hyper frames a body it knows the length of
+    parts.headers.remove(TRANSFER_ENCODING);
+    if has_body(parts.status) {
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// whether the body of `resp`, the response to a HEAD request if `head`, can be rewritten
+pub(super) fn can_replace(head: bool, resp: &Response<Body>) -> bool {
+    !head
+        && resp.status() != StatusCode::PARTIAL_CONTENT
+        && !resp.headers().contains_key(CONTENT_RANGE)
+}
+
+/// the Content-Length of a 304 describes the body it stands in for,
+/// and responses that never have a body mustn't have one at all
+fn has_body(status: StatusCode) -> bool {
+    !(status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Method, Request};
+
+    /// what a client receives when a Worker's 5 byte body is replaced with `replaced`
+    async fn received(replaced: Vec<u8>) -> (Option<String>, Vec<u8>) {
+        received_for(Method::GET, replaced).await
+    }
+
+    /// like `received`, for a request with `method`, whose body is left alone where it can't be replaced
+    async fn received_for(method: Method, replaced: Vec<u8>) -> (Option<String>, Vec<u8>) {
+        let make_service = make_service_fn(move |_| {
+            let replaced = replaced.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                    let replaced = replaced.clone();
+                    async move {
+                        let worker = Response::builder()
+                            .header(CONTENT_LENGTH, "5")
+                            .body(Body::from("hello"))
+                            .unwrap();
+                        if !can_replace(req.method() == Method::HEAD, &worker) {
+                            return Ok::<_, hyper::Error>(worker);
+                        }
+                        let (parts, _) = worker.into_parts();
+                        Ok::<_, hyper::Error>(replace(parts, replaced))
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}/", addr))
+            .body(Body::empty())
+            .unwrap();
+        let resp = hyper::Client::new().request(req).await.unwrap();
+        let content_length = resp
+            .headers()
+            .get(CONTENT_LENGTH)
+            .map(|len| len.to_str().unwrap().to_string());
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        (content_length, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn clients_receive_exactly_the_new_body() {
+        for size in &[0, 3, 5, 4096, 256 * 1024 + 1] {
+            let replaced = vec![b'x'; *size];
+            let (content_length, body) = received(replaced.clone()).await;
+            assert_eq!(content_length, Some(size.to_string()));
+            assert_eq!(body, replaced);
+        }
+    }
+
+    #[tokio::test]
+    async fn head_responses_keep_the_length_of_the_body_they_stand_in_for() {
+        let (content_length, body) = received_for(Method::HEAD, b"replaced".to_vec()).await;
+        assert_eq!(content_length, Some("5".to_string()));
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn ranges_and_head_responses_are_not_replaced() {
+        let ok = Response::new(Body::empty());
+        assert!(can_replace(false, &ok));
+        assert!(!can_replace(true, &ok));
+
+        let mut partial = Response::new(Body::empty());
+        *partial.status_mut() = StatusCode::PARTIAL_CONTENT;
+        assert!(!can_replace(false, &partial));
+
+        let mut ranged = Response::new(Body::empty());
+        ranged
+            .headers_mut()
+            .insert(CONTENT_RANGE, HeaderValue::from_static("bytes */100"));
+        assert!(!can_replace(false, &ranged));
+    }
+
+    #[test]
+    fn chunked_framing_is_dropped() {
+        let worker = Response::builder()
+            .header(TRANSFER_ENCODING, "chunked")
+            .body(Body::empty())
+            .unwrap();
+        let (parts, _) = worker.into_parts();
+        let resp = replace(parts, "hello world");
+        assert!(resp.headers().get(TRANSFER_ENCODING).is_none());
+        assert_eq!(resp.headers()[CONTENT_LENGTH], "11");
+    }
+
+    #[test]
+    fn not_modified_keeps_its_length() {
+        let worker = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(CONTENT_LENGTH, "1024")
+            .body(Body::empty())
+            .unwrap();
+        let (parts, _) = worker.into_parts();
+        let resp = replace(parts, "");
+        assert_eq!(resp.headers()[CONTENT_LENGTH], "1024");
+    }
+}
@@ -0,0 +1,192 @@
+//! A Worker can build responses with headers too large for real clients to accept,
+//! like a pile of `Set-Cookie`s or a very long `Content-Security-Policy`. Browsers
+//! fail on those in ways that are hard to trace back to the Worker, so responses
+//! whose headers are over `--max-response-header-size` are caught in dev.
+//!
+//! By default they are replaced with a `502` describing the problem. With
+//! `--oversized-response-headers truncate` the largest headers are dropped instead,
+//! until what's left fits
+//!
+//! Headers that fit can still be close to the limit Workers have in production,
+//! 128KiB of response headers, so a warning is given once they're over 80% of it,
+//! or of `--max-response-header-size` if that's lower. With `-v`, the number of
+//! response headers and their size is noted on every request's log line
+use crate::commands::dev::error_response;
+use crate::commands::dev::serve::headers_size;
+use crate::terminal::message::{Message, StdErr};
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use hyper::header::{HeaderMap, HeaderName};
+use hyper::{Body, Response, StatusCode};
+
+/// the most response headers a Worker can send in production, in bytes
+const WORKERS_LIMIT: usize = 128 * 1024;
+/// how close to the limit, in percent, headers get before it's pointed out
+const WARN_AT_PERCENT: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OversizedHeaders {
+    Reject,
+    Truncate,
+}
+
+impl Default for OversizedHeaders {
+    fn default() -> Self {
+        OversizedHeaders::Reject
+    }
+}
+
+impl FromStr for OversizedHeaders {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "reject" => Ok(OversizedHeaders::Reject),
+            "truncate" => Ok(OversizedHeaders::Truncate),
+            _ => anyhow::bail!("Expected reject or truncate, got {}", s),
+        }
+    }
+}
+
+/// keeps the headers of `resp` within `limit` bytes, warning about the header responsible
+/// if they aren't. `description` says which request the response is to
+pub(super) fn guard(
+    mut resp: Response<Body>,
+    limit: usize,
+    oversized: OversizedHeaders,
+    description: &str,
+) -> Response<Body> {
+    let size = headers_size(resp.headers());
+    if size <= limit {
+        return resp;
+    }
+
+    match oversized {
+        OversizedHeaders::Reject => {
+            let (name, largest) = largest_header(resp.headers());
+            StdErr::warn(&format!(
+                "Answered {} with a 502, the Worker's response headers are {} bytes which is over the limit of {} bytes set by --max-response-header-size. The largest is {} at {} bytes",
+                description, size, limit, name, largest
+            ));
+            bad_gateway(size, limit, &name)
+        }
+        OversizedHeaders::Truncate => {
+            while headers_size(resp.headers()) > limit {
+                let (name, largest) = largest_header(resp.headers());
+                StdErr::warn(&format!(
+                    "Dropped the {} header from the response to {}, at {} bytes it took the response headers over the limit of {} bytes set by --max-response-header-size",
+                    name, description, largest, limit
+                ));
+                resp.headers_mut().remove(&name);
+            }
+            resp
+        }
+    }
+}
+
+/// warns about headers of `size` bytes that fit within `limit`, and the limit of
+/// Workers in production, but only just
+pub(super) fn warn_if_near_limit(size: usize, limit: usize, description: &str) {
+    let limit = limit.min(WORKERS_LIMIT);
+    if is_near_limit(size, limit) {
+        StdErr::warn(&format!(
+            "The Worker's response headers to {} are {} bytes, close to the limit of {} bytes{}",
+            description,
+            size,
+            limit,
+            if limit == WORKERS_LIMIT {
+                " on response headers in production"
+            } else {
+                " set by --max-response-header-size"
+            }
+        ));
+    }
+}
+
+fn is_near_limit(size: usize, limit: usize) -> bool {
+    size <= limit && size * 100 > limit * WARN_AT_PERCENT
+}
+
+/// the header taking up the most space, counting every value of a repeated header
+fn largest_header(headers: &HeaderMap) -> (HeaderName, usize) {
+    headers
+        .keys()
+        .map(|name| {
+            let size = headers
+                .get_all(name)
+                .iter()
+                .map(|value| name.as_str().len() + value.len() + 4)
+                .sum();
+            (name.clone(), size)
+        })
+        .max_by_key(|(_, size)| *size)
+        .expect("headers over the limit can't be empty")
+}
+
+fn bad_gateway(size: usize, limit: usize, largest: &HeaderName) -> Response<Body> {
+    error_response::build(
+        StatusCode::BAD_GATEWAY,
+        format!(
+            "The Worker responded with {} bytes of headers, which is over the {} byte limit of wrangler dev. The largest is {}",
+            size, limit, largest
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{CONTENT_SECURITY_POLICY, CONTENT_TYPE, SET_COOKIE};
+
+    fn oversized() -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html")
+            .header(SET_COOKIE, "a".repeat(600))
+            .header(SET_COOKIE, "b".repeat(600))
+            .header(CONTENT_SECURITY_POLICY, "c".repeat(1000))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn headers_close_to_the_limit_are_pointed_out() {
+        assert!(!is_near_limit(1000, WORKERS_LIMIT));
+        assert!(!is_near_limit(WORKERS_LIMIT * 8 / 10, WORKERS_LIMIT));
+        assert!(is_near_limit(WORKERS_LIMIT * 8 / 10 + 1, WORKERS_LIMIT));
+        assert!(is_near_limit(WORKERS_LIMIT, WORKERS_LIMIT));
+        // over the limit is guarded against instead
+        assert!(!is_near_limit(WORKERS_LIMIT + 1, WORKERS_LIMIT));
+    }
+
+    #[test]
+    fn repeated_headers_count_together() {
+        let resp = oversized();
+        let (name, size) = largest_header(resp.headers());
+        assert_eq!(name, SET_COOKIE);
+        assert_eq!(size, 2 * (10 + 600 + 4));
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let resp = guard(oversized(), 1024, OversizedHeaders::Reject, "GET /");
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+        let resp = guard(oversized(), 64 * 1024, OversizedHeaders::Reject, "GET /");
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn truncating_drops_the_largest_headers() {
+        let resp = guard(oversized(), 1024, OversizedHeaders::Truncate, "GET /");
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!resp.headers().contains_key(SET_COOKIE));
+        assert!(!resp.headers().contains_key(CONTENT_SECURITY_POLICY));
+        assert_eq!(resp.headers()[CONTENT_TYPE], "text/html");
+
+        let resp = guard(oversized(), 2048, OversizedHeaders::Truncate, "GET /");
+        assert!(!resp.headers().contains_key(SET_COOKIE));
+        assert!(resp.headers().contains_key(CONTENT_SECURITY_POLICY));
+    }
+}
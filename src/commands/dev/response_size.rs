@@ -0,0 +1,70 @@
+use crate::terminal::message::{Message, StdErr};
+
+use hyper::body::{HttpBody, Sender};
+use hyper::{Body, Response};
+
+/// counts the bytes of a response body as they are streamed to the client,
+/// warning once it is done if there were more than `limit` of them
+pub(super) fn watch(resp: Response<Body>, limit: u64, description: String) -> Response<Body> {
+    let (parts, body) = resp.into_parts();
+    let (sender, counted) = Body::channel();
+
+    tokio::spawn(async move {
+        let size = relay(body, sender).await;
+        if size > limit {
+            StdErr::warn(&format!(
+                "The response to {} was {} bytes, which is over the {} bytes set by --warn-response-size",
+                description, size, limit
+            ));
+        }
+    });
+
+    Response::from_parts(parts, counted)
+}
+
+/// sends the body on without buffering it, returning how many bytes were sent
+pub(super) async fn relay(mut body: Body, mut sender: Sender) -> u64 {
+    let mut size = 0;
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => {
+                size += chunk.len() as u64;
+                if sender.send_data(chunk).await.is_err() {
+                    // the client went away, only what was sent counts
+                    break;
+                }
+            }
+            Err(e) => {
+                log::info!("Response body failed after {} bytes: {}", size, e);
+                sender.abort();
+                break;
+            }
+        }
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn large_bodies_are_counted_as_they_stream() {
+        let (mut upstream, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..64 {
+                upstream
+                    .send_data(vec![b'a'; 64 * 1024].into())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let (sender, relayed) = Body::channel();
+        let size = tokio::spawn(relay(body, sender));
+
+        let relayed = hyper::body::to_bytes(relayed).await.unwrap();
+        assert_eq!(relayed.len(), 64 * 64 * 1024);
+        assert_eq!(size.await.unwrap(), 64 * 64 * 1024);
+    }
+}
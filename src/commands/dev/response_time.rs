@@ -0,0 +1,97 @@
+//! `--max-response-time <secs>` limits how long a response body can take to
+//! stream to the client in all, so a Worker stuck sending a response forever,
+//! like one looping on a stream it never closes, doesn't tie up the connection
+//! for the rest of the session. The body is cut off once the time is up, which
+//! ends the connection, and a warning names the request. Unlike
+//! `--read-timeout`, which is about the gaps between chunks, this counts from
+//! the response starting however steadily it arrives. It's off by default.
+use crate::terminal::message::{Message, StdErr};
+
+use std::time::Duration;
+
+use hyper::body::HttpBody;
+use hyper::{Body, Response};
+use tokio::time::Instant;
+
+/// forwards the body as it arrives, cutting it off once it's been streaming for `max`
+pub(super) fn cap(resp: Response<Body>, max: Duration, description: String) -> Response<Body> {
+    if resp.body().is_end_stream() {
+        return resp;
+    }
+
+    let (parts, mut body) = resp.into_parts();
+    let (mut sender, capped) = Body::channel();
+    let deadline = Instant::now() + max;
+    tokio::spawn(async move {
+        loop {
+            match tokio::time::timeout_at(deadline, body.data()).await {
+                Ok(Some(Ok(chunk))) => {
+                    if sender.send_data(chunk).await.is_err() {
+                        // the client is no longer reading the response
+                        break;
+                    }
+                }
+                Ok(Some(Err(e))) => {
+                    log::debug!("Failed to read response body: {}", e);
+                    sender.abort();
+                    break;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    StdErr::warn(&format!(
+                        "Cut off the response to {} after {}s, the limit set by --max-response-time",
+                        description,
+                        max.as_secs_f64()
+                    ));
+                    sender.abort();
+                    break;
+                }
+            }
+        }
+    });
+    Response::from_parts(parts, capped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn endless_responses_are_cut_off() {
+        let (mut upstream, body) = Body::channel();
+        tokio::spawn(async move {
+            // a Worker that never finishes its response
+            while upstream.send_data("tick\n".into()).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        let started = std::time::Instant::now();
+        let resp = cap(
+            Response::new(body),
+            Duration::from_millis(50),
+            "GET example.com/stream".to_string(),
+        );
+        assert!(hyper::body::to_bytes(resp.into_body()).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn responses_within_the_limit_are_passed_on() {
+        let (mut upstream, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                upstream.send_data("tick\n".into()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        let resp = cap(
+            Response::new(body),
+            Duration::from_secs(5),
+            "GET example.com/stream".to_string(),
+        );
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "tick\ntick\ntick\n");
+    }
+}
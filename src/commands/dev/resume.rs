@@ -0,0 +1,180 @@
+use crate::terminal::message::{Message, StdErr};
+
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_RANGE, RANGE};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri, Version};
+
+/// sends a request again, asking for the response body from the given offset onwards
+pub(super) type Resend = Box<dyn FnOnce(u64) -> BoxFuture<'static, Result<Response<Body>>> + Send>;
+
+/// the parts of a request needed to send it again
+pub(super) struct RequestTemplate {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+}
+
+impl RequestTemplate {
+    /// only a GET without a body or a range of its own can be sent again to resume it
+    pub(super) fn of(req: &Request<Body>) -> Option<RequestTemplate> {
+        let resumable = req.method() == Method::GET
+            && req.body().is_end_stream()
+            && !req.headers().contains_key(RANGE);
+
+        if resumable {
+            Some(RequestTemplate {
+                method: req.method().clone(),
+                uri: req.uri().clone(),
+                version: req.version(),
+                headers: req.headers().clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn build(self, offset: u64) -> Request<Body> {
+        let mut req = Request::new(Body::empty());
+        *req.method_mut() = self.method;
+        *req.uri_mut() = self.uri;
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers;
+        if let Ok(range) = HeaderValue::from_str(&format!("bytes={}-", offset)) {
+            req.headers_mut().insert(RANGE, range);
+        }
+        req
+    }
+}
+
+/// streams a response body from the preview service to the client,
+/// making sure the client's response is never cut off silently
+///
+/// if the connection to the preview service drops part way through the body
+/// and the request can be sent again, the rest of the body is requested once
+/// with a `Range` header, otherwise a warning describes what was lost
+pub(super) fn forward(
+    resp: Response<Body>,
+    resend: Option<Resend>,
+    description: String,
+) -> Response<Body> {
+    let accepts_ranges = resp
+        .headers()
+        .get(ACCEPT_RANGES)
+        .map_or(false, |accept_ranges| accept_ranges == "bytes");
+    let mut resend = if accepts_ranges { resend } else { None };
+    let resendable = resend.is_some();
+
+    let (parts, mut body) = resp.into_parts();
+    let (mut sender, forwarded) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut sent: u64 = 0;
+        loop {
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    sent += chunk.len() as u64;
+                    if sender.send_data(chunk).await.is_err() {
+                        // the client went away, there is no one left to send the body to
+                        return;
+                    }
+                }
+                Some(Err(e)) => {
+                    log::info!("Upstream body for {} failed: {}", description, e);
+                    if let Some(resend) = resend.take() {
+                        StdErr::working(&format!(
+                            "Lost the connection to the preview service after sending {} bytes of {}, resuming...",
+                            sent, description
+                        ));
+                        match resend(sent).await {
+                            Ok(resp) if resumes_at(&resp, sent) => {
+                                body = resp.into_body();
+                                continue;
+                            }
+                            Ok(resp) => log::info!(
+                                "Could not resume {}, got {} instead",
+                                description,
+                                resp.status()
+                            ),
+                            Err(e) => log::info!("Could not resume {}: {}", description, e),
+                        }
+                        StdErr::warn(&format!(
+                            "The response to {} was cut off after {} bytes, resuming it failed",
+                            description, sent
+                        ));
+                    } else if resendable {
+                        StdErr::warn(&format!(
+                            "The response to {} was cut off after {} bytes, it had already been resumed once",
+                            description, sent
+                        ));
+                    } else {
+                        StdErr::warn(&format!(
+                            "The response to {} was cut off after {} bytes, the connection to the preview service dropped and the request could not be safely resumed",
+                            description, sent
+                        ));
+                    }
+                    // aborting makes sure the client sees an incomplete response,
+                    // not one that looks like it ended normally
+                    sender.abort();
+                    return;
+                }
+                None => return,
+            }
+        }
+    });
+
+    Response::from_parts(parts, forwarded)
+}
+
+/// a resumed response must be the rest of the body, starting where the last one left off
+fn resumes_at(resp: &Response<Body>, offset: u64) -> bool {
+    resp.status() == StatusCode::PARTIAL_CONTENT
+        && resp
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|content_range| content_range.to_str().ok())
+            .map_or(false, |content_range| {
+                content_range.starts_with(&format!("bytes {}-", offset))
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bodyless_gets_are_resumable() {
+        let get = Request::get("/").body(Body::empty()).unwrap();
+        let post = Request::post("/").body(Body::empty()).unwrap();
+        let ranged = Request::get("/")
+            .header(RANGE, "bytes=0-10")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(RequestTemplate::of(&get).is_some());
+        assert!(RequestTemplate::of(&post).is_none());
+        assert!(RequestTemplate::of(&ranged).is_none());
+    }
+
+    #[test]
+    fn resent_requests_ask_for_the_rest_of_the_body() {
+        let get = Request::get("/").body(Body::empty()).unwrap();
+        let resent = RequestTemplate::of(&get).unwrap().build(1024);
+
+        assert_eq!(resent.headers()[RANGE], "bytes=1024-");
+    }
+
+    #[test]
+    fn resumed_responses_must_start_at_the_offset() {
+        let resumed = Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(CONTENT_RANGE, "bytes 1024-2047/2048")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(resumes_at(&resumed, 1024));
+        assert!(!resumes_at(&resumed, 0));
+    }
+}
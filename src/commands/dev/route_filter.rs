@@ -0,0 +1,143 @@
+//! `--route-filter <file>` decides which requests reach the preview service.
+//! Each line of the file is one of
+//!
+//! ```text
+//! # a comment
+//! include /api/*
+//! exclude /api/internal/*
+//! respond 204 optional body
+//! ```
+//!
+//! Patterns are globs matched against the path, without its query string,
+//! and `*` matches across `/`. A path matching any `exclude` pattern is
+//! excluded, even if it also matches an `include` pattern. When there are
+//! `include` patterns, paths matching none of them are excluded too.
+//!
+//! Excluded requests are answered locally, with a 404 unless a `respond`
+//! line gives another status and body.
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+
+#[derive(Debug, Clone)]
+pub struct RouteFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+    status: StatusCode,
+    body: String,
+}
+
+impl RouteFilter {
+    pub fn load(path: &Path) -> Result<RouteFilter> {
+        let filter = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read --route-filter {}: {}", path.display(), e))?;
+        RouteFilter::parse(&filter)
+            .map_err(|e| anyhow!("Invalid --route-filter {}: {}", path.display(), e))
+    }
+
+    fn parse(filter: &str) -> Result<RouteFilter> {
+        let mut include = GlobSetBuilder::new();
+        let mut exclude = GlobSetBuilder::new();
+        let mut status = StatusCode::NOT_FOUND;
+        let mut body = String::new();
+
+        for (i, line) in filter.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+            match directive {
+                "include" => {
+                    include.add(glob(rest, i)?);
+                }
+                "exclude" => {
+                    exclude.add(glob(rest, i)?);
+                }
+                "respond" => {
+                    let (code, text) = rest.split_once(' ').unwrap_or((rest, ""));
+                    status = code
+                        .parse()
+                        .map_err(|_| anyhow!("line {}: {} is not a status code", i + 1, code))?;
+                    body = text.trim().to_string();
+                }
+                _ => anyhow::bail!(
+                    "line {}: expected include, exclude or respond, found {}",
+                    i + 1,
+                    directive
+                ),
+            }
+        }
+
+        Ok(RouteFilter {
+            include: include.build()?,
+            exclude: exclude.build()?,
+            status,
+            body,
+        })
+    }
+
+    /// whether a request for `path` should be sent to the preview service
+    pub fn allows(&self, path: &str) -> bool {
+        let path = path.split('?').next().unwrap_or("");
+        if self.exclude.is_match(path) {
+            return false;
+        }
+        self.include.is_empty() || self.include.is_match(path)
+    }
+
+    /// the local response to a request that isn't sent to the preview service
+    pub fn response(&self) -> Response<Body> {
+        let mut resp = Response::new(Body::from(self.body.clone()));
+        *resp.status_mut() = self.status;
+        resp.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        resp
+    }
+}
+
+fn glob(pattern: &str, line: usize) -> Result<Glob> {
+    if pattern.is_empty() {
+        anyhow::bail!("line {}: expected a pattern", line + 1)
+    }
+    Glob::new(pattern).map_err(|e| anyhow!("line {}: {}", line + 1, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excludes_take_precedence_over_includes() {
+        let filter = RouteFilter::parse(
+            "# only the API reaches the Worker\ninclude /api/*\nexclude /api/internal/*\n",
+        )
+        .unwrap();
+
+        assert!(filter.allows("/api/users?page=2"));
+        assert!(!filter.allows("/api/internal/metrics"));
+        assert!(!filter.allows("/assets/app.js"));
+    }
+
+    #[test]
+    fn everything_is_included_without_include_patterns() {
+        let filter = RouteFilter::parse("exclude /assets/*\nrespond 204").unwrap();
+
+        assert!(filter.allows("/"));
+        assert!(!filter.allows("/assets/app.js"));
+        assert_eq!(filter.response().status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn bad_filters_are_rejected() {
+        assert!(RouteFilter::parse("include").is_err());
+        assert!(RouteFilter::parse("allow /api/*").is_err());
+        assert!(RouteFilter::parse("respond nope").is_err());
+    }
+}
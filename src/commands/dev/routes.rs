@@ -0,0 +1,109 @@
+use crate::deploy::DeployTarget;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use regex::Regex;
+
+/// the routes configured for the Worker being previewed,
+/// used to tell which of them a request was matched by
+#[derive(Debug, Clone, Default)]
+pub struct Routes {
+    routes: Arc<Vec<Route>>,
+}
+
+#[derive(Debug)]
+struct Route {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Routes {
+    pub fn new(deploy_target: &DeployTarget) -> Result<Routes> {
+        let patterns = match deploy_target {
+            DeployTarget::Zoned(zoned) => zoned
+                .routes
+                .iter()
+                .map(|route| route.pattern.as_str())
+                .collect(),
+            _ => Vec::new(),
+        };
+        Routes::from_patterns(&patterns)
+    }
+
+    fn from_patterns(patterns: &[&str]) -> Result<Routes> {
+        let routes = patterns
+            .iter()
+            .map(|pattern| {
+                Ok(Route {
+                    pattern: pattern.to_string(),
+                    regex: route_regex(pattern)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Routes {
+            routes: Arc::new(routes),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.routes.iter().map(|route| route.pattern.as_str())
+    }
+
+    /// the pattern of the route a request to `host` and `path` is matched by.
+    /// When more than one route matches, the longest and so most specific one wins
+    pub fn matched(&self, host: &str, path: &str) -> Option<&str> {
+        let path = path.split('?').next().unwrap_or("");
+        let url = format!("{}{}", strip_scheme(host), path);
+        self.routes
+            .iter()
+            .filter(|route| route.regex.is_match(&url))
+            .max_by_key(|route| route.pattern.len())
+            .map(|route| route.pattern.as_str())
+    }
+}
+
+/// route patterns are a host and a path with `*` wildcards,
+/// where the scheme is optional and ignored
+fn route_regex(pattern: &str) -> Result<Regex> {
+    let pattern = strip_scheme(pattern);
+    let pattern = regex::escape(pattern).replace(r"\*", ".*");
+    Ok(Regex::new(&format!("^{}$", pattern))?)
+}
+
+fn strip_scheme(url: &str) -> &str {
+    url.find("://").map_or(url, |i| &url[i + 3..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_most_specific_route_is_matched() {
+        let routes = Routes::from_patterns(&["example.com/*", "example.com/api/*"]).unwrap();
+
+        assert_eq!(
+            routes.matched("example.com", "/api/users?page=2"),
+            Some("example.com/api/*")
+        );
+        assert_eq!(routes.matched("example.com", "/"), Some("example.com/*"));
+        assert_eq!(routes.matched("other.com", "/"), None);
+    }
+
+    #[test]
+    fn wildcard_subdomains_and_schemes_are_matched() {
+        let routes = Routes::from_patterns(&["https://*.example.com/*"]).unwrap();
+
+        assert_eq!(
+            routes.matched("www.example.com", "/"),
+            Some("https://*.example.com/*")
+        );
+        assert_eq!(routes.matched("example.org", "/"), None);
+    }
+}
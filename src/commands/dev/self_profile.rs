@@ -0,0 +1,224 @@
+//! `--self-profile <file>` measures how much of each request's time is spent
+//! in wrangler dev itself rather than waiting on the preview service, to answer
+//! whether it's dev or the network that's slow. When the session ends, the
+//! totals are written to the file as folded stacks, in microseconds, which
+//! `flamegraph.pl` and `inferno-flamegraph` turn into a flame graph:
+//!
+//! ```text
+//! wrangler_dev;handle 48210
+//! wrangler_dev;upstream 112
+//! wrangler_dev;upstream;destructure_response 1930
+//! wrangler_dev;upstream;preview_request 5210334
+//! wrangler_dev;upstream;structure_request 2841
+//! ```
+//!
+//! `handle` is everything dev does to a request and its response outside of
+//! sending it upstream, and `preview_request` is the wait on the preview
+//! service. A summary of the two is printed as well. Timings are only taken
+//! with the flag, so without it a request costs no more than a check of it.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures_util::future::{BoxFuture, FutureExt};
+use once_cell::sync::OnceCell;
+
+const ROOT: &str = "wrangler_dev";
+pub(super) const HANDLE: &str = "wrangler_dev;handle";
+pub(super) const UPSTREAM: &str = "wrangler_dev;upstream";
+pub(super) const STRUCTURE_REQUEST: &str = "wrangler_dev;upstream;structure_request";
+pub(super) const PREVIEW_REQUEST: &str = "wrangler_dev;upstream;preview_request";
+pub(super) const DESTRUCTURE_RESPONSE: &str = "wrangler_dev;upstream;destructure_response";
+
+static PROFILER: OnceCell<Profiler> = OnceCell::new();
+
+struct Profiler {
+    path: PathBuf,
+    profile: Mutex<Profile>,
+}
+
+/// the time spent in each stack, over every request
+#[derive(Debug, Default)]
+struct Profile {
+    requests: u64,
+    totals: BTreeMap<&'static str, Duration>,
+}
+
+/// start profiling requests, to be written to `path` by `save`
+pub fn init(path: &Path) {
+    let profiler = Profiler {
+        path: path.to_path_buf(),
+        profile: Mutex::new(Profile::default()),
+    };
+    if PROFILER.set(profiler).is_err() {
+        log::debug!("Self profiling was already started");
+    }
+}
+
+/// a stack being timed, which is counted once it's dropped
+pub(super) struct Span {
+    stack: &'static str,
+    started: Instant,
+}
+
+impl Span {
+    /// starts timing `stack`, if profiling
+    pub(super) fn enter(stack: &'static str) -> Option<Span> {
+        PROFILER.get()?;
+        Some(Span {
+            stack,
+            started: Instant::now(),
+        })
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        record(self.stack, self.started.elapsed());
+    }
+}
+
+/// times `sending` a request as waiting on the preview service, if profiling
+pub(super) fn waiting<T: Send + 'static>(sending: BoxFuture<'static, T>) -> BoxFuture<'static, T> {
+    match Span::enter(PREVIEW_REQUEST) {
+        Some(span) => async move {
+            let sent = sending.await;
+            drop(span);
+            sent
+        }
+        .boxed(),
+        None => sending,
+    }
+}
+
+/// counts `took` towards the time spent in `stack`, if profiling
+pub(super) fn record(stack: &'static str, took: Duration) {
+    if let Some(profiler) = PROFILER.get() {
+        profiler.profile.lock().unwrap().add(stack, took);
+    }
+}
+
+/// counts a request that's been handled, if profiling
+pub(super) fn finish_request() {
+    if let Some(profiler) = PROFILER.get() {
+        profiler.profile.lock().unwrap().requests += 1;
+    }
+}
+
+/// write the profile to its file, if profiling
+///
+/// returns a summary of where the time went
+pub fn save() -> Result<Option<String>> {
+    if let Some(profiler) = PROFILER.get() {
+        let profile = profiler.profile.lock().unwrap();
+        fs::write(&profiler.path, profile.folded())?;
+        Ok(Some(profile.summary()))
+    } else {
+        Ok(None)
+    }
+}
+
+impl Profile {
+    fn add(&mut self, stack: &'static str, took: Duration) {
+        *self.totals.entry(stack).or_default() += took;
+    }
+
+    fn total(&self, stack: &str) -> Duration {
+        self.totals.get(stack).copied().unwrap_or_default()
+    }
+
+    /// the time spent in each stack itself, leaving out the stacks inside it
+    fn self_time(&self, stack: &str) -> Duration {
+        let prefix = format!("{};", stack);
+        let inside: Duration = self
+            .totals
+            .iter()
+            .filter(|(other, _)| {
+                other
+                    .strip_prefix(&prefix)
+                    .map_or(false, |rest| !rest.contains(';'))
+            })
+            .map(|(_, took)| *took)
+            .sum();
+        self.total(stack).checked_sub(inside).unwrap_or_default()
+    }
+
+    /// every stack with the microseconds spent in it, in the folded format flame graph tools read
+    fn folded(&self) -> String {
+        self.totals
+            .keys()
+            .map(|stack| format!("{} {}\n", stack, self.self_time(stack).as_micros()))
+            .collect()
+    }
+
+    fn summary(&self) -> String {
+        let waiting = self.total(PREVIEW_REQUEST);
+        let own: Duration = self
+            .totals
+            .keys()
+            .filter(|stack| stack.starts_with(ROOT) && **stack != PREVIEW_REQUEST)
+            .map(|stack| self.self_time(stack))
+            .sum();
+        let per_request = |took: Duration| {
+            if self.requests == 0 {
+                0.0
+            } else {
+                took.as_secs_f64() * 1000.0 / self.requests as f64
+            }
+        };
+        format!(
+            "Over {} requests, wrangler dev spent {:.2}ms per request on its own processing \
+            and {:.2}ms waiting on the preview service",
+            self.requests,
+            per_request(own),
+            per_request(waiting)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> Profile {
+        let mut profile = Profile::default();
+        for _ in 0..2 {
+            profile.requests += 1;
+            profile.add(HANDLE, Duration::from_millis(2));
+            profile.add(UPSTREAM, Duration::from_millis(105));
+            profile.add(STRUCTURE_REQUEST, Duration::from_millis(1));
+            profile.add(PREVIEW_REQUEST, Duration::from_millis(100));
+            profile.add(DESTRUCTURE_RESPONSE, Duration::from_millis(1));
+        }
+        profile
+    }
+
+    #[test]
+    fn stacks_are_folded_with_their_own_time() {
+        assert_eq!(
+            profile().folded(),
+            "wrangler_dev;handle 4000\n\
+            wrangler_dev;upstream 6000\n\
+            wrangler_dev;upstream;destructure_response 2000\n\
+            wrangler_dev;upstream;preview_request 200000\n\
+            wrangler_dev;upstream;structure_request 2000\n"
+        );
+    }
+
+    #[test]
+    fn waiting_upstream_is_told_apart_from_processing() {
+        assert_eq!(
+            profile().summary(),
+            "Over 2 requests, wrangler dev spent 7.00ms per request on its own processing \
+            and 100.00ms waiting on the preview service"
+        );
+        assert_eq!(
+            Profile::default().summary(),
+            "Over 0 requests, wrangler dev spent 0.00ms per request on its own processing \
+            and 0.00ms waiting on the preview service"
+        );
+    }
+}
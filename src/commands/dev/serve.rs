@@ -0,0 +1,1232 @@
+use crate::commands::dev::access_log::{self, LogFormat};
+use crate::commands::dev::allowed_methods;
+use crate::commands::dev::base_path;
+use crate::commands::dev::body_timeout::{self, BodyTimeout};
+use crate::commands::dev::cf;
+use crate::commands::dev::coalesce;
+use crate::commands::dev::compress;
+use crate::commands::dev::cors;
+use crate::commands::dev::diff;
+use crate::commands::dev::echo_request;
+use crate::commands::dev::error_response;
+use crate::commands::dev::events::{self, Event};
+use crate::commands::dev::fail_path;
+use crate::commands::dev::favicon::{self, Favicon};
+use crate::commands::dev::har;
+use crate::commands::dev::heartbeat;
+use crate::commands::dev::hop_by_hop;
+use crate::commands::dev::internal;
+use crate::commands::dev::latency;
+use crate::commands::dev::local_static;
+use crate::commands::dev::log_sink::{self, Entry};
+use crate::commands::dev::log_status;
+use crate::commands::dev::loop_guard;
+use crate::commands::dev::mixed_content;
+use crate::commands::dev::once;
+use crate::commands::dev::original_host;
+use crate::commands::dev::rebuild;
+use crate::commands::dev::replace;
+use crate::commands::dev::request_body::{self, Buffered, ChunkedBodies};
+use crate::commands::dev::response_body;
+use crate::commands::dev::response_headers;
+use crate::commands::dev::response_size;
+use crate::commands::dev::response_time;
+use crate::commands::dev::resume::{self, RequestTemplate, Resend};
+use crate::commands::dev::self_profile;
+use crate::commands::dev::stats;
+use crate::commands::dev::strict_host;
+use crate::commands::dev::trace_context;
+use crate::commands::dev::tui;
+use crate::commands::dev::upstream::peer_addr;
+use crate::commands::dev::upstream_timeout;
+use crate::commands::dev::utils::{get_path_as_str, rewrite_redirect};
+use crate::commands::dev::verbosity::{self, Verbosity};
+use crate::commands::dev::worker_logs;
+use crate::commands::dev::{DevOptions, ServerConfig};
+use crate::http::feature::get_user_agent;
+use crate::terminal::message::{Message, StdErr};
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use futures_util::FutureExt;
+use hyper::body::HttpBody;
+use hyper::header::HeaderName;
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_LENGTH, ETAG, EXPIRES,
+    LAST_MODIFIED, ORIGIN, TRANSFER_ENCODING, USER_AGENT,
+};
+use hyper::{Body, Method, Request, Response, StatusCode, Version};
+
+const UPSTREAM_VERSION: Version = Version::HTTP_11;
+
+/// not one of the headers hyper has a constant for
+const SERVER_TIMING: &str = "server-timing";
+
+/// handles a single request to `wrangler dev`, doing everything that
+/// does not depend on which preview service the request is routed to
+///
+/// `upstream` sends the request along to the Workers runtime, and
+/// `host` is the host that request is sent to. `upstream` may be called
+/// again to resume a response that was cut off part way through
+pub(super) async fn handle<F, Fut>(
+    mut req: Request<Body>,
+    server_config: &ServerConfig,
+    host: &str,
+    https: bool,
+    upstream: F,
+) -> Result<Response<Body>>
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<Response<Body>>> + Send + 'static,
+{
+    if let Some(resp) = reject_oversized_headers(&req, server_config.options.max_header_size()) {
+        return Ok(resp);
+    }
+    if let Some(resp) = loop_guard::guard(&mut req) {
+        return Ok(resp);
+    }
+
+    // wrangler's own endpoints are answered before anything is sent upstream
+    let prefix = server_config.options.internal_prefix();
+    let mut echo = false;
+    if let Some(endpoint) = internal::endpoint(req.uri().path(), prefix).map(str::to_string) {
+        // except a request to be echoed, which goes through everything the path it names would
+        echo = echo_request::start(&endpoint, &mut req, &server_config.options);
+        if !echo {
+            return internal::handle_internal(&endpoint, req).await;
+        }
+    }
+
+    let version = req.version();
+
+    // record the time of the request
+    let now: DateTime<Local> = Local::now();
+    let start = Instant::now();
+
+    let local_host = format!(
+        "{}:{}",
+        server_config.listening_address.ip().to_string(),
+        server_config.listening_address.port().to_string()
+    );
+
+    let req_method = req.method().to_string();
+    let is_head = req.method() == Method::HEAD;
+    let request_size = content_length(req.headers());
+
+    // parse the path so we can send it to the preview service
+    // we don't want to send "localhost:8787/path", just "/path"
+    let path = get_path_as_str(req.uri());
+    let logged = Logged {
+        now,
+        method: &req_method,
+        host,
+        path: &path,
+        version,
+        client: access_log::Client::of(&req),
+        options: &server_config.options,
+    };
+
+    // requests dev answers itself are logged like any other, with a note saying why
+    let no_cache = server_config.options.no_cache_responses;
+    let answer_locally = |mut resp: Response<Body>, note: &str| -> Result<Response<Body>> {
+        if no_cache {
+            no_store(resp.headers_mut());
+        }
+        log_request(&logged, &resp, &[note.to_string()]);
+        stats::record(&path, resp.status(), start.elapsed());
+        Ok(resp)
+    };
+
+    // with --strict-host, requests for hosts dev doesn't expect never reach the Worker
+    if let Some(resp) = strict_host::check(&req, server_config) {
+        return answer_locally(resp, "--strict-host");
+    }
+
+    // paths given to --fail-path are down, whatever the Worker would say
+    if let Some(resp) = fail_path::respond(&server_config.options.fail_path, &path) {
+        return answer_locally(resp, "--fail-path");
+    }
+
+    // with --cors, preflights never reach the Worker
+    let cors = server_config.options.cors;
+    if cors && cors::is_preflight(&req) {
+        let resp = cors::preflight(&req, &server_config.options);
+        return answer_locally(resp, "cors preflight");
+    }
+    let origin = req.headers().get(ORIGIN).cloned();
+    let accept_encoding = req.headers().get(ACCEPT_ENCODING).cloned();
+
+    let allowed = &server_config.options.allowed_methods;
+    if let Some(resp) = allowed_methods::reject(req.method(), &path, allowed) {
+        return answer_locally(resp, "method not allowed");
+    }
+    if let Some(resp) = allowed_methods::trace(&req, server_config.options.allow_trace) {
+        return answer_locally(resp, "trace");
+    }
+
+    // with --favicon, the browser's requests for an icon never reach the Worker
+    if let Some(favicon) = &server_config.options.favicon {
+        if favicon::is_favicon(&req) {
+            let resp = favicon::serve(favicon, &req, &server_config.options.mime)?;
+            if *favicon == Favicon::None {
+                return Ok(resp);
+            }
+            return answer_locally(resp, "local favicon");
+        }
+    }
+
+    // files in the Workers Sites bucket are served without involving the Worker
+    if let Some(root) = &server_config.static_root {
+        if let Some(resp) = local_static::serve(
+            root,
+            &req,
+            &server_config.options.mime,
+            server_config.options.static_max_age,
+        )? {
+            return answer_locally(resp, "local static");
+        }
+    }
+
+    // paths excluded by --route-filter never reach the preview service
+    if let Some(route_filter) = &server_config.route_filter {
+        if !route_filter.allows(&path) {
+            let resp = route_filter.response();
+            return answer_locally(resp, "excluded by --route-filter");
+        }
+    }
+
+    // the preview is being replaced, so there's nothing reliable to send the request to
+    if server_config.options.rebuild_placeholder && rebuild::in_progress() {
+        let resp = rebuild::placeholder();
+        return answer_locally(resp, "rebuilding");
+    }
+
+    // with --base-path, the Worker sees paths without the prefix they're requested under
+    let mut stripped_base_path = None;
+    if let Some(prefix) = server_config.options.base_path.as_deref() {
+        match base_path::strip(req.uri(), prefix) {
+            Some(uri) => {
+                *req.uri_mut() = uri;
+                stripped_base_path = Some(prefix);
+            }
+            None if server_config.options.base_path_passthrough => {}
+            None => return answer_locally(base_path::not_found(prefix), "outside --base-path"),
+        }
+    }
+
+    let request_id = events::next_request_id();
+    events::emit(Event::Request {
+        id: request_id,
+        method: req_method.clone(),
+        url: format!("{}{}", host, path),
+    });
+    // the Worker's console calls are tagged with the requests in flight when they're made
+    let _in_flight = worker_logs::InFlight::start(request_id);
+
+    set_user_agent(req.headers_mut(), server_config.options.user_agent.as_ref())?;
+    if let Some(properties) = &server_config.cf {
+        cf::inject(req.headers_mut(), properties);
+    }
+    if server_config.options.no_preview_header_overwrite {
+        original_host::preserve(&mut req, host);
+    }
+    let trace_id = if server_config.options.trace_context {
+        trace_context::inject(req.headers_mut())
+    } else {
+        None
+    };
+
+    let downgraded = set_upstream_version(&mut req);
+
+    // some preview services mishandle HEAD, so it can be sent as a GET and the body dropped here
+    let head_as_get = server_config.options.head_as_get && req.method() == Method::HEAD;
+    if head_as_get {
+        *req.method_mut() = Method::GET;
+    }
+
+    let chunked = request_body::is_chunked(&req);
+    if chunked {
+        request_body::strip_framing(&mut req);
+    }
+
+    let body_read_timeout = server_config.options.body_read_timeout();
+    let (req, body_timeout) = BodyTimeout::guard(req, body_read_timeout);
+
+    let buffer = server_config.options.buffer_request_body
+        || (chunked && server_config.options.chunked_request_bodies == ChunkedBodies::Buffer);
+    let req = if buffer && !req.body().is_end_stream() {
+        match request_body::buffer(req, request_body::MAX_BUFFERED).await {
+            Ok(Buffered::Complete(req)) => req,
+            Ok(Buffered::TooLarge(resp)) => return answer_locally(resp, "request body too large"),
+            Err(_) if body_timeout.timed_out() => {
+                return Ok(body_timeout::request_timeout(
+                    &req_method,
+                    &path,
+                    body_read_timeout,
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+    } else {
+        req
+    };
+
+    let resend = RequestTemplate::of(&req).map(|template| {
+        let upstream = upstream.clone();
+        Box::new(move |offset| upstream(template.build(offset)).boxed()) as Resend
+    });
+
+    // an echoed request is answered by dev, so there's no exchange with upstream to record
+    let (req, recording) = if server_config.options.har.is_some() && !echo {
+        let scheme = if https { "https" } else { "http" };
+        let url = format!("{}://{}{}", scheme, local_host, path);
+        let (req, recording) = har::Recording::start(req, url, now);
+        (req, Some(recording))
+    } else {
+        (req, None)
+    };
+
+    // with -v, what is sent upstream is shown below the log line
+    let verbosity = server_config.options.verbosity;
+    let (req, shown_request) = match verbosity::request(req, verbosity).await {
+        Ok(shown) => shown,
+        Err(_) if body_timeout.timed_out() => {
+            return Ok(body_timeout::request_timeout(
+                &req_method,
+                &path,
+                body_read_timeout,
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+
+    let coalesce_key = if server_config.options.coalesce && !echo {
+        coalesce::key(&req, host)
+    } else {
+        None
+    };
+
+    // held until the preview service responds, with --upstream-concurrency,
+    // by every request but an echoed one, which is never sent
+    let permit = match &server_config.upstream_limit {
+        Some(_) if echo => None,
+        Some(limit) => match limit.acquire(&format!("{} {}", req_method, path)).await {
+            Ok(permit) => Some(permit),
+            Err(resp) => return answer_locally(resp, "upstream queue timed out"),
+        },
+        None => None,
+    };
+
+    // send the request to the preview service, or wait on an identical one that already was
+    let sent_at = Instant::now();
+    // an echoed request isn't waiting on upstream, so it can't time out
+    let read_timeout = if echo {
+        None
+    } else {
+        server_config.options.read_timeout()
+    };
+    let responding = upstream_timeout::guard(read_timeout, upstream(req));
+    let sent = match coalesce_key {
+        Some(key) => coalesce::fetch(key, responding).await,
+        None => responding.await.map(|resp| (resp, false)),
+    };
+    let upstream_latency = sent_at.elapsed();
+    let responded_at = Instant::now();
+    drop(permit);
+    let (mut resp, coalesced) = match sent {
+        Ok(sent) => sent,
+        // the upstream request failed because its body was cut off
+        Err(_) if body_timeout.timed_out() => {
+            return Ok(body_timeout::request_timeout(
+                &req_method,
+                &path,
+                body_read_timeout,
+            ))
+        }
+        Err(e) => {
+            stats::record_upstream_error();
+            events::emit(Event::Error {
+                message: e.to_string(),
+            });
+            return Err(e);
+        }
+    };
+
+    if echo_request::is_echo(&resp) {
+        return answer_locally(resp, "echoed with --echo-request");
+    }
+
+    let upstream_addr = if server_config.options.log_upstream_ip {
+        peer_addr(&resp)
+    } else {
+        None
+    };
+
+    resp.headers_mut().remove(loop_guard::HOPS_HEADER);
+    // the client's connection is dev's to manage, whatever upstream said about its own
+    let status = resp.status();
+    hop_by_hop::strip(status, resp.headers_mut());
+    strip_headers(
+        resp.headers_mut(),
+        &server_config.options.strip_response_header,
+    );
+    // headers too large for real clients are caught before dev adds any of its own
+    let description = format!("{} {}{}", req_method, host, path);
+    let response_headers = (resp.headers().len(), headers_size(resp.headers()));
+    response_headers::warn_if_near_limit(
+        response_headers.1,
+        server_config.options.max_response_header_size(),
+        &description,
+    );
+    resp = response_headers::guard(
+        resp,
+        server_config.options.max_response_header_size(),
+        server_config.options.oversized_response_headers,
+        &description,
+    );
+    if server_config.options.server_timing {
+        add_server_timing(resp.headers_mut(), upstream_latency);
+    }
+    rewrite_redirect(&mut resp, host, &local_host, https);
+    if let Some(prefix) = stripped_base_path {
+        base_path::restore_location(resp.headers_mut(), prefix);
+    }
+    if cors {
+        cors::allow(&mut resp, origin.as_ref(), &server_config.options);
+    }
+    if no_cache {
+        no_store(resp.headers_mut());
+    }
+    if head_as_get {
+        resp = strip_body(resp).await?;
+    } else {
+        if let Some(limit) = server_config.options.warn_response_size {
+            resp = response_size::watch(resp, limit, description.clone());
+        }
+        if let Some(max) = server_config.options.max_response_time() {
+            resp = response_time::cap(resp, max, description.clone());
+        }
+        resp = resume::forward(resp, resend, description.clone());
+        // the body of a HEAD response or a byte range isn't one to rewrite
+        if response_body::can_replace(is_head, &resp) {
+            resp = replace::apply(resp, &server_config.options.replace).await?;
+            resp = replace::rewrite_hosts(resp, &server_config.options.rewrite_host).await?;
+            // only an https page has its http subresources blocked
+            if https && server_config.options.warn_mixed_content {
+                resp = mixed_content::warn(resp, &description).await?;
+            }
+            if server_config.options.compress {
+                resp = compress::gzip(resp, accept_encoding.as_ref()).await?;
+            }
+        }
+        if let Some(interval) = server_config.options.keepalive_interval() {
+            resp = heartbeat::inject(resp, interval);
+        }
+    }
+
+    // notes shown after the log line, explaining anything dev did to the request
+    let mut notes = Vec::new();
+    if !server_config.routes.is_empty() {
+        let pattern = server_config.routes.matched(host, &path);
+        notes.push(format!("route {}", pattern.unwrap_or("default")));
+    }
+
+    if coalesced {
+        notes.push("coalesced".to_string());
+    }
+
+    if let Some(addr) = upstream_addr {
+        notes.push(format!("upstream {}", addr.ip()));
+    }
+
+    if downgraded {
+        notes.push(format!("sent upstream as {:?}", UPSTREAM_VERSION));
+    }
+
+    if verbosity >= Verbosity::Headers {
+        let (count, size) = response_headers;
+        notes.push(format!("{} response headers, {} bytes", count, size));
+    }
+
+    if let Some(trace_id) = trace_id {
+        notes.push(format!("trace {}", trace_id));
+    }
+
+    if worker_logs::is_enabled() {
+        notes.push(format!("#{}", request_id));
+    }
+
+    // with --self-profile, what dev does itself is told apart from the wait upstream
+    self_profile::record(self_profile::UPSTREAM, upstream_latency);
+    self_profile::record(
+        self_profile::HANDLE,
+        start
+            .elapsed()
+            .checked_sub(upstream_latency)
+            .unwrap_or_default(),
+    );
+    self_profile::finish_request();
+
+    // hold the response as if the Worker had spent this long computing it
+    if let Some(simulate_cpu) = server_config.options.simulate_cpu {
+        tokio::time::sleep(Duration::from_millis(simulate_cpu)).await;
+        notes.push(format!("+{}ms simulated CPU time", simulate_cpu));
+    }
+
+    // hold the response as if it came over a slow network
+    let options = &server_config.options;
+    let (delayed, note) = latency::inject(
+        resp,
+        options.chaos_ttfb,
+        options.chaos_latency,
+        responded_at,
+    )
+    .await?;
+    resp = delayed;
+    notes.extend(note);
+
+    let (shown, shown_response) = verbosity::response(resp, verbosity).await?;
+    resp = shown;
+
+    // what's shown of a request goes with its line, so it's left out when the line is
+    if log_request(&logged, &resp, &notes) {
+        verbosity::print(shown_request, shown_response);
+    }
+    stats::record(&path, resp.status(), start.elapsed());
+    stats::record_sizes(request_size, content_length(resp.headers()));
+    events::emit(Event::Response {
+        id: request_id,
+        status: resp.status().as_u16(),
+        duration_ms: start.elapsed().as_millis() as u64,
+    });
+
+    if let Some(recording) = recording {
+        resp = recording.finish(resp);
+    }
+
+    if server_config.options.diff.is_some() {
+        resp = diff::compare(&req_method, &path, resp);
+    }
+
+    if server_config.options.once {
+        resp = once::watch(resp);
+    }
+
+    Ok(resp)
+}
+
+/// what a request's log line says about the request itself
+struct Logged<'a> {
+    now: DateTime<Local>,
+    method: &'a str,
+    host: &'a str,
+    path: &'a str,
+    version: Version,
+    client: access_log::Client,
+    options: &'a DevOptions,
+}
+
+/// print information about the response, followed by any notes on what dev did to it
+/// [2020-04-20 15:25:54] GET example.com/ HTTP/1.1 200 OK (route example.com/*)
+///
+/// returns whether a line was printed, which `--log-status` and `--quiet` can prevent
+fn log_request(logged: &Logged, resp: &Response<Body>, notes: &[String]) -> bool {
+    let Logged {
+        now,
+        method,
+        host,
+        path,
+        version,
+        ..
+    } = *logged;
+    let status = resp.status();
+    log_sink::send(Entry {
+        timestamp: now.to_rfc3339(),
+        method: method.to_string(),
+        url: format!("{}{}", host, path),
+        version: format!("{:?}", version),
+        status: status.as_u16(),
+        notes: notes.to_vec(),
+    });
+
+    // the dashboard shows requests itself
+    if tui::is_active() || !log_status::is_shown(logged.options, status) {
+        return false;
+    }
+    let format = logged.options.log_format;
+    if format != LogFormat::Pretty {
+        println!(
+            "{}",
+            access_log::line(
+                format,
+                &now,
+                &logged.client,
+                &format!("{} {} {:?}", method, path, version),
+                status,
+                content_length(resp.headers()),
+            )
+        );
+        return true;
+    }
+    let notes = if notes.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", notes.join(", "))
+    };
+    println!(
+        "[{}] {} {}{} {:?} {}{}",
+        now.format("%Y-%m-%d %H:%M:%S"),
+        method,
+        host,
+        path,
+        version,
+        status,
+        notes
+    );
+    true
+}
+
+/// turns the response to a GET into the response to a HEAD, keeping its length
+async fn strip_body(resp: Response<Body>) -> Result<Response<Body>> {
+    let (mut parts, body) = resp.into_parts();
+    if !parts.headers.contains_key(CONTENT_LENGTH) {
+        // the length is only known by reading the body
+        let body = hyper::body::to_bytes(body).await?;
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+    }
+    Ok(Response::from_parts(parts, Body::empty()))
+}
+
+/// every request reaches the preview service over HTTP/1.1, as that's all its client
+/// speaks, so requests made with HTTP/2 are downgraded rather than failing.
+/// HTTP/1.0 is upgraded too, so what the Worker sees doesn't depend on the client
+fn set_upstream_version(req: &mut Request<Body>) -> bool {
+    let version = req.version();
+    if version == UPSTREAM_VERSION {
+        return false;
+    }
+    log::info!(
+        "Sending a {:?} request upstream as {:?}",
+        version,
+        UPSTREAM_VERSION
+    );
+    *req.version_mut() = UPSTREAM_VERSION;
+    version == Version::HTTP_2 || version == Version::HTTP_3
+}
+
+/// `--no-cache-responses` stops the browser from keeping a response, so it never shows
+/// what an earlier version of the Worker returned. The validators go too, as there
+/// is nothing cached for them to revalidate
+fn no_store(headers: &mut HeaderMap) {
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.remove(ETAG);
+    headers.remove(LAST_MODIFIED);
+    headers.remove(EXPIRES);
+}
+
+/// `--strip-response-header` takes headers the Worker sets off its responses, like an
+/// `X-Powered-By` or a `Content-Security-Policy` that gets in the way locally
+fn strip_headers(headers: &mut HeaderMap, names: &[HeaderName]) {
+    for name in names {
+        headers.remove(name);
+    }
+}
+
+/// the size of a body, if it is known before reading it
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// `--server-timing` shows how long the preview service took to respond in the
+/// browser's network panel, alongside any Server-Timing the Worker sent itself
+fn add_server_timing(headers: &mut HeaderMap, upstream_latency: Duration) {
+    let timing = format!(
+        "upstream;dur={};desc=\"wrangler dev preview\"",
+        upstream_latency.as_millis()
+    );
+    if let Ok(timing) = HeaderValue::from_str(&timing) {
+        headers.append(HeaderName::from_static(SERVER_TIMING), timing);
+    }
+}
+
+/// `--user-agent` replaces the User-Agent sent upstream, removing it when empty,
+/// and requests without one are sent with wrangler's own
+fn set_user_agent(headers: &mut HeaderMap, user_agent: Option<&HeaderValue>) -> Result<()> {
+    match user_agent {
+        Some(user_agent) if user_agent.is_empty() => {
+            headers.remove(USER_AGENT);
+        }
+        Some(user_agent) => {
+            headers.insert(USER_AGENT, user_agent.clone());
+        }
+        None => {
+            if !headers.contains_key(USER_AGENT) {
+                headers.insert(USER_AGENT, HeaderValue::from_str(&get_user_agent(None))?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the size hyper's read buffer is capped at for a given header limit, leaving 8KiB
+/// for the request line, which also keeps it above hyper's own minimum of 8KiB.
+/// Anything larger is rejected by hyper itself, with a bare 431
+pub(super) fn max_buf_size(server_config: &ServerConfig) -> usize {
+    server_config.options.max_header_size() + 8 * 1024
+}
+
+/// builds a 431 response if the request headers add up to more than `limit` bytes
+fn reject_oversized_headers(req: &Request<Body>, limit: usize) -> Option<Response<Body>> {
+    let size = headers_size(req.headers());
+    if size <= limit {
+        return None;
+    }
+
+    StdErr::warn(&format!(
+        "Rejected {} {} with a 431, its headers are {} bytes which is over the limit of {} bytes set by --max-header-size",
+        req.method(),
+        get_path_as_str(req.uri()),
+        size,
+        limit
+    ));
+
+    Some(error_response::build(
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+        format!(
+            "Request headers are {} bytes, which is over the {} byte limit of wrangler dev",
+            size, limit
+        ),
+    ))
+}
+
+/// the size of the headers as they were sent, `name: value\r\n` for each one
+pub(super) fn headers_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::dev::{DevOptions, Protocol};
+    use hyper::header::HOST;
+
+    fn server_config(options: DevOptions) -> ServerConfig {
+        let ip = "127.0.0.1".parse().unwrap();
+        ServerConfig::new(None, ip, 0, Protocol::Https, options).unwrap()
+    }
+
+    /// an upstream that responds with the User-Agent it was sent
+    async fn echo_user_agent(req: Request<Body>) -> Result<Response<Body>> {
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .map(|user_agent| user_agent.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        Ok(Response::new(Body::from(user_agent)))
+    }
+
+    async fn upstream_user_agent(options: DevOptions, user_agent: Option<&str>) -> String {
+        let mut req = Request::get("/");
+        if let Some(user_agent) = user_agent {
+            req = req.header(USER_AGENT, user_agent);
+        }
+        let req = req.body(Body::empty()).unwrap();
+
+        let config = server_config(options);
+        let resp = handle(req, &config, "example.com", false, echo_user_agent)
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    /// an upstream that responds with the version of the request it was sent
+    async fn echo_version(req: Request<Body>) -> Result<Response<Body>> {
+        Ok(Response::new(Body::from(format!("{:?}", req.version()))))
+    }
+
+    #[tokio::test]
+    async fn requests_reach_the_upstream_as_http_1_1() {
+        let config = server_config(DevOptions::default());
+        for version in [Version::HTTP_10, Version::HTTP_11, Version::HTTP_2].iter() {
+            let req = Request::get("https://localhost:8787/")
+                .version(*version)
+                .body(Body::empty())
+                .unwrap();
+            let resp = handle(req, &config, "example.com", true, echo_version)
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(body, "HTTP/1.1", "for an {:?} request", version);
+        }
+    }
+
+    /// an upstream that responds with a response browsers would cache
+    async fn cacheable(_req: Request<Body>) -> Result<Response<Body>> {
+        let resp = Response::builder()
+            .header(CACHE_CONTROL, "public, max-age=3600")
+            .header(ETAG, "\"v1\"")
+            .body(Body::from("v1"))?;
+        Ok(resp)
+    }
+
+    #[tokio::test]
+    async fn responses_can_be_kept_out_of_the_browser_cache() {
+        let options = DevOptions {
+            no_cache_responses: true,
+            ..Default::default()
+        };
+        let config = server_config(options);
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let resp = handle(req, &config, "example.com", false, cacheable)
+            .await
+            .unwrap();
+
+        assert_eq!(resp.headers()[CACHE_CONTROL], "no-store");
+        assert!(!resp.headers().contains_key(ETAG));
+    }
+
+    #[tokio::test]
+    async fn strict_hosts_turn_away_unexpected_hosts() {
+        let options = DevOptions {
+            strict_host: true,
+            ..Default::default()
+        };
+        let config = server_config(options);
+
+        let req = Request::get("/")
+            .header(HOST, "evil.example")
+            .body(Body::empty())
+            .unwrap();
+        let resp = handle(req, &config, "example.com", false, cacheable)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::MISDIRECTED_REQUEST);
+
+        let req = Request::get("/")
+            .header(
+                HOST,
+                format!("localhost:{}", config.listening_address.port()),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let resp = handle(req, &config, "example.com", false, cacheable)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    /// an upstream that responds with headers only useful to whoever runs it
+    async fn chatty(_req: Request<Body>) -> Result<Response<Body>> {
+        let resp = Response::builder()
+            .header("x-powered-by", "Workers")
+            .header("x-debug-id", "1")
+            .header("x-debug-id", "2")
+            .header(ETAG, "\"v1\"")
+            .body(Body::empty())?;
+        Ok(resp)
+    }
+
+    #[tokio::test]
+    async fn configured_response_headers_are_stripped() {
+        let options = DevOptions {
+            strip_response_header: vec![
+                "X-Powered-By".parse().unwrap(),
+                "X-DEBUG-ID".parse().unwrap(),
+                "x-not-sent".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let config = server_config(options);
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let resp = handle(req, &config, "example.com", false, chatty)
+            .await
+            .unwrap();
+
+        assert!(!resp.headers().contains_key("x-powered-by"));
+        assert!(!resp.headers().contains_key("x-debug-id"));
+        assert_eq!(resp.headers()[ETAG], "\"v1\"");
+    }
+
+    #[tokio::test]
+    async fn user_agent_overrides_reach_the_upstream() {
+        let options = DevOptions {
+            user_agent: Some(HeaderValue::from_static("tester/1.0")),
+            ..Default::default()
+        };
+        assert_eq!(
+            upstream_user_agent(options, Some("curl/7.64.1")).await,
+            "tester/1.0"
+        );
+
+        let options = DevOptions {
+            user_agent: Some(HeaderValue::from_static("")),
+            ..Default::default()
+        };
+        assert_eq!(upstream_user_agent(options, Some("curl/7.64.1")).await, "");
+
+        assert_eq!(
+            upstream_user_agent(DevOptions::default(), Some("curl/7.64.1")).await,
+            "curl/7.64.1"
+        );
+        assert!(upstream_user_agent(DevOptions::default(), None)
+            .await
+            .starts_with("wrangler/"));
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let req = Request::get("/")
+            .header("x-huge", "a".repeat(32 * 1024))
+            .body(Body::empty())
+            .unwrap();
+        let resp = reject_oversized_headers(&req, 16 * 1024).unwrap();
+
+        assert_eq!(resp.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    #[test]
+    fn server_timing_is_added_to_the_workers_own() {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER_TIMING, HeaderValue::from_static("db;dur=53"));
+        add_server_timing(&mut headers, Duration::from_millis(142));
+
+        let timings: Vec<_> = headers.get_all(SERVER_TIMING).iter().collect();
+        assert_eq!(
+            timings,
+            vec![
+                "db;dur=53",
+                "upstream;dur=142;desc=\"wrangler dev preview\""
+            ]
+        );
+    }
+
+    #[test]
+    fn headers_under_the_limit_are_allowed() {
+        let req = Request::get("/")
+            .header("x-small", "a")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(reject_oversized_headers(&req, 16 * 1024).is_none());
+    }
+
+    /// an upstream that responds with the body it was sent
+    async fn echo_body(req: Request<Body>) -> Result<Response<Body>> {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok(Response::new(Body::from(body)))
+    }
+
+    #[tokio::test]
+    async fn slow_request_bodies_time_out() {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            // send a little of the body, then stall
+            sender.send_data("a".into()).await.ok();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            drop(sender);
+        });
+        let req = Request::post("/").body(body).unwrap();
+
+        let options = DevOptions {
+            body_read_timeout: Some(1),
+            ..Default::default()
+        };
+        let config = server_config(options);
+        let resp = handle(req, &config, "example.com", false, echo_body)
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    /// sends a two part upload through `handle`, where the second part is only sent
+    /// once the upstream has received the first, returning the size the upstream saw
+    async fn streamed_upload_size(options: DevOptions) -> String {
+        use hyper::body::HttpBody;
+        use std::sync::{Arc, Mutex};
+        use tokio::sync::oneshot;
+
+        let (started_tx, started_rx) = oneshot::channel();
+        let started_tx = Arc::new(Mutex::new(Some(started_tx)));
+
+        // if the body were buffered on the way, the upstream would never see the first part
+        // and the upload would be cut short after waiting for it
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data(vec![b'a'; 64 * 1024].into()).await.ok();
+            if tokio::time::timeout(Duration::from_secs(5), started_rx)
+                .await
+                .is_ok()
+            {
+                sender.send_data(vec![b'b'; 64 * 1024].into()).await.ok();
+            }
+        });
+        let req = Request::post("/upload").body(body).unwrap();
+
+        let upstream = move |req: Request<Body>| {
+            let started_tx = Arc::clone(&started_tx);
+            async move {
+                let mut body = req.into_body();
+                let mut size = match body.data().await {
+                    Some(chunk) => chunk?.len(),
+                    None => 0,
+                };
+                if let Some(started_tx) = started_tx.lock().unwrap().take() {
+                    started_tx.send(()).ok();
+                }
+                size += hyper::body::to_bytes(body).await?.len();
+                Ok::<_, anyhow::Error>(Response::new(Body::from(size.to_string())))
+            }
+        };
+
+        let config = server_config(options);
+        let resp = handle(req, &config, "example.com", false, upstream)
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn request_bodies_are_streamed_upstream() {
+        let uploaded = (128 * 1024).to_string();
+        assert_eq!(streamed_upload_size(DevOptions::default()).await, uploaded);
+
+        // recording a HAR file captures the body as it streams through
+        let options = DevOptions {
+            har: Some("streamed.har".into()),
+            ..Default::default()
+        };
+        assert_eq!(streamed_upload_size(options).await, uploaded);
+    }
+
+    /// an upstream that responds with how the body it was sent was framed, and its size
+    async fn echo_framing(req: Request<Body>) -> Result<Response<Body>> {
+        let header = |name| {
+            req.headers().get(name).map_or("none".to_string(), |value| {
+                value.to_str().unwrap().to_string()
+            })
+        };
+        let framing = format!("{} {}", header(CONTENT_LENGTH), header(TRANSFER_ENCODING));
+        let size = hyper::body::to_bytes(req.into_body()).await?.len();
+        Ok(Response::new(Body::from(format!("{} {}", framing, size))))
+    }
+
+    async fn chunked_upload_framing(options: DevOptions) -> String {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..4 {
+                sender.send_data(vec![b'a'; 16 * 1024].into()).await.ok();
+            }
+        });
+        let req = Request::post("/upload")
+            .header(TRANSFER_ENCODING, "chunked")
+            .body(body)
+            .unwrap();
+
+        let config = server_config(options);
+        let resp = handle(req, &config, "example.com", false, echo_framing)
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn chunked_request_bodies_reach_the_upstream_in_full() {
+        assert_eq!(
+            chunked_upload_framing(DevOptions::default()).await,
+            "none none 65536"
+        );
+        let options = DevOptions {
+            chunked_request_bodies: ChunkedBodies::Buffer,
+            ..Default::default()
+        };
+        assert_eq!(chunked_upload_framing(options).await, "65536 none 65536");
+    }
+
+    #[tokio::test]
+    async fn request_bodies_can_all_be_buffered() {
+        let options = DevOptions {
+            buffer_request_body: true,
+            ..Default::default()
+        };
+        assert_eq!(chunked_upload_framing(options).await, "65536 none 65536");
+    }
+
+    #[tokio::test]
+    async fn only_allowed_methods_are_proxied() {
+        let options = DevOptions {
+            allowed_methods: vec![Method::GET, Method::POST],
+            ..Default::default()
+        };
+        let config = server_config(options);
+
+        let delete = Request::delete("/users/1").body(Body::empty()).unwrap();
+        let resp = handle(delete, &config, "example.com", false, echo_body)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers()["allow"], "GET, POST");
+
+        let post = Request::post("/users").body(Body::from("hi")).unwrap();
+        let resp = handle(post, &config, "example.com", false, echo_body)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "hi");
+    }
+
+    #[tokio::test]
+    async fn favicons_can_be_answered_locally() {
+        let options = DevOptions {
+            favicon: Some(Favicon::None),
+            ..Default::default()
+        };
+        let config = server_config(options);
+
+        let favicon = Request::get("/favicon.ico").body(Body::empty()).unwrap();
+        let resp = handle(favicon, &config, "example.com", false, echo_version)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let page = Request::get("/").body(Body::empty()).unwrap();
+        let resp = handle(page, &config, "example.com", false, echo_version)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn trace_is_disabled_by_default() {
+        let trace = || {
+            Request::builder()
+                .method(Method::TRACE)
+                .uri("/")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let config = server_config(DevOptions::default());
+        let resp = handle(trace(), &config, "example.com", false, echo_version)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let options = DevOptions {
+            allow_trace: true,
+            ..Default::default()
+        };
+        let config = server_config(options);
+        let resp = handle(trace(), &config, "example.com", false, echo_version)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn fail_paths_are_answered_locally() {
+        let options = DevOptions {
+            fail_path: vec![fail_path::parse("/api/payments/*=503").unwrap()],
+            ..Default::default()
+        };
+        let config = server_config(options);
+
+        let req = Request::get("/api/payments/42")
+            .body(Body::empty())
+            .unwrap();
+        let resp = handle(req, &config, "example.com", false, echo_version)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let req = Request::get("/api/users").body(Body::empty()).unwrap();
+        let resp = handle(req, &config, "example.com", false, echo_version)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(hyper::body::to_bytes(resp).await.unwrap(), "HTTP/1.1");
+    }
+
+    #[tokio::test]
+    async fn upstream_connection_close_is_not_passed_on() {
+        use hyper::header::CONNECTION;
+        use hyper::service::{make_service_fn, service_fn};
+
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(service_fn(|_| async {
+                let resp = Response::builder()
+                    .header(CONNECTION, "close")
+                    .body(Body::from("all of the body"))
+                    .unwrap();
+                Ok::<_, hyper::Error>(resp)
+            }))
+        });
+        let preview = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let preview_addr = preview.local_addr();
+        tokio::spawn(preview);
+
+        let upstream = move |mut req: Request<Body>| async move {
+            *req.uri_mut() = format!("http://{}/", preview_addr).parse().unwrap();
+            Ok::<_, anyhow::Error>(hyper::Client::new().request(req).await?)
+        };
+        let config = server_config(DevOptions::default());
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let resp = handle(req, &config, "example.com", false, upstream)
+            .await
+            .unwrap();
+
+        assert!(resp.headers().get(CONNECTION).is_none());
+        assert_eq!(
+            hyper::body::to_bytes(resp).await.unwrap(),
+            "all of the body"
+        );
+    }
+
+    /// an upstream that only has a body for GETs, like a preview service that mishandles HEAD
+    async fn get_only(req: Request<Body>) -> Result<Response<Body>> {
+        let body = if req.method() == Method::GET {
+            Body::from("hello")
+        } else {
+            Body::empty()
+        };
+        Ok(Response::new(body))
+    }
+
+    #[tokio::test]
+    async fn head_as_get_keeps_the_headers_of_a_get() {
+        let options = DevOptions {
+            head_as_get: true,
+            ..Default::default()
+        };
+        let config = server_config(options);
+
+        let get = Request::get("/").body(Body::empty()).unwrap();
+        let get = handle(get, &config, "example.com", false, get_only)
+            .await
+            .unwrap();
+        let get_body = hyper::body::to_bytes(get.into_body()).await.unwrap();
+
+        let head = Request::head("/").body(Body::empty()).unwrap();
+        let head = handle(head, &config, "example.com", false, get_only)
+            .await
+            .unwrap();
+
+        assert_eq!(head.headers()[CONTENT_LENGTH], get_body.len().to_string());
+        assert!(hyper::body::to_bytes(head.into_body())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! The socket `wrangler dev` listens on.
+//!
+//! Bound to `::`, it also accepts IPv4 connections, so `localhost` works
+//! whichever address family it resolves to first. Linux usually does this
+//! already, but Windows and some BSDs only accept IPv6 on such a socket unless
+//! `IPV6_V6ONLY` is turned off. Where it can't be turned off, like OpenBSD,
+//! the socket is left IPv6 only
+//!
+//! With port 0 the OS picks a port when the address is first checked, and that
+//! listener is kept for the server, so nothing else can take the port between
+//! the two
+//!
+//! A listener bound by someone else can be handed over with `adopt`, and the
+//! server listening on its address takes it instead of binding its own. Test
+//! harnesses use this to pick a port without racing other tests for it.
+//!
+//! On Unix, `wrangler dev` also takes over a socket passed to it by systemd
+//! socket activation, or anything following the same convention: the socket is
+//! file descriptor 3, `LISTEN_FDS` is set to the number of sockets passed, of
+//! which only the first is used, and `LISTEN_PID` is set to the process id of
+//! `wrangler dev`, so a socket meant for another process isn't taken by
+//! mistake. The socket must already be bound and listening, and `--ip` and
+//! `--port` are ignored in favor of its address
+use std::env;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// how many connections may wait to be accepted, as the standard library allows
+const BACKLOG: i32 = 128;
+
+/// listeners on a port the OS picked, until the server takes them
+static RESERVED: Lazy<Mutex<Vec<TcpListener>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// checks `addr` can be listened on, returning the address that was bound. A port the
+/// OS picked is held on to until a server binds to it
+pub fn reserve(addr: &SocketAddr) -> io::Result<SocketAddr> {
+    let listener = bind(addr)?;
+    let bound = listener.local_addr()?;
+    if addr.port() == 0 {
+        RESERVED.lock().unwrap().push(listener);
+    }
+    Ok(bound)
+}
+
+/// hands `listener` to the server that will listen on its address, returning the address
+pub fn adopt(listener: TcpListener) -> io::Result<SocketAddr> {
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    RESERVED.lock().unwrap().push(listener);
+    Ok(addr)
+}
+
+/// the listener passed to `wrangler dev` with socket activation, if there is one
+#[cfg(unix)]
+pub fn inherited() -> io::Result<Option<TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    /// the first file descriptor passed, after stdin, stdout and stderr
+    const LISTEN_FDS_START: i32 = 3;
+
+    let activated = is_activated(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    // so they aren't passed on to processes wrangler starts, which would take them as their own
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    if !activated {
+        return Ok(None);
+    }
+
+    // safe as long as the convention was followed, and nothing else takes the descriptor
+    let listener = unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.local_addr()?;
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+pub fn inherited() -> io::Result<Option<TcpListener>> {
+    Ok(None)
+}
+
+/// whether sockets were passed to the process `pid`, going by `LISTEN_PID` and `LISTEN_FDS`
+#[cfg_attr(not(unix), allow(dead_code))]
+fn is_activated(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> bool {
+    let for_us = listen_pid.and_then(|listen_pid| listen_pid.parse::<u32>().ok()) == Some(pid);
+    let passed = listen_fds
+        .and_then(|listen_fds| listen_fds.parse::<u32>().ok())
+        .unwrap_or(0);
+    for_us && passed > 0
+}
+
+/// a non-blocking listener bound to `addr`, which accepts IPv4 as well when `addr` is `::`
+pub fn bind(addr: &SocketAddr) -> io::Result<TcpListener> {
+    let mut reserved = RESERVED.lock().unwrap();
+    let held = reserved
+        .iter()
+        .position(|listener| listener.local_addr().ok() == Some(*addr));
+    if let Some(held) = held {
+        return Ok(reserved.swap_remove(held));
+    }
+    drop(reserved);
+
+    let socket = Socket::new(
+        Domain::for_address(*addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        if let Err(e) = socket.set_only_v6(false) {
+            log::debug!("Could not accept IPv4 connections on {}: {}", addr, e);
+        }
+    }
+    // as the standard library does, so a port that was just closed can be reused
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+
+    socket.bind(&(*addr).into())?;
+    socket.listen(BACKLOG)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpStream};
+
+    #[test]
+    #[cfg(not(target_os = "openbsd"))]
+    fn unspecified_ipv6_accepts_ipv4_too() {
+        // not every machine running the tests has IPv6
+        let listener = match bind(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0)) {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_ok());
+    }
+
+    #[test]
+    fn a_picked_port_is_held_for_the_server() {
+        let addr = reserve(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        assert_ne!(addr.port(), 0);
+        // still listening, before the server has bound to it
+        assert!(TcpStream::connect(addr).is_ok());
+
+        let listener = bind(&addr).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn adopted_listeners_are_taken_by_the_server() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = adopt(listener).unwrap();
+        assert!(TcpStream::connect(addr).is_ok());
+
+        let listener = bind(&addr).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        // no longer held, so the next server binds its own
+        assert!(RESERVED
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|held| held.local_addr().unwrap() != addr));
+    }
+
+    #[test]
+    fn sockets_are_only_taken_when_passed_to_this_process() {
+        assert!(is_activated(Some("42"), Some("1"), 42));
+        assert!(is_activated(Some("42"), Some("2"), 42));
+        assert!(!is_activated(Some("43"), Some("1"), 42));
+        assert!(!is_activated(Some("42"), Some("0"), 42));
+        assert!(!is_activated(None, Some("1"), 42));
+        assert!(!is_activated(Some("42"), None, 42));
+    }
+}
@@ -1,17 +1,40 @@
 mod host;
+pub mod listener;
 mod protocol;
 
 pub use protocol::Protocol;
 
 use host::Host;
 
+use crate::commands::dev::cf;
+use crate::commands::dev::concurrency::UpstreamLimit;
+use crate::commands::dev::route_filter::RouteFilter;
+use crate::commands::dev::{DevOptions, Routes};
+use crate::watch::WatchScope;
+
 use anyhow::Result;
-use std::net::{IpAddr, SocketAddr, TcpListener};
+use hyper::header::HeaderValue;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
     pub host: Host,
     pub listening_address: SocketAddr,
+    pub options: Arc<DevOptions>,
+    pub routes: Routes,
+    /// where `--local-static` serves files from
+    pub static_root: Option<PathBuf>,
+    /// which requests `--route-filter` lets through to the preview service
+    pub route_filter: Option<RouteFilter>,
+    /// which changes `--watch-paths` and `--ignore-paths` let trigger a rebuild
+    pub watch_scope: WatchScope,
+    /// the `request.cf` properties given with `--cf` and its shortcuts
+    pub cf: Option<HeaderValue>,
+    /// shared by every connection, so `--upstream-concurrency` caps the total
+    pub upstream_limit: Option<Arc<UpstreamLimit>>,
 }
 
 impl ServerConfig {
@@ -20,13 +43,38 @@ impl ServerConfig {
         ip: IpAddr,
         port: u16,
         upstream_protocol: Protocol,
+        options: DevOptions,
     ) -> Result<Self> {
+        if let Some(inherited) = listener::inherited()? {
+            log::info!("Listening on a socket passed in with socket activation");
+            return ServerConfig::with_listener(host, inherited, upstream_protocol, options);
+        }
+
         let addr = SocketAddr::new(ip, port);
-        let listening_address = match TcpListener::bind(&addr) {
-            Ok(socket) => socket.local_addr(),
+        let listening_address = match listener::reserve(&addr) {
+            Ok(listening_address) => listening_address,
             Err(_) => anyhow::bail!("{} is unavailable, try binding to another address with the --port and --ip flags, or stop other `wrangler dev` processes.", &addr)
-        }?;
+        };
+        ServerConfig::listening_on(host, listening_address, upstream_protocol, options)
+    }
+
+    /// like `new`, but serving on a listener that is already bound, rather than binding one
+    pub fn with_listener(
+        host: Option<String>,
+        listener: TcpListener,
+        upstream_protocol: Protocol,
+        options: DevOptions,
+    ) -> Result<Self> {
+        let listening_address = listener::adopt(listener)?;
+        ServerConfig::listening_on(host, listening_address, upstream_protocol, options)
+    }
 
+    fn listening_on(
+        host: Option<String>,
+        listening_address: SocketAddr,
+        upstream_protocol: Protocol,
+        options: DevOptions,
+    ) -> Result<Self> {
         let host = if let Some(host) = host {
             Host::new(&host, false)?
         } else {
@@ -39,9 +87,134 @@ impl ServerConfig {
             )?
         };
 
+        let cf = cf::properties(&options)?;
+        if options.upstream_concurrency == Some(0) {
+            anyhow::bail!("--upstream-concurrency must allow at least 1 request")
+        }
+        let upstream_limit = options.upstream_concurrency.map(|max| {
+            let queue_timeout = options.upstream_queue_timeout.map(Duration::from_millis);
+            Arc::new(UpstreamLimit::new(max, queue_timeout))
+        });
+
         Ok(ServerConfig {
             host,
             listening_address,
+            options: Arc::new(options),
+            routes: Routes::default(),
+            static_root: None,
+            route_filter: None,
+            watch_scope: WatchScope::default(),
+            cf,
+            upstream_limit,
         })
     }
+
+    /// with `--dual-listen`, the config for the https server listening alongside this one,
+    /// on `--https-port` or the port after this one's
+    pub fn dual_listen_https(&self) -> Result<Option<ServerConfig>> {
+        if !self.options.dual_listen {
+            return Ok(None);
+        }
+        let port = match self.options.https_port {
+            Some(port) => port,
+            None => match self.listening_address.port().checked_add(1) {
+                Some(port) => port,
+                None => anyhow::bail!("There is no port after 65535 for the https server, choose one with --https-port"),
+            },
+        };
+        let addr = SocketAddr::new(self.listening_address.ip(), port);
+        let listening_address = match listener::reserve(&addr) {
+            Ok(listening_address) => listening_address,
+            Err(_) => anyhow::bail!(
+                "{} is unavailable for the https server, choose another port with --https-port",
+                &addr
+            ),
+        };
+        Ok(Some(ServerConfig {
+            listening_address,
+            ..self.clone()
+        }))
+    }
+
+    /// the URL to reach the dev server on. An unspecified address listens on
+    /// loopback too, so that is what's connected to
+    pub fn url(&self, local_protocol: Protocol) -> String {
+        let mut addr = self.listening_address;
+        if addr.ip().is_unspecified() {
+            let loopback: IpAddr = match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            };
+            addr.set_ip(loopback);
+        }
+        let scheme = if local_protocol.is_https() {
+            "https"
+        } else {
+            "http"
+        };
+        format!("{}://{}", scheme, addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_connect_to_loopback_for_unspecified_addresses() {
+        let mut config = ServerConfig::new(
+            None,
+            Ipv4Addr::LOCALHOST.into(),
+            0,
+            Protocol::Https,
+            DevOptions::default(),
+        )
+        .unwrap();
+        config.listening_address = "[::]:54321".parse().unwrap();
+        assert_eq!(config.url(Protocol::Https), "https://[::1]:54321");
+
+        config.listening_address = "0.0.0.0:8787".parse().unwrap();
+        assert_eq!(config.url(Protocol::Http), "http://127.0.0.1:8787");
+    }
+
+    #[test]
+    fn servers_can_be_handed_a_bound_listener() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config =
+            ServerConfig::with_listener(None, listener, Protocol::Https, DevOptions::default())
+                .unwrap();
+        assert_eq!(config.listening_address, addr);
+        let listener = listener::bind(&config.listening_address).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn dual_listening_adds_an_https_server_on_its_own_port() {
+        let config = |options| {
+            ServerConfig::new(
+                None,
+                Ipv4Addr::LOCALHOST.into(),
+                0,
+                Protocol::Https,
+                options,
+            )
+            .unwrap()
+        };
+        assert!(config(DevOptions::default())
+            .dual_listen_https()
+            .unwrap()
+            .is_none());
+
+        let http = config(DevOptions {
+            dual_listen: true,
+            https_port: Some(0),
+            ..Default::default()
+        });
+        let https = http.dual_listen_https().unwrap().unwrap();
+        assert_eq!(https.listening_address.ip(), http.listening_address.ip());
+        assert_ne!(https.listening_address, http.listening_address);
+        assert!(listener::bind(&https.listening_address).is_ok());
+    }
 }
@@ -0,0 +1,54 @@
+//! `wrangler dev` stops serving cleanly when the user hits Ctrl-C, or when a
+//! process manager like foreman, overmind or docker asks it to with SIGTERM.
+//! A clean stop exits 0, only errors exit with anything else
+use std::io::{self, Write};
+
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+
+/// asks for a stop from inside wrangler dev, like `q` in the `--tui` dashboard
+static REQUESTED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// resolves once the process has been asked to stop
+pub(super) async fn signal() {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate() => {}
+        _ = REQUESTED.notified() => {}
+    }
+    log::info!("Shutting down the dev server");
+}
+
+/// stops serving as if the process got Ctrl-C
+pub(super) fn request() {
+    // stored as a permit if nothing is waiting yet
+    REQUESTED.notify_one();
+}
+
+#[cfg(unix)]
+async fn terminate() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        }
+        // Ctrl-C still works without it
+        Err(e) => {
+            log::info!("Failed to listen for SIGTERM: {}", e);
+            futures_util::future::pending::<()>().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminate() {
+    futures_util::future::pending::<()>().await
+}
+
+/// stdout is line buffered, but a line logged without its newline
+/// would otherwise be lost when the process exits
+pub(super) fn flush() {
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+}
@@ -7,6 +7,7 @@ use futures_util::sink::SinkExt;
 use futures_util::stream::{SplitStream, StreamExt};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
+use crate::commands::dev::worker_logs;
 use crate::terminal::colored_json_string;
 use crate::terminal::message::{Message, StdErr, StdOut};
 use protocol::domain::runtime::event::Event::ExceptionThrown;
@@ -21,6 +22,17 @@ use url::Url;
 
 const KEEP_ALIVE_INTERVAL: u64 = 10;
 
+/// like `listen`, but only for a preview wrangler uploaded itself. The console of one
+/// given with `--preview-token` belongs to the session that uploaded it, so this never
+/// finishes instead
+pub async fn listen_if_uploaded(socket_url: Url, uploaded: bool) -> Result<()> {
+    if uploaded {
+        listen(socket_url).await
+    } else {
+        futures_util::future::pending().await
+    }
+}
+
 /// connect to a Workers runtime WebSocket emitting the Chrome Devtools Protocol
 /// parse all console messages, and print them to stdout
 pub async fn listen(socket_url: Url) -> Result<()> {
@@ -119,6 +131,10 @@ async fn print_ws_messages(
         let message_text = message.into_text().unwrap();
         log::info!("{}", &message_text);
 
+        if worker_logs::print(&message_text) {
+            continue;
+        }
+
         let parsed_message: Result<protocol::Runtime> = serde_json::from_str(&message_text)
             .map_err(|e| anyhow!("Failed to parse event:\n{}", e));
 
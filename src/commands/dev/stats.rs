@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use hyper::StatusCode;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// the most paths counted separately, any others are counted together under `OTHER`
+/// so a Worker with high cardinality paths can't grow the map without bound
+const MAX_PATHS: usize = 256;
+const OTHER: &str = "other";
+
+/// upper bounds of the latency buckets, from sub-millisecond to multi-second
+const DEFAULT_LATENCY_BUCKETS: &[f64] = &[
+    0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+/// upper bounds of the size buckets, from 1KiB to 10MiB
+const SIZE_BUCKETS: &[f64] = &[
+    1024.0,
+    4096.0,
+    16384.0,
+    65536.0,
+    262_144.0,
+    1_048_576.0,
+    4_194_304.0,
+    10_485_760.0,
+];
+
+static PATHS: Lazy<Mutex<HashMap<String, Counter>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static TOTALS: Lazy<Mutex<Totals>> = Lazy::new(|| Mutex::new(Totals::default()));
+static LATENCY: OnceCell<Histogram> = OnceCell::new();
+static REQUEST_SIZES: Lazy<Histogram> = Lazy::new(|| Histogram::new(SIZE_BUCKETS.to_vec()));
+static RESPONSE_SIZES: Lazy<Histogram> = Lazy::new(|| Histogram::new(SIZE_BUCKETS.to_vec()));
+
+/// counts of observations in fixed buckets, which can be updated from any
+/// number of requests at once without taking a lock
+struct Histogram {
+    bounds: Vec<f64>,
+    /// one more than there are bounds, for observations over the last of them
+    counts: Vec<AtomicU64>,
+    /// in thousandths of the unit, so it can be kept in an integer
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: Vec<f64>) -> Histogram {
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Histogram {
+            bounds,
+            counts,
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or_else(|| self.bounds.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum
+            .fetch_add((value * 1000.0) as u64, Ordering::Relaxed);
+    }
+
+    /// cumulative counts for each bucket, in the style of Prometheus:
+    /// every bucket counts the observations less than or equal to its bound
+    fn to_json(&self) -> Value {
+        let mut cumulative = 0;
+        let mut buckets = Vec::with_capacity(self.counts.len());
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let le = match self.bounds.get(i) {
+                Some(bound) => json!(bound),
+                None => json!("+Inf"),
+            };
+            buckets.push(json!({ "le": le, "count": cumulative }));
+        }
+        json!({
+            "buckets": buckets,
+            "count": cumulative,
+            "sum": self.sum.load(Ordering::Relaxed) as f64 / 1000.0,
+        })
+    }
+}
+
+/// replaces the default latency buckets with `--latency-buckets`,
+/// which has to happen before any request is recorded
+pub(super) fn set_latency_buckets(bounds: &[f64]) -> Result<()> {
+    let ascending = bounds.windows(2).all(|pair| pair[0] < pair[1]);
+    if !ascending
+        || bounds
+            .iter()
+            .any(|bound| !bound.is_finite() || *bound <= 0.0)
+    {
+        anyhow::bail!("--latency-buckets must be positive and in ascending order, like 1,10,100")
+    }
+    LATENCY
+        .set(Histogram::new(bounds.to_vec()))
+        .map_err(|_| anyhow::anyhow!("latency buckets were already set"))
+}
+
+fn latency() -> &'static Histogram {
+    LATENCY.get_or_init(|| Histogram::new(DEFAULT_LATENCY_BUCKETS.to_vec()))
+}
+
+/// counts for the whole session, summarized when it ends
+#[derive(Default)]
+struct Totals {
+    /// responses by status class, 1xx to 5xx
+    by_class: [u64; 5],
+    upstream_errors: u64,
+}
+
+impl Totals {
+    fn requests(&self) -> u64 {
+        self.by_class.iter().sum::<u64>() + self.upstream_errors
+    }
+
+    fn summary(&self, uptime: Duration) -> String {
+        let mut counts: Vec<String> = self
+            .by_class
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(class, count)| format!("{} {}xx", count, class + 1))
+            .collect();
+        if self.upstream_errors > 0 {
+            counts.push(format!("{} upstream errors", self.upstream_errors));
+        }
+
+        let secs = uptime.as_secs();
+        let mut summary = format!(
+            "Served {} requests in {}m {}s",
+            self.requests(),
+            secs / 60,
+            secs % 60
+        );
+        if !counts.is_empty() {
+            summary.push_str(&format!(": {}", counts.join(", ")));
+        }
+        summary
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    count: u64,
+    total: Duration,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub(super) struct PathStats {
+    pub path: String,
+    pub count: u64,
+    pub avg_ms: f64,
+}
+
+/// count a request to `path` that took `duration` to respond to with `status`
+pub(super) fn record(path: &str, status: StatusCode, duration: Duration) {
+    let mut paths = PATHS.lock().unwrap();
+    record_in(&mut paths, path, duration);
+
+    drop(paths);
+    latency().observe(duration.as_secs_f64() * 1000.0);
+
+    let class = (status.as_u16() / 100) as usize;
+    if (1..=5).contains(&class) {
+        TOTALS.lock().unwrap().by_class[class - 1] += 1;
+    }
+}
+
+/// count the sizes of a request and its response, where they are known up front
+pub(super) fn record_sizes(request: Option<u64>, response: Option<u64>) {
+    if let Some(request) = request {
+        REQUEST_SIZES.observe(request as f64);
+    }
+    if let Some(response) = response {
+        RESPONSE_SIZES.observe(response as f64);
+    }
+}
+
+/// the latency and size histograms so far, for scraping during a session
+pub(super) fn histograms() -> Value {
+    json!({
+        "latency_ms": latency().to_json(),
+        "request_bytes": REQUEST_SIZES.to_json(),
+        "response_bytes": RESPONSE_SIZES.to_json(),
+    })
+}
+
+/// count a request that got no response, as sending it upstream failed
+pub(super) fn record_upstream_error() {
+    TOTALS.lock().unwrap().upstream_errors += 1;
+}
+
+/// a line summarizing the session, if any requests were made
+pub(super) fn summary(uptime: Duration) -> Option<String> {
+    let totals = TOTALS.lock().unwrap();
+    if totals.requests() > 0 {
+        Some(totals.summary(uptime))
+    } else {
+        None
+    }
+}
+
+/// the requests counted so far, most requested path first
+pub(super) fn paths() -> Vec<PathStats> {
+    let paths = PATHS.lock().unwrap();
+    sorted(&paths)
+}
+
+fn record_in(paths: &mut HashMap<String, Counter>, path: &str, duration: Duration) {
+    let path = normalize(path);
+    let key = if paths.contains_key(path) || paths.len() < MAX_PATHS {
+        path
+    } else {
+        OTHER
+    };
+    let counter = paths.entry(key.to_string()).or_default();
+    counter.count += 1;
+    counter.total += duration;
+}
+
+fn sorted(paths: &HashMap<String, Counter>) -> Vec<PathStats> {
+    let mut stats: Vec<PathStats> = paths
+        .iter()
+        .map(|(path, counter)| PathStats {
+            path: path.clone(),
+            count: counter.count,
+            avg_ms: counter.total.as_secs_f64() * 1000.0 / counter.count as f64,
+        })
+        .collect();
+    stats.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+    stats
+}
+
+/// `/users/?page=2` and `/users` are counted as the same path
+fn normalize(path: &str) -> &str {
+    let path = path.split('?').next().unwrap_or("");
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_sorted_by_count() {
+        let mut paths = HashMap::new();
+        record_in(&mut paths, "/a", Duration::from_millis(10));
+        record_in(&mut paths, "/b/?page=2", Duration::from_millis(10));
+        record_in(&mut paths, "/b", Duration::from_millis(30));
+
+        let stats = sorted(&paths);
+        assert_eq!(stats[0].path, "/b");
+        assert_eq!(stats[0].count, 2);
+        assert!((stats[0].avg_ms - 20.0).abs() < f64::EPSILON);
+        assert_eq!(stats[1].path, "/a");
+    }
+
+    #[test]
+    fn overflowing_paths_are_counted_as_other() {
+        let mut paths = HashMap::new();
+        for i in 0..MAX_PATHS + 10 {
+            record_in(&mut paths, &format!("/{}", i), Duration::from_millis(1));
+        }
+
+        assert_eq!(paths.len(), MAX_PATHS + 1);
+        assert_eq!(paths[OTHER].count, 10);
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new(vec![1.0, 10.0]);
+        for value in &[0.5, 1.0, 5.0, 50.0] {
+            histogram.observe(*value);
+        }
+
+        assert_eq!(
+            histogram.to_json(),
+            json!({
+                "buckets": [
+                    { "le": 1.0, "count": 2 },
+                    { "le": 10.0, "count": 3 },
+                    { "le": "+Inf", "count": 4 },
+                ],
+                "count": 4,
+                "sum": 56.5,
+            })
+        );
+        assert!(set_latency_buckets(&[10.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn summaries_count_status_classes_and_errors() {
+        let totals = Totals {
+            by_class: [0, 38, 1, 3, 1],
+            upstream_errors: 2,
+        };
+
+        assert_eq!(
+            totals.summary(Duration::from_secs(312)),
+            "Served 45 requests in 5m 12s: 38 2xx, 1 3xx, 3 4xx, 1 5xx, 2 upstream errors"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! `--strict-host` answers requests for any host other than the ones dev
+//! expects with a `421 Misdirected Request`, before they're sent upstream, to
+//! check how an app copes with a spoofed Host header or to catch requests meant
+//! for another machine on a shared network. The hosts accepted are:
+//!
+//! - the upstream host, from `--host` or the Worker's route, on any port
+//! - the address dev is listening on, and `localhost`, `127.0.0.1` and `[::1]`
+//!   on its port when it's listening on a loopback address or on all of them
+//! - each host given with `--allowed-host`, on any port, or only on the port
+//!   given with it, like `myapp.test:8787`
+//!
+//! A request without a Host header, or an authority in its URI for HTTP/2, is
+//! rejected too. Hosts are compared case-insensitively.
+use crate::commands::dev::error_response;
+use crate::commands::dev::ServerConfig;
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{anyhow, Result};
+use hyper::header::HOST;
+use hyper::http::uri::Authority;
+use hyper::{Body, Request, Response, StatusCode};
+
+const LOOPBACK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+/// a host given to `--allowed-host`, with an optional port
+pub fn parse_allowed_host(host: &str) -> Result<String> {
+    let authority = host
+        .trim()
+        .parse::<Authority>()
+        .ok()
+        .filter(|authority| !authority.host().is_empty() && !authority.as_str().contains('@'))
+        .ok_or_else(|| {
+            anyhow!(
+                "{} is not a valid host, which is a host name and an optional port",
+                host
+            )
+        })?;
+    Ok(authority.as_str().to_lowercase())
+}
+
+/// the 421 for a request to a host dev doesn't expect, with `--strict-host`
+pub(super) fn check(req: &Request<Body>, server_config: &ServerConfig) -> Option<Response<Body>> {
+    let options = &server_config.options;
+    if !options.strict_host {
+        return None;
+    }
+    let requested = match req.headers().get(HOST) {
+        Some(host) => host.to_str().ok().map(str::to_string),
+        // HTTP/2 requests carry the host in the URI instead
+        None => req.uri().authority().map(|authority| authority.to_string()),
+    };
+    let upstream_host = server_config.host.to_string();
+    let allowed = requested.as_deref().map_or(false, |requested| {
+        is_allowed(
+            requested,
+            &upstream_host,
+            server_config.listening_address,
+            &options.allowed_host,
+        )
+    });
+    if allowed {
+        return None;
+    }
+
+    let detail = match requested {
+        Some(requested) => format!(
+            "wrangler dev doesn't serve {} with --strict-host, allow it with --allowed-host",
+            requested
+        ),
+        None => "wrangler dev doesn't serve requests without a Host with --strict-host".to_string(),
+    };
+    Some(error_response::build(
+        StatusCode::MISDIRECTED_REQUEST,
+        detail,
+    ))
+}
+
+fn is_allowed(
+    requested: &str,
+    upstream_host: &str,
+    listening: SocketAddr,
+    allowed_hosts: &[String],
+) -> bool {
+    let requested = match requested.trim().parse::<Authority>() {
+        Ok(requested) => requested,
+        Err(_) => return false,
+    };
+    let host = requested.host().to_lowercase();
+    let port = requested.port_u16();
+
+    if host == upstream_host.to_lowercase() {
+        return true;
+    }
+
+    if port == Some(listening.port()) {
+        let listening_host = match listening.ip() {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        };
+        let loopback = listening.ip().is_loopback() || listening.ip().is_unspecified();
+        if host == listening_host || (loopback && LOOPBACK_HOSTS.contains(&host.as_str())) {
+            return true;
+        }
+    }
+
+    allowed_hosts.iter().any(|allowed| {
+        let allowed = match allowed.parse::<Authority>() {
+            Ok(allowed) => allowed,
+            Err(_) => return false,
+        };
+        allowed.host().eq_ignore_ascii_case(&host)
+            && allowed
+                .port_u16()
+                .map_or(true, |allowed| Some(allowed) == port)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(requested: &str, listening: &str, allowed_hosts: &[&str]) -> bool {
+        let allowed_hosts: Vec<String> = allowed_hosts
+            .iter()
+            .map(|host| parse_allowed_host(host).unwrap())
+            .collect();
+        is_allowed(
+            requested,
+            "example.com",
+            listening.parse().unwrap(),
+            &allowed_hosts,
+        )
+    }
+
+    #[test]
+    fn the_upstream_host_and_dev_itself_are_allowed() {
+        assert!(allowed("example.com", "127.0.0.1:8787", &[]));
+        assert!(allowed("EXAMPLE.com:443", "127.0.0.1:8787", &[]));
+        assert!(allowed("localhost:8787", "127.0.0.1:8787", &[]));
+        assert!(allowed("127.0.0.1:8787", "127.0.0.1:8787", &[]));
+        assert!(allowed("[::1]:8787", "0.0.0.0:8787", &[]));
+        assert!(allowed("192.168.1.20:8787", "192.168.1.20:8787", &[]));
+    }
+
+    #[test]
+    fn other_hosts_are_rejected() {
+        assert!(!allowed("evil.example", "127.0.0.1:8787", &[]));
+        assert!(!allowed("sub.example.com", "127.0.0.1:8787", &[]));
+        assert!(!allowed("localhost:9000", "127.0.0.1:8787", &[]));
+        assert!(!allowed("localhost:8787", "192.168.1.20:8787", &[]));
+        assert!(!allowed("192.168.1.30:8787", "0.0.0.0:8787", &[]));
+        assert!(!allowed("", "127.0.0.1:8787", &[]));
+    }
+
+    #[test]
+    fn allowed_hosts_match_any_port_unless_given_one() {
+        let allowed_hosts = &["myapp.test", "api.test:8443"];
+        assert!(allowed("myapp.test:8787", "127.0.0.1:8787", allowed_hosts));
+        assert!(allowed("MyApp.test", "127.0.0.1:8787", allowed_hosts));
+        assert!(allowed("api.test:8443", "127.0.0.1:8787", allowed_hosts));
+        assert!(!allowed("api.test:8787", "127.0.0.1:8787", allowed_hosts));
+        assert!(!allowed("other.test", "127.0.0.1:8787", allowed_hosts));
+    }
+
+    #[test]
+    fn allowed_hosts_must_be_hosts() {
+        assert_eq!(parse_allowed_host("MyApp.test").unwrap(), "myapp.test");
+        assert!(parse_allowed_host("https://myapp.test").is_err());
+        assert!(parse_allowed_host("user@myapp.test").is_err());
+        assert!(parse_allowed_host("").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! The accept loop for the https servers: TCP connections are accepted
+//! for as long as the listener works, and TLS handshakes happen alongside it
+use super::verbose;
+use crate::terminal::message::{Message, StdErr};
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+/// the most TLS handshakes that may be in progress at once
+const MAX_HANDSHAKES: usize = 64;
+/// how long to wait after the first accept error caused by running out of something,
+/// doubling on each error after that
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+static HTTPS_HINT: Once = Once::new();
+
+pub(in crate::commands::dev) type Incoming =
+    Pin<Box<dyn Stream<Item = io::Result<TlsStream<TcpStream>>> + Send>>;
+
+/// every connection to `tcp` that completes a TLS handshake. The stream only
+/// fails, which stops the server, if the listener itself stops working.
+/// Each handshake is described with `verbose`
+pub(in crate::commands::dev) fn incoming(
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+    verbose: bool,
+) -> Incoming {
+    stream::unfold(Some(tcp), accept_next)
+        .map(move |tcp_stream| {
+            let acceptor = acceptor.clone();
+            async move {
+                let tcp_stream = tcp_stream?;
+                let peer = tcp_stream.peer_addr().ok();
+                let started = Instant::now();
+                match acceptor.accept(tcp_stream).await {
+                    Ok(tls_stream) => {
+                        // resumed sessions show up as much quicker handshakes
+                        log::debug!("TLS handshake took {:?}", started.elapsed());
+                        if verbose {
+                            verbose::negotiated(peer, tls_stream.get_ref().1);
+                        }
+                        Ok(Some(tls_stream))
+                    }
+                    Err(e) => {
+                        if verbose {
+                            verbose::failed(peer, &e);
+                        }
+                        // one client failing its handshake says nothing about the next
+                        StdErr::warn(&format!("Client connection error: {}", e));
+                        HTTPS_HINT.call_once(|| {
+                            StdErr::info("Make sure to use https and `--insecure` with curl")
+                        });
+                        Ok(None)
+                    }
+                }
+            }
+        })
+        .buffer_unordered(MAX_HANDSHAKES)
+        .filter_map(|handshake| async move { handshake.transpose() })
+        .boxed()
+}
+
+#[derive(Debug, PartialEq)]
+enum AcceptError {
+    /// something went wrong with one connection, the next may be fine
+    Transient,
+    /// the process or system ran out of file descriptors, memory or buffers,
+    /// which takes a while to recover from
+    Exhausted,
+    /// the listener can't accept any more connections
+    Fatal,
+}
+
+fn classify(e: &io::Error) -> AcceptError {
+    match e.kind() {
+        io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::TimedOut => AcceptError::Transient,
+        io::ErrorKind::InvalidInput
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::PermissionDenied => AcceptError::Fatal,
+        // EMFILE, ENFILE, ENOBUFS and ENOMEM don't have a kind of their own
+        _ => AcceptError::Exhausted,
+    }
+}
+
+/// accepts the next connection, retrying errors that may go away and backing off
+/// from those that take time to, so running out of file descriptors doesn't spin
+async fn accept_next(
+    tcp: Option<TcpListener>,
+) -> Option<(io::Result<TcpStream>, Option<TcpListener>)> {
+    let tcp = tcp?;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let e = match tcp.accept().await {
+            Ok((tcp_stream, _addr)) => return Some((Ok(tcp_stream), Some(tcp))),
+            Err(e) => e,
+        };
+
+        match classify(&e) {
+            AcceptError::Transient => log::info!("Failed to accept a connection: {}", e),
+            AcceptError::Exhausted => {
+                if backoff == MIN_BACKOFF {
+                    StdErr::warn(&format!(
+                        "Failed to accept a connection: {}. Retrying until it succeeds",
+                        e
+                    ));
+                } else {
+                    log::info!("Failed to accept a connection: {}", e);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            // ends the stream after this error
+            AcceptError::Fatal => return Some((Err(e), None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_errors_are_classified() {
+        let error = |kind| io::Error::new(kind, "accept failed");
+
+        assert_eq!(
+            classify(&error(io::ErrorKind::ConnectionAborted)),
+            AcceptError::Transient
+        );
+        assert_eq!(
+            classify(&error(io::ErrorKind::InvalidInput)),
+            AcceptError::Fatal
+        );
+        // EMFILE on Linux and macOS
+        assert_eq!(
+            classify(&io::Error::from_raw_os_error(24)),
+            AcceptError::Exhausted
+        );
+    }
+}
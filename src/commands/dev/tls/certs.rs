@@ -10,29 +10,64 @@ use openssl::x509::extension::{
 };
 use openssl::x509::{X509NameBuilder, X509Req, X509ReqBuilder, X509};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::settings::get_wrangler_home_dir;
-use crate::terminal::message::{Message, StdOut};
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
+
+/// how long generated certificates are valid for, unless `--cert-validity-days` says otherwise
+const DEFAULT_VALIDITY_DAYS: u32 = 365;
+/// the longest validity macOS and iOS accept for a TLS certificate, even a trusted one
+const MAX_VALIDITY_DAYS: u32 = 825;
+
+/// parses the number of days given to `--cert-validity-days`
+pub fn parse_validity_days(days: &str) -> Result<u32> {
+    match days.parse() {
+        Ok(days) if (1..=MAX_VALIDITY_DAYS).contains(&days) => Ok(days),
+        _ => anyhow::bail!(
+            "{} is not a number of days between 1 and {}, the most some browsers accept",
+            days,
+            MAX_VALIDITY_DAYS
+        ),
+    }
+}
+
 /// Create files for cert and private key
-fn create_output_files() -> Result<Option<(PathBuf, PathBuf)>> {
+fn create_output_files(validity_days: Option<u32>) -> Result<Option<(PathBuf, PathBuf)>> {
     let home = get_wrangler_home_dir()?.join("config");
     let cert = home.join("dev-cert.pem");
     let privkey = home.join("dev-privkey.rsa");
 
-    if cert.exists() && privkey.exists() {
+    if cert.exists() && privkey.exists() && !has_expired(&cert)? {
+        if validity_days.is_some() {
+            StdErr::info(&format!(
+                "Using the existing certificate at {}, --cert-validity-days only applies to new ones. Delete it and its private key to generate one",
+                cert.display()
+            ));
+        }
         Ok(None)
     } else {
         fs::create_dir_all(&home)?;
 
-        StdOut::info(format!("Generating certificate and private key for https server, if you would like to use your own you can replace `dev-cert.pem` and `dev-privkey.rsa` at {}", home.to_str().unwrap()).as_str());
+        StdErr::info(format!("Generating certificate and private key for https server, if you would like to use your own you can replace `dev-cert.pem` and `dev-privkey.rsa` at {}", home.to_str().unwrap()).as_str());
 
         Ok(Some((cert, privkey)))
     }
 }
 
+/// a certificate that has expired, like a short lived one from an earlier session, is replaced
+fn has_expired(cert: &Path) -> Result<bool> {
+    let cert = X509::from_pem(&fs::read(cert)?)?;
+    let expired = cert.not_after() < Asn1Time::days_from_now(0)?;
+    if expired {
+        StdErr::info("The certificate for the https server has expired");
+    }
+    Ok(expired)
+}
+
 /// Generate certificate authority to sign cert
-fn create_ca() -> Result<(X509, PKey<Private>)> {
+fn create_ca(validity_days: u32) -> Result<(X509, PKey<Private>)> {
     let rsa = Rsa::generate(2048)?;
     let privkey = PKey::from_rsa(rsa)?;
 
@@ -56,7 +91,7 @@ fn create_ca() -> Result<(X509, PKey<Private>)> {
     cert_builder.set_pubkey(&privkey)?;
     let not_before = Asn1Time::days_from_now(0)?;
     cert_builder.set_not_before(&not_before)?;
-    let not_after = Asn1Time::days_from_now(365)?;
+    let not_after = Asn1Time::days_from_now(validity_days)?;
     cert_builder.set_not_after(&not_after)?;
 
     cert_builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
@@ -95,16 +130,17 @@ fn create_req(privkey: &PKey<Private>) -> Result<X509Req> {
     Ok(req)
 }
 
-/// Generate cert and private key
-pub fn generate_cert() -> Result<()> {
-    let files = create_output_files()?;
+/// Generate cert and private key, valid for `validity_days` or a year
+pub fn generate_cert(validity_days: Option<u32>) -> Result<()> {
+    let files = create_output_files(validity_days)?;
     if files.is_none() {
         return Ok(());
     }
 
     let (cert_file, priv_file) = files.unwrap();
+    let validity_days = validity_days.unwrap_or(DEFAULT_VALIDITY_DAYS);
 
-    let (ca, ca_key) = create_ca()?;
+    let (ca, ca_key) = create_ca(validity_days)?;
 
     let rsa = Rsa::generate(2048)?;
     let privkey = PKey::from_rsa(rsa)?;
@@ -127,7 +163,7 @@ pub fn generate_cert() -> Result<()> {
     cert_builder.set_pubkey(&privkey)?;
     let not_before = Asn1Time::days_from_now(0)?;
     cert_builder.set_not_before(&not_before)?;
-    let not_after = Asn1Time::days_from_now(365)?;
+    let not_after = Asn1Time::days_from_now(validity_days)?;
     cert_builder.set_not_after(&not_after)?;
 
     cert_builder.append_extension(BasicConstraints::new().build()?)?;
@@ -153,6 +189,9 @@ pub fn generate_cert() -> Result<()> {
     cert_builder.append_extension(auth_key_identifier)?;
 
     let subject_alt_name = SubjectAlternativeName::new()
+        .dns("localhost")
+        .ip("127.0.0.1")
+        .ip("::1")
         .dns("*.example.com")
         .dns("hello.com")
         .build(&cert_builder.x509v3_context(Some(&ca), None))?;
@@ -168,3 +207,61 @@ pub fn generate_cert() -> Result<()> {
 
     Ok(())
 }
+
+/// Copy the generated certificate to `path`, so it can be added to a trust store
+pub fn export_cert(path: &Path) -> Result<()> {
+    let cert_file = get_wrangler_home_dir()?.join("config").join("dev-cert.pem");
+    let cert_str = fs::read(&cert_file)?;
+    fs::write(path, &cert_str)?;
+    StdErr::success(&format!(
+        "Exported the wrangler dev certificate to {}",
+        path.display()
+    ));
+
+    // certificates from older versions of wrangler aren't valid for localhost,
+    // so trusting them wouldn't get rid of the warning
+    let cert = X509::from_pem(&cert_str)?;
+    let covers_localhost = cert.subject_alt_names().map_or(false, |names| {
+        names.iter().any(|name| name.dnsname() == Some("localhost"))
+    });
+    if !covers_localhost {
+        StdErr::warn(&format!(
+            "This certificate isn't valid for localhost. Delete {} and its private key to generate one that is",
+            cert_file.display()
+        ));
+    }
+
+    let path = path.display();
+    let instructions = if cfg!(target_os = "macos") {
+        format!(
+            "sudo security add-trusted-cert -d -r trustAsRoot -k /Library/Keychains/System.keychain {}",
+            path
+        )
+    } else if cfg!(target_os = "windows") {
+        format!("certutil -addstore -user Root {}", path)
+    } else {
+        format!(
+            "certutil -d sql:$HOME/.pki/nssdb -A -t \"P,,\" -n wrangler-dev -i {}",
+            path
+        )
+    };
+    StdErr::help(&format!(
+        "To trust it, run {}\nFirefox keeps its own trust store, import the certificate under Settings > Privacy & Security > Certificates. The certificate is kept between runs, so this only needs doing once",
+        styles::highlight(instructions)
+    ));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validity_must_be_positive_and_accepted_by_browsers() {
+        assert_eq!(parse_validity_days("30").unwrap(), 30);
+        assert!(parse_validity_days("0").is_err());
+        assert!(parse_validity_days("3650").is_err());
+        assert!(parse_validity_days("-1").is_err());
+    }
+}
@@ -1,12 +1,17 @@
+mod accept;
 mod certs;
-pub use certs::generate_cert;
+mod verbose;
+mod versions;
+pub(super) use accept::incoming;
+pub use certs::{export_cert, generate_cert, parse_validity_days};
+pub use versions::{parse_cipher_suite, parse_version};
 
 use anyhow::Result;
 use core::task::{Context, Poll};
 use fs::File;
 use futures_util::stream::Stream;
 use rustls::internal::pemfile;
-use rustls::{NoClientAuth, ServerConfig};
+use rustls::{NoClientAuth, ServerConfig, ServerSessionMemoryCache, Ticketer};
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -15,10 +20,14 @@ use std::{fs, io};
 use tokio::net::TcpStream;
 use tokio_rustls::{server::TlsStream, TlsAcceptor};
 
+use crate::commands::dev::DevOptions;
 use crate::settings::get_wrangler_home_dir;
 
+/// how many TLS 1.2 sessions are kept around for clients to resume
+const SESSION_CACHE_SIZE: usize = 1024;
+
 // Build TLS configuration
-pub(super) fn get_tls_acceptor() -> Result<TlsAcceptor> {
+pub(super) fn get_tls_acceptor(options: &DevOptions) -> Result<TlsAcceptor> {
     let home = get_wrangler_home_dir()?.join("config");
     let cert = home.join("dev-cert.pem");
     let privkey = home.join("dev-privkey.rsa");
@@ -36,6 +45,18 @@ pub(super) fn get_tls_acceptor() -> Result<TlsAcceptor> {
     cfg.set_single_cert(certs, key)
         .map_err(|e| io_error(format!("{}", e)))?;
 
+    // Let clients resume earlier sessions rather than doing a full handshake
+    // for every connection, which browsers open a lot of for pages with many assets.
+    // Ticket keys are random and only live as long as this process
+    cfg.session_storage = ServerSessionMemoryCache::new(SESSION_CACHE_SIZE);
+    cfg.ticketer = Ticketer::new();
+
+    versions::restrict(&mut cfg, options)?;
+
+    if options.traces_tls() {
+        cfg.cert_resolver = Arc::new(verbose::ClientHelloLogger(Arc::clone(&cfg.cert_resolver)));
+    }
+
     Ok(TlsAcceptor::from(Arc::new(cfg)))
 }
 
@@ -0,0 +1,91 @@
+//! `--verbose-tls` describes every TLS handshake, to help work out why a
+//! particular browser or curl can't connect to the self-signed dev server
+use crate::terminal::message::{Message, StdErr};
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use rustls::{ClientHello, ResolvesServerCert, ServerSession, Session, SignatureScheme};
+
+/// describes what a client offered in its ClientHello before picking the certificate as usual,
+/// which shows what it asked for even when the handshake goes on to fail
+pub(super) struct ClientHelloLogger(pub(super) Arc<dyn ResolvesServerCert>);
+
+impl ResolvesServerCert for ClientHelloLogger {
+    fn resolve(&self, client_hello: ClientHello) -> Option<rustls::sign::CertifiedKey> {
+        let sni = client_hello
+            .server_name()
+            .map(<&str>::from)
+            .unwrap_or("none");
+        let alpn = client_hello
+            .alpn()
+            .map(|protocols| {
+                protocols
+                    .iter()
+                    .map(|p| alpn(p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_else(|| "none".to_string());
+        StdErr::info(&format!(
+            "TLS client hello: SNI {}, ALPN {}, signature schemes {}",
+            sni,
+            alpn,
+            signature_schemes(client_hello.sigschemes())
+        ));
+
+        let certified_key = self.0.resolve(client_hello);
+        if certified_key.is_none() {
+            StdErr::warn("TLS: no certificate matches what the client asked for");
+        }
+        certified_key
+    }
+}
+
+/// what was negotiated with a client once its handshake completed
+pub(super) fn negotiated(peer: Option<SocketAddr>, session: &ServerSession) {
+    let version = session
+        .get_protocol_version()
+        .map_or_else(|| "unknown".to_string(), |version| format!("{:?}", version));
+    let cipher_suite = session.get_negotiated_ciphersuite().map_or_else(
+        || "unknown".to_string(),
+        |suite| format!("{:?}", suite.suite),
+    );
+    let alpn = session
+        .get_alpn_protocol()
+        .map_or_else(|| "none".to_string(), alpn);
+    StdErr::info(&format!(
+        "TLS handshake with {} completed: {}, {}, ALPN {}, SNI {}",
+        peer_description(peer),
+        version,
+        cipher_suite,
+        alpn,
+        session.get_sni_hostname().unwrap_or("none")
+    ));
+}
+
+/// why a client's handshake failed. rustls puts the alert or error it hit in the message
+pub(super) fn failed(peer: Option<SocketAddr>, e: &std::io::Error) {
+    StdErr::info(&format!(
+        "TLS handshake with {} failed ({:?}): {}",
+        peer_description(peer),
+        e.kind(),
+        e
+    ));
+}
+
+fn peer_description(peer: Option<SocketAddr>) -> String {
+    peer.map_or_else(|| "an unknown client".to_string(), |peer| peer.to_string())
+}
+
+fn alpn(protocol: &[u8]) -> String {
+    String::from_utf8_lossy(protocol).into_owned()
+}
+
+fn signature_schemes(schemes: &[SignatureScheme]) -> String {
+    schemes
+        .iter()
+        .map(|scheme| format!("{:?}", scheme))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
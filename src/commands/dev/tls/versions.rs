@@ -0,0 +1,141 @@
+//! `--tls-min-version`, `--tls-max-version` and `--tls-cipher-suites` narrow down
+//! what the https server will negotiate, to reproduce handshake failures from
+//! older or stricter clients locally. Without them, rustls's defaults are kept
+use anyhow::{anyhow, Result};
+use rustls::{CipherSuite, ProtocolVersion, ServerConfig, ALL_CIPHERSUITES};
+
+use crate::commands::dev::DevOptions;
+
+/// the versions rustls supports, oldest first
+const VERSIONS: [(&str, ProtocolVersion); 2] = [
+    ("1.2", ProtocolVersion::TLSv1_2),
+    ("1.3", ProtocolVersion::TLSv1_3),
+];
+
+/// parses a version given to `--tls-min-version` or `--tls-max-version`
+pub fn parse_version(version: &str) -> Result<ProtocolVersion> {
+    VERSIONS
+        .iter()
+        .find(|(name, _)| *name == version)
+        .map(|(_, version)| *version)
+        .ok_or_else(|| anyhow!("{} is not a TLS version, expected 1.2 or 1.3", version))
+}
+
+/// parses a cipher suite given to `--tls-cipher-suites` by its IANA name, whatever its case
+pub fn parse_cipher_suite(suite: &str) -> Result<CipherSuite> {
+    ALL_CIPHERSUITES
+        .iter()
+        .map(|supported| supported.suite)
+        .find(|known| format!("{:?}", known).eq_ignore_ascii_case(suite.trim()))
+        .ok_or_else(|| {
+            let known = ALL_CIPHERSUITES
+                .iter()
+                .map(|supported| format!("{:?}", supported.suite))
+                .collect::<Vec<_>>();
+            anyhow!(
+                "{} is not a cipher suite wrangler dev supports, expected one of {}",
+                suite,
+                known.join(", ")
+            )
+        })
+}
+
+/// restricts `cfg` to the versions and cipher suites given in `options`
+pub(super) fn restrict(cfg: &mut ServerConfig, options: &DevOptions) -> Result<()> {
+    if options.tls_min_version.is_some() || options.tls_max_version.is_some() {
+        let min = rank(options.tls_min_version.unwrap_or(ProtocolVersion::TLSv1_2));
+        let max = rank(options.tls_max_version.unwrap_or(ProtocolVersion::TLSv1_3));
+        if min > max {
+            anyhow::bail!(
+                "--tls-min-version {} is newer than --tls-max-version {}",
+                VERSIONS[min].0,
+                VERSIONS[max].0
+            );
+        }
+        cfg.versions = VERSIONS[min..=max]
+            .iter()
+            .map(|(_, version)| *version)
+            .collect();
+    }
+
+    if !options.tls_cipher_suites.is_empty() {
+        cfg.ciphersuites = ALL_CIPHERSUITES
+            .iter()
+            .copied()
+            .filter(|supported| options.tls_cipher_suites.contains(&supported.suite))
+            .collect();
+
+        for supported in &cfg.ciphersuites {
+            let usable = cfg
+                .versions
+                .iter()
+                .any(|version| supported.usable_for_version(*version));
+            if !usable {
+                anyhow::bail!(
+                    "The cipher suite {:?} can't be used with the TLS versions allowed by --tls-min-version and --tls-max-version",
+                    supported.suite
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rank(version: ProtocolVersion) -> usize {
+    VERSIONS
+        .iter()
+        .position(|(_, known)| *known == version)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::NoClientAuth;
+
+    #[test]
+    fn versions_and_suites_are_parsed_by_name() {
+        assert_eq!(parse_version("1.2").unwrap(), ProtocolVersion::TLSv1_2);
+        assert!(parse_version("1.1").is_err());
+
+        assert_eq!(
+            parse_cipher_suite("tls13_aes_128_gcm_sha256").unwrap(),
+            CipherSuite::TLS13_AES_128_GCM_SHA256
+        );
+        assert!(parse_cipher_suite("TLS_RSA_WITH_RC4_128_MD5").is_err());
+    }
+
+    #[test]
+    fn the_server_can_be_limited_to_tls_1_2() {
+        let options = DevOptions {
+            tls_max_version: Some(ProtocolVersion::TLSv1_2),
+            tls_cipher_suites: vec![CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256],
+            ..Default::default()
+        };
+        let mut cfg = ServerConfig::new(NoClientAuth::new());
+        restrict(&mut cfg, &options).unwrap();
+
+        assert_eq!(cfg.versions, vec![ProtocolVersion::TLSv1_2]);
+        assert_eq!(cfg.ciphersuites.len(), 1);
+    }
+
+    #[test]
+    fn contradictory_restrictions_are_rejected() {
+        let options = DevOptions {
+            tls_min_version: Some(ProtocolVersion::TLSv1_3),
+            tls_max_version: Some(ProtocolVersion::TLSv1_2),
+            ..Default::default()
+        };
+        let mut cfg = ServerConfig::new(NoClientAuth::new());
+        assert!(restrict(&mut cfg, &options).is_err());
+
+        let options = DevOptions {
+            tls_min_version: Some(ProtocolVersion::TLSv1_3),
+            tls_cipher_suites: vec![CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256],
+            ..Default::default()
+        };
+        let mut cfg = ServerConfig::new(NoClientAuth::new());
+        assert!(restrict(&mut cfg, &options).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! `--trace-context` starts a W3C trace for every request that isn't already
+//! part of one, so spans from the Worker link up in a tracing backend during
+//! dev. A request without a `traceparent` header is sent upstream with a new
+//! one, sampled, and a `tracestate` naming wrangler dev, and the trace id is
+//! shown in the request log so the trace can be looked up.
+//!
+//! A request that arrives with a `traceparent` is already being traced by the
+//! client, so its trace context is sent upstream as it is.
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// adds a new trace context to a request that doesn't have one, returning its trace id
+pub(super) fn inject(headers: &mut HeaderMap) -> Option<String> {
+    if headers.contains_key(TRACEPARENT) {
+        return None;
+    }
+
+    // all zeroes is an invalid id
+    let trace_id = format!("{:032x}", rand::random::<u128>().max(1));
+    let parent_id = format!("{:016x}", rand::random::<u64>().max(1));
+    let traceparent = HeaderValue::from_str(&format!("00-{}-{}-01", trace_id, parent_id)).ok()?;
+    headers.insert(HeaderName::from_static(TRACEPARENT), traceparent);
+    // a tracestate without a traceparent belongs to no trace, so it's replaced
+    headers.insert(
+        HeaderName::from_static(TRACESTATE),
+        HeaderValue::from_static("wrangler=dev"),
+    );
+    Some(trace_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_without_a_trace_get_one() {
+        let mut headers = HeaderMap::new();
+        let trace_id = inject(&mut headers).unwrap();
+
+        let traceparent = headers[TRACEPARENT].to_str().unwrap();
+        let fields: Vec<&str> = traceparent.split('-').collect();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0], "00");
+        assert_eq!(fields[1], trace_id);
+        assert_eq!(fields[1].len(), 32);
+        assert_eq!(fields[2].len(), 16);
+        assert_eq!(fields[3], "01");
+        assert_eq!(headers[TRACESTATE], "wrangler=dev");
+
+        let mut other = HeaderMap::new();
+        assert_ne!(inject(&mut other), Some(trace_id));
+    }
+
+    #[test]
+    fn client_trace_context_is_passed_on_unchanged() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00";
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT, HeaderValue::from_static(traceparent));
+        headers.insert(TRACESTATE, HeaderValue::from_static("vendor=abc"));
+
+        assert_eq!(inject(&mut headers), None);
+        assert_eq!(headers[TRACEPARENT], traceparent);
+        assert_eq!(headers[TRACESTATE], "vendor=abc");
+    }
+}
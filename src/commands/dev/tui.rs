@@ -0,0 +1,339 @@
+//! `--tui` replaces the scrolling request log with a live dashboard: the most
+//! recent requests, counts by status class, the current preview and a sparkline
+//! of recent latency. It is redrawn from the same events `--events-socket`
+//! writes, and falls back to the plain log when stdout isn't a terminal.
+//!
+//! | key     | action                          |
+//! |---------|---------------------------------|
+//! | `p`     | pause or resume redrawing       |
+//! | `c`     | clear the requests              |
+//! | `1`-`5` | only show 1xx to 5xx responses  |
+//! | `0`     | show every response             |
+//! | `r`     | rebuild and upload the Worker   |
+//! | `q`     | stop wrangler dev, like Ctrl-C  |
+use crate::commands::dev::events::Event;
+use crate::commands::dev::rebuild;
+use crate::commands::dev::shutdown;
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::prelude::*;
+use console::{Key, Term};
+use once_cell::sync::Lazy;
+
+/// how many requests are kept for the list, older ones are dropped
+const MAX_REQUESTS: usize = 500;
+/// how many of the latest latencies the sparkline shows
+const SPARKLINE_WIDTH: usize = 40;
+const SPARKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+const HELP: &str = "p pause  c clear  1-5 filter by status  0 all  r rebuild  q quit";
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static DASHBOARD: Lazy<Mutex<Dashboard>> = Lazy::new(|| Mutex::new(Dashboard::default()));
+
+#[derive(Default)]
+struct Dashboard {
+    url: Option<String>,
+    preview: Option<String>,
+    requests: VecDeque<Row>,
+    /// responses by status class, 1xx to 5xx
+    by_class: [u64; 5],
+    errors: u64,
+    latencies: VecDeque<u64>,
+    paused: bool,
+    /// only show responses in this status class
+    filter: Option<u16>,
+}
+
+struct Row {
+    id: u64,
+    time: DateTime<Local>,
+    method: String,
+    url: String,
+    /// the status and duration, once the response has arrived
+    response: Option<(u16, u64)>,
+}
+
+impl Dashboard {
+    fn observe(&mut self, event: &Event) {
+        match event {
+            Event::ServerReady { url } => self.url = Some(url.clone()),
+            Event::Request { id, method, url } => {
+                if self.requests.len() == MAX_REQUESTS {
+                    self.requests.pop_front();
+                }
+                self.requests.push_back(Row {
+                    id: *id,
+                    time: Local::now(),
+                    method: method.clone(),
+                    url: url.clone(),
+                    response: None,
+                });
+            }
+            Event::Response {
+                id,
+                status,
+                duration_ms,
+            } => {
+                if let Some(row) = self.requests.iter_mut().rev().find(|row| row.id == *id) {
+                    row.response = Some((*status, *duration_ms));
+                }
+                let class = (status / 100) as usize;
+                if (1..=5).contains(&class) {
+                    self.by_class[class - 1] += 1;
+                }
+                if self.latencies.len() == SPARKLINE_WIDTH {
+                    self.latencies.pop_front();
+                }
+                self.latencies.push_back(*duration_ms);
+            }
+            Event::Error { .. } => self.errors += 1,
+            Event::Rebuild | Event::Shutdown => {}
+        }
+    }
+
+    fn clear(&mut self) {
+        self.requests.clear();
+        self.by_class = [0; 5];
+        self.errors = 0;
+        self.latencies.clear();
+    }
+
+    /// the lines of the dashboard, fitted to a terminal `width` wide and `height` high
+    fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let mut header = format!(
+            "wrangler dev  {}",
+            self.url.as_deref().unwrap_or("starting...")
+        );
+        if let Some(preview) = &self.preview {
+            header.push_str(&format!("  preview {}", preview));
+        }
+        if self.paused {
+            header.push_str("  [paused]");
+        }
+
+        let mut counts = format!("requests {}", self.requests.len());
+        for (class, count) in self.by_class.iter().enumerate() {
+            counts.push_str(&format!("  {}xx {}", class + 1, count));
+        }
+        if self.errors > 0 {
+            counts.push_str(&format!("  errors {}", self.errors));
+        }
+        match self.filter {
+            Some(class) => counts.push_str(&format!("  showing {}xx", class)),
+            None => counts.push_str("  showing all"),
+        }
+
+        let mut lines = vec![
+            header,
+            counts,
+            format!("latency {}", sparkline(&self.latencies)),
+            "-".repeat(width),
+        ];
+
+        // the newest requests that fit, between the header and the help line
+        let rows = height.saturating_sub(lines.len() + 1);
+        let mut shown: Vec<String> = self
+            .requests
+            .iter()
+            .rev()
+            .filter(|row| match (self.filter, row.response) {
+                (Some(class), Some((status, _))) => status / 100 == class,
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+            .take(rows)
+            .map(Row::render)
+            .collect();
+        shown.reverse();
+        lines.extend(shown);
+        while lines.len() < height.saturating_sub(1) {
+            lines.push(String::new());
+        }
+        lines.push(HELP.to_string());
+
+        lines
+            .into_iter()
+            .map(|line| console::truncate_str(&line, width, "").into_owned())
+            .collect()
+    }
+}
+
+impl Row {
+    fn render(&self) -> String {
+        let response = match self.response {
+            Some((status, duration_ms)) => format!("{} {:>6}ms", status, duration_ms),
+            None => "...".to_string(),
+        };
+        format!(
+            "{} {:<7} {} {}",
+            self.time.format("%H:%M:%S"),
+            self.method,
+            response,
+            self.url
+        )
+    }
+}
+
+fn sparkline(latencies: &VecDeque<u64>) -> String {
+    let max = latencies.iter().copied().max().unwrap_or(0).max(1);
+    latencies
+        .iter()
+        .map(|latency| SPARKS[(*latency * (SPARKS.len() as u64 - 1) / max) as usize])
+        .collect()
+}
+
+/// takes over the terminal, unless stdout isn't one, in which case requests are logged as usual
+pub(super) fn start() -> Result<()> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        StdErr::warn(&format!(
+            "{} needs stdout to be a terminal, logging requests instead",
+            styles::highlight("--tui")
+        ));
+        return Ok(());
+    }
+
+    term.hide_cursor()?;
+    ACTIVE.store(true, Ordering::SeqCst);
+
+    thread::spawn(move || {
+        while is_active() {
+            if let Err(e) = redraw(&term) {
+                log::debug!("Failed to draw the dashboard: {}", e);
+            }
+            thread::sleep(REDRAW_INTERVAL);
+        }
+    });
+    thread::spawn(read_keys);
+    Ok(())
+}
+
+/// gives the terminal back once the session is over
+pub(super) fn stop() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        let term = Term::stdout();
+        term.clear_screen().ok();
+        term.show_cursor().ok();
+    }
+}
+
+/// whether the dashboard has the terminal, and requests shouldn't be logged to it
+pub(super) fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+pub(super) fn observe(event: &Event) {
+    if is_active() {
+        DASHBOARD.lock().unwrap().observe(event);
+    }
+}
+
+/// the preview requests are currently sent to
+pub(super) fn set_preview(preview: &str) {
+    if is_active() {
+        DASHBOARD.lock().unwrap().preview = Some(preview.to_string());
+    }
+}
+
+fn redraw(term: &Term) -> io::Result<()> {
+    let dashboard = DASHBOARD.lock().unwrap();
+    if dashboard.paused {
+        return Ok(());
+    }
+    let (height, width) = term.size();
+    let lines = dashboard.render(width as usize, height as usize);
+    drop(dashboard);
+
+    term.move_cursor_to(0, 0)?;
+    for line in lines {
+        term.clear_line()?;
+        term.write_line(&line)?;
+    }
+    Ok(())
+}
+
+fn read_keys() {
+    let term = Term::stdout();
+    while is_active() {
+        let key = match term.read_key() {
+            Ok(key) => key,
+            // Ctrl-C arrives as a key rather than a signal while waiting for one
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Key::Char('q'),
+            Err(e) => {
+                log::debug!("Failed to read a key for the dashboard: {}", e);
+                return;
+            }
+        };
+
+        let mut dashboard = DASHBOARD.lock().unwrap();
+        match key {
+            Key::Char('p') => dashboard.paused = !dashboard.paused,
+            Key::Char('c') => dashboard.clear(),
+            Key::Char('0') => dashboard.filter = None,
+            Key::Char(class @ '1'..='5') => {
+                dashboard.filter = class.to_digit(10).map(|class| class as u16)
+            }
+            // nothing waits on the result, the rebuild is reported like one for a change
+            Key::Char('r') => {
+                rebuild::force();
+            }
+            Key::Char('q') | Key::Char('\u{3}') => {
+                drop(dashboard);
+                shutdown::request();
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dashboard() -> Dashboard {
+        let mut dashboard = Dashboard::default();
+        for (id, status) in [(1, 200), (2, 404), (3, 200)].iter() {
+            dashboard.observe(&Event::Request {
+                id: *id,
+                method: "GET".to_string(),
+                url: format!("example.com/{}", id),
+            });
+            dashboard.observe(&Event::Response {
+                id: *id,
+                status: *status,
+                duration_ms: *id * 10,
+            });
+        }
+        dashboard
+    }
+
+    #[test]
+    fn responses_are_counted_by_status_class() {
+        let dashboard = dashboard();
+        assert_eq!(dashboard.by_class, [0, 2, 0, 1, 0]);
+        assert_eq!(sparkline(&dashboard.latencies), "▃▅█");
+    }
+
+    #[test]
+    fn requests_can_be_filtered_by_status_class() {
+        let mut dashboard = dashboard();
+        dashboard.filter = Some(4);
+
+        let lines = dashboard.render(80, 10);
+        assert_eq!(lines.len(), 10);
+        let requests: Vec<_> = lines.iter().filter(|line| line.contains("GET")).collect();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].ends_with("404     20ms example.com/2"));
+    }
+}
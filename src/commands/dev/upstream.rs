@@ -0,0 +1,328 @@
+//! The connection `wrangler dev` makes to the preview service.
+//!
+//! `--resolve host:ip` connects to `ip` whenever `host` would otherwise be
+//! looked up in DNS, like curl's flag of the same name. Only the address that
+//! is connected to changes: the Host header and the TLS server name (SNI) are
+//! still the real hostname, so certificates are validated as usual.
+//!
+//! `--trust-upstream-cert` adds a PEM certificate to the roots the preview
+//! service's certificate is validated against, and `--upstream-roots-only`
+//! trusts only that certificate, for private preview endpoints.
+//! `--accept-invalid-upstream-certs` goes further and accepts any certificate
+//! at all, which is only ever meant for a staging endpoint whose certificate
+//! can't be had. It leaves the connection open to interception, so wrangler
+//! warns about it whenever it's used.
+//!
+//! `--log-upstream-ip` notes which address each request was sent to in the
+//! request log, for when failures seem to follow particular edge nodes. The
+//! address comes from the connection hyper sent the request over, so it is the
+//! one actually used, even when DNS returned several.
+use crate::commands::dev::upstream_timeout::ConnectTimeout;
+use crate::commands::dev::DevOptions;
+use crate::terminal::message::{Message, StdErr};
+use crate::terminal::styles;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Once};
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, Result};
+use futures_util::future::{BoxFuture, FutureExt};
+use hyper::client::connect::dns::{GaiResolver, Name};
+use hyper::client::connect::HttpInfo;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::{Body, Response};
+use hyper_rustls::HttpsConnector;
+use rustls::internal::pemfile;
+use rustls::{Certificate, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError};
+use tokio_rustls::webpki::DNSNameRef;
+
+static INSECURE_WARNING: Once = Once::new();
+
+pub(super) type Connector = ConnectTimeout<HttpsConnector<HttpConnector<Resolver>>>;
+
+/// parses a `host:ip` pair, where an IPv6 address may be wrapped in brackets
+pub fn parse_resolve(resolve: &str) -> Result<(String, IpAddr)> {
+    let (host, ip) = resolve
+        .split_once(':')
+        .filter(|(host, _)| !host.is_empty())
+        .ok_or_else(|| anyhow!("Expected host:ip, like example.com:127.0.0.1"))?;
+    let ip = ip
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|_| anyhow!("{} is not a valid IP address", ip))?;
+    Ok((host.to_lowercase(), ip))
+}
+
+/// looks hosts up in DNS, unless they were overridden with `--resolve`
+#[derive(Clone)]
+pub(super) struct Resolver {
+    overrides: Arc<HashMap<String, IpAddr>>,
+    gai: GaiResolver,
+}
+
+impl Resolver {
+    fn new(options: &DevOptions) -> Resolver {
+        Resolver {
+            overrides: Arc::new(options.resolve.iter().cloned().collect()),
+            gai: GaiResolver::new(),
+        }
+    }
+}
+
+impl Service<Name> for Resolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.gai.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        if let Some(ip) = self.overrides.get(name.as_str()) {
+            log::info!("Resolving {} to {} as set by --resolve", name, ip);
+            // the connector fills in the port
+            let addrs = vec![SocketAddr::new(*ip, 0)];
+            return async move { Ok(addrs.into_iter()) }.boxed();
+        }
+
+        let lookup = self.gai.call(name);
+        async move { Ok(lookup.await?.collect::<Vec<_>>().into_iter()) }.boxed()
+    }
+}
+
+/// the connector used for requests to the preview service
+pub(super) fn connector(options: &DevOptions) -> Result<Connector> {
+    let mut http = HttpConnector::new_with_resolver(Resolver::new(options));
+    http.enforce_http(false);
+
+    let mut tls = rustls::ClientConfig::new();
+    tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    tls.root_store = if options.upstream_roots_only {
+        RootCertStore::empty()
+    } else {
+        native_roots()?
+    };
+
+    if let Some(path) = &options.trust_upstream_cert {
+        for cert in load_certs(path)? {
+            tls.root_store.add(&cert).map_err(|e| {
+                anyhow!(
+                    "{} given to --trust-upstream-cert is not a valid certificate: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+        }
+    }
+
+    if options.accept_invalid_upstream_certs {
+        INSECURE_WARNING.call_once(|| {
+            StdErr::warn(&format!(
+                "{} Certificates of the preview service are not being checked, as --accept-invalid-upstream-certs was given. Anyone between you and it can read and change your requests",
+                styles::warning("INSECURE:")
+            ))
+        });
+        tls.dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCert));
+    }
+
+    let https = HttpsConnector::from((http, tls));
+    Ok(ConnectTimeout::new(https, options.connect_timeout()))
+}
+
+/// the address of the preview service the response came from, if it came over a connection
+pub(super) fn peer_addr(resp: &Response<Body>) -> Option<SocketAddr> {
+    resp.extensions()
+        .get::<HttpInfo>()
+        .map(HttpInfo::remote_addr)
+}
+
+/// accepts whatever certificate the preview service presents, for --accept-invalid-upstream-certs
+struct AcceptAnyCert;
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn native_roots() -> Result<RootCertStore> {
+    match rustls_native_certs::load_native_certs() {
+        Ok(store) => Ok(store),
+        Err((Some(store), e)) => {
+            log::warn!("Could not load all native certificates: {}", e);
+            Ok(store)
+        }
+        Err((None, e)) => Err(anyhow!(
+            "Could not load the native certificate store: {}",
+            e
+        )),
+    }
+}
+
+/// every certificate in a PEM file, of which there must be at least one
+fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let file = File::open(path).map_err(|e| {
+        anyhow!(
+            "Could not open {} given to --trust-upstream-cert: {}",
+            path.display(),
+            e
+        )
+    })?;
+    match pemfile::certs(&mut BufReader::new(file)) {
+        Ok(certs) if !certs.is_empty() => Ok(certs),
+        _ => Err(anyhow!(
+            "{} given to --trust-upstream-cert does not contain a PEM certificate",
+            path.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_pairs_are_parsed() {
+        let (host, ip) = parse_resolve("Example.com:127.0.0.1").unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+
+        let (_, ip) = parse_resolve("example.com:[::1]").unwrap();
+        assert_eq!(ip, "::1".parse::<IpAddr>().unwrap());
+
+        assert!(parse_resolve("example.com").is_err());
+        assert!(parse_resolve(":127.0.0.1").is_err());
+        assert!(parse_resolve("example.com:localhost").is_err());
+    }
+
+    #[tokio::test]
+    async fn responses_know_the_address_they_came_from() {
+        use hyper::service::{make_service_fn, service_fn};
+
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(service_fn(|_| async {
+                Ok::<_, hyper::Error>(Response::new(Body::from("ok")))
+            }))
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let url = format!("http://{}/", addr).parse().unwrap();
+        let resp = hyper::Client::new().get(url).await.unwrap();
+        assert_eq!(peer_addr(&resp), Some(addr));
+        assert_eq!(peer_addr(&Response::new(Body::empty())), None);
+    }
+
+    #[test]
+    fn files_without_certificates_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.pem");
+        std::fs::write(&path, "not a certificate").unwrap();
+
+        assert!(load_certs(&path).is_err());
+        assert!(load_certs(&dir.path().join("missing.pem")).is_err());
+    }
+
+    /// a certificate and private key for localhost, signed by nobody but itself
+    fn self_signed() -> (Certificate, rustls::PrivateKey) {
+        use openssl::asn1::Asn1Time;
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::rsa::Rsa;
+        use openssl::x509::extension::SubjectAlternativeName;
+        use openssl::x509::{X509NameBuilder, X509};
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .build(&cert.x509v3_context(None, None))
+            .unwrap();
+        cert.append_extension(san).unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (
+            Certificate(cert.build().to_der().unwrap()),
+            rustls::PrivateKey(key.private_key_to_der().unwrap()),
+        )
+    }
+
+    /// a preview service on localhost with a self-signed certificate, returning its port
+    async fn self_signed_upstream() -> u16 {
+        use hyper::service::service_fn;
+        use tokio_rustls::TlsAcceptor;
+
+        let (cert, key) = self_signed();
+        let mut cfg = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        cfg.set_single_cert(vec![cert], key).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(cfg));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(tls) = acceptor.accept(tcp).await {
+                        let service = service_fn(|_| async {
+                            Ok::<_, hyper::Error>(Response::new(Body::from("private")))
+                        });
+                        let _ = hyper::server::conn::Http::new()
+                            .serve_connection(tls, service)
+                            .await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn invalid_certificates_are_only_accepted_when_asked() {
+        let port = self_signed_upstream().await;
+        let url: hyper::Uri = format!("https://localhost:{}/", port).parse().unwrap();
+        let options = |accept_invalid_upstream_certs| DevOptions {
+            resolve: vec![("localhost".to_string(), "127.0.0.1".parse().unwrap())],
+            // there's nothing to trust, and the system's roots wouldn't trust it anyway
+            upstream_roots_only: true,
+            accept_invalid_upstream_certs,
+            ..DevOptions::default()
+        };
+
+        let client = hyper::Client::builder().build::<_, Body>(connector(&options(false)).unwrap());
+        assert!(client.get(url.clone()).await.is_err());
+
+        let client = hyper::Client::builder().build::<_, Body>(connector(&options(true)).unwrap());
+        let resp = client.get(url).await.unwrap();
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        assert_eq!(body, "private");
+    }
+}
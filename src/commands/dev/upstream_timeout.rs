@@ -0,0 +1,292 @@
+//! Limits on how long dev waits on the preview service, split by what it's
+//! waiting for, so an unreachable preview host can be told apart from a slow
+//! Worker.
+//!
+//! `--connect-timeout <secs>` limits how long connecting to the preview
+//! service takes, up to and including the TLS handshake. `--read-timeout
+//! <secs>` limits how long dev waits for the response headers once the request
+//! is sent, and then for each chunk of the body. A request that runs out of
+//! either is answered with a `504 Gateway Timeout` saying which it was. A body
+//! that stalls once the response has started can only be cut off, so the client
+//! sees it end early. Neither is limited by default.
+use crate::commands::dev::error_response;
+use crate::terminal::message::{Message, StdErr};
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::future::{BoxFuture, FutureExt};
+use hyper::body::HttpBody;
+use hyper::service::Service;
+use hyper::{Body, Response, StatusCode, Uri};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    Connect,
+    Read,
+}
+
+/// the error for a request to the preview service that ran out of time
+#[derive(Debug)]
+pub(super) struct TimedOut {
+    phase: Phase,
+    after: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.phase {
+            Phase::Connect => write!(
+                f,
+                "Connecting to the preview service took longer than {}s, the limit set by --connect-timeout",
+                self.after.as_secs_f64()
+            ),
+            Phase::Read => write!(
+                f,
+                "The preview service took longer than {}s to respond, the limit set by --read-timeout",
+                self.after.as_secs_f64()
+            ),
+        }
+    }
+}
+
+impl Error for TimedOut {}
+
+/// a connector that gives up on connections that take longer than `timeout`
+#[derive(Clone)]
+pub(super) struct ConnectTimeout<C> {
+    inner: C,
+    timeout: Option<Duration>,
+}
+
+impl<C> ConnectTimeout<C> {
+    pub(super) fn new(inner: C, timeout: Option<Duration>) -> ConnectTimeout<C> {
+        ConnectTimeout { inner, timeout }
+    }
+}
+
+impl<C> Service<Uri> for ConnectTimeout<C>
+where
+    C: Service<Uri> + 'static,
+    C::Error: Into<BoxError>,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<C::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.inner.call(uri);
+        let timeout = self.timeout;
+        async move {
+            let timeout = match timeout {
+                Some(timeout) => timeout,
+                None => return connecting.await.map_err(Into::into),
+            };
+            match tokio::time::timeout(timeout, connecting).await {
+                Ok(connected) => connected.map_err(Into::into),
+                Err(_) => Err(TimedOut {
+                    phase: Phase::Connect,
+                    after: timeout,
+                }
+                .into()),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// waits on a response from the preview service for no longer than `timeout`,
+/// and on each chunk of its body for no longer than that again. A request that
+/// runs out of time is answered with a 504, which is shared by any coalesced with it
+pub(super) async fn guard<F>(
+    timeout: Option<Duration>,
+    responding: F,
+) -> anyhow::Result<Response<Body>>
+where
+    F: Future<Output = anyhow::Result<Response<Body>>>,
+{
+    let responded = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, responding).await {
+            Ok(responded) => responded,
+            Err(_) => Err(TimedOut {
+                phase: Phase::Read,
+                after: timeout,
+            }
+            .into()),
+        },
+        None => responding.await,
+    };
+    let resp = match responded {
+        Ok(resp) => resp,
+        Err(e) => {
+            return match gateway_timeout(&e) {
+                Some(resp) => Ok(resp),
+                None => Err(e),
+            }
+        }
+    };
+
+    match timeout {
+        Some(timeout) if !resp.body().is_end_stream() => Ok(guard_body(resp, timeout)),
+        _ => Ok(resp),
+    }
+}
+
+/// forwards the body as it arrives, cutting it off if it stalls for longer than `timeout`
+fn guard_body(resp: Response<Body>, timeout: Duration) -> Response<Body> {
+    let (parts, mut body) = resp.into_parts();
+    let (mut sender, guarded) = Body::channel();
+    tokio::spawn(async move {
+        loop {
+            match tokio::time::timeout(timeout, body.data()).await {
+                Ok(Some(Ok(chunk))) => {
+                    if sender.send_data(chunk).await.is_err() {
+                        // the client is no longer reading the response
+                        break;
+                    }
+                }
+                Ok(Some(Err(e))) => {
+                    log::debug!("Failed to read response body: {}", e);
+                    sender.abort();
+                    break;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    StdErr::warn(&format!(
+                        "Cut off a response body that stalled for longer than {}s, the limit set by --read-timeout",
+                        timeout.as_secs_f64()
+                    ));
+                    sender.abort();
+                    break;
+                }
+            }
+        }
+    });
+    Response::from_parts(parts, guarded)
+}
+
+/// the 504 for a request that failed because it ran out of time, if it did
+fn gateway_timeout(e: &anyhow::Error) -> Option<Response<Body>> {
+    let timed_out = e
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<TimedOut>())?;
+    StdErr::warn(&timed_out.to_string());
+
+    Some(error_response::build(
+        StatusCode::GATEWAY_TIMEOUT,
+        timed_out.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future;
+    use hyper::client::HttpConnector;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Request;
+    use std::io;
+    use tokio::net::TcpStream;
+
+    /// a connector whose connections never finish being made, like one to a host that
+    /// drops every packet
+    #[derive(Clone)]
+    struct Unreachable;
+
+    impl Service<Uri> for Unreachable {
+        type Response = TcpStream;
+        type Error = io::Error;
+        type Future = future::Pending<io::Result<TcpStream>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Uri) -> Self::Future {
+            future::pending()
+        }
+    }
+
+    /// a server that takes `delay` to send its response headers
+    async fn slow_upstream(delay: Duration) -> String {
+        let make_service = make_service_fn(move |_| async move {
+            Ok::<_, hyper::Error>(service_fn(move |_: Request<Body>| async move {
+                tokio::time::sleep(delay).await;
+                Ok::<_, hyper::Error>(Response::new(Body::from("slow")))
+            }))
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    /// the status and body dev answers with when sending a request with `client`
+    async fn guarded<C>(
+        client: hyper::Client<C>,
+        url: &str,
+        read_timeout: Duration,
+    ) -> (StatusCode, String)
+    where
+        C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+    {
+        let responding = client
+            .get(url.parse().unwrap())
+            .map(|resp| Ok::<_, anyhow::Error>(resp?));
+        let resp = guard(Some(read_timeout), responding).await.unwrap();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp).await.unwrap();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn slow_connections_time_out() {
+        let connector = ConnectTimeout::new(Unreachable, Some(Duration::from_millis(50)));
+        let client = hyper::Client::builder().build::<_, Body>(connector);
+
+        let (status, body) = guarded(client, "http://example.com/", Duration::from_secs(5)).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert!(body.contains("--connect-timeout"));
+    }
+
+    #[tokio::test]
+    async fn slow_responses_time_out() {
+        let url = slow_upstream(Duration::from_secs(5)).await;
+        let connector = ConnectTimeout::new(HttpConnector::new(), Some(Duration::from_secs(5)));
+        let client = hyper::Client::builder().build::<_, Body>(connector);
+
+        let (status, body) = guarded(client, &url, Duration::from_millis(50)).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert!(body.contains("--read-timeout"));
+    }
+
+    #[tokio::test]
+    async fn responses_within_the_limits_are_passed_on() {
+        let url = slow_upstream(Duration::from_millis(10)).await;
+        let connector = ConnectTimeout::new(HttpConnector::new(), Some(Duration::from_secs(5)));
+        let client = hyper::Client::builder().build::<_, Body>(connector);
+
+        let (status, body) = guarded(client, &url, Duration::from_secs(5)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "slow");
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_passed_on() {
+        let failing = async { Err(anyhow::anyhow!("connection refused")) };
+        let e = guard(Some(Duration::from_secs(5)), failing)
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "connection refused");
+    }
+}
@@ -0,0 +1,120 @@
+//! `--variant-param <name>` serves other previews alongside the one for your
+//! local code, picked by a query parameter, to try A/B branches of a Worker side
+//! by side. Each variant is a preview id given with `--variant <value>=<id>`,
+//! like the ids `--preview-token` takes, from a preview uploaded earlier:
+//!
+//! ```text
+//! wrangler dev --variant-param v --variant a=<preview id> --variant b=<preview id>
+//! ```
+//!
+//! `/checkout?v=a` is then sent to the first preview and `/checkout?v=b` to the
+//! second. Requests without the parameter, or with a value that isn't a variant,
+//! go to the preview for your local code as usual. The parameter is left on the
+//! request, so the Worker sees the same URL either way.
+//!
+//! Variants are previews on the preview service, so they're only used without
+//! authentication, like `--preview-token`.
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use hyper::header::HeaderValue;
+use hyper::Uri;
+use url::form_urlencoded;
+
+use crate::commands::dev::DevOptions;
+
+/// a preview served for requests with `--variant-param` set to `name`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    name: String,
+    preview_id: String,
+}
+
+impl FromStr for Variant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, preview_id) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected a variant like a=<preview id>, got {}", s))?;
+        let (name, preview_id) = (name.trim(), preview_id.trim());
+        if name.is_empty() || preview_id.is_empty() {
+            anyhow::bail!("Expected a variant like a=<preview id>, got {}", s)
+        }
+        HeaderValue::from_str(preview_id)
+            .map_err(|_| anyhow!("{} is not a valid preview id", preview_id))?;
+        Ok(Variant {
+            name: name.to_string(),
+            preview_id: preview_id.to_string(),
+        })
+    }
+}
+
+/// the preview id of the variant `uri` asks for, if it asks for one that was given
+pub(super) fn select<'a>(uri: &Uri, options: &'a DevOptions) -> Option<&'a str> {
+    let param = options.variant_param.as_deref()?;
+    let query = uri.query()?;
+    let (_, value) = form_urlencoded::parse(query.as_bytes()).find(|(name, _)| name == param)?;
+    options
+        .variant
+        .iter()
+        .find(|variant| variant.name == value)
+        .map(|variant| variant.preview_id.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> DevOptions {
+        DevOptions {
+            variant_param: Some("v".to_string()),
+            variant: vec![
+                "a=preview-a".parse().unwrap(),
+                "b=preview-b".parse().unwrap(),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn selected<'a>(uri: &str, options: &'a DevOptions) -> Option<&'a str> {
+        select(&uri.parse().unwrap(), options)
+    }
+
+    #[test]
+    fn variants_are_picked_by_the_query_param() {
+        let options = options();
+        assert_eq!(selected("/checkout?v=a", &options), Some("preview-a"));
+        assert_eq!(
+            selected("/checkout?page=2&v=b", &options),
+            Some("preview-b")
+        );
+        assert_eq!(selected("/checkout?v=c", &options), None);
+        assert_eq!(selected("/checkout?variant=a", &options), None);
+        assert_eq!(selected("/checkout", &options), None);
+    }
+
+    #[test]
+    fn variants_are_ignored_without_a_param() {
+        let options = DevOptions {
+            variant_param: None,
+            ..options()
+        };
+        assert_eq!(selected("/checkout?v=a", &options), None);
+    }
+
+    #[test]
+    fn variants_need_a_name_and_an_id() {
+        assert_eq!(
+            " a = abc ".parse::<Variant>().unwrap(),
+            Variant {
+                name: "a".to_string(),
+                preview_id: "abc".to_string()
+            }
+        );
+        assert!("abc".parse::<Variant>().is_err());
+        assert!("=abc".parse::<Variant>().is_err());
+        assert!("a=".parse::<Variant>().is_err());
+        assert!("a=ab\nc".parse::<Variant>().is_err());
+    }
+}
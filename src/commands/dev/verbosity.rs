@@ -0,0 +1,202 @@
+//! `-v`, `-vv` and `-vvv` show progressively more of each request, below its
+//! line in the request log:
+//!
+//! | Flag   | Shows                                                             |
+//! | ------ | ----------------------------------------------------------------- |
+//! | none   | one line per request                                              |
+//! | `-v`   | the headers sent to the preview service and those it answered with |
+//! | `-vv`  | the start of text bodies both ways, up to 2KiB                   |
+//! | `-vvv` | every TLS handshake with the https server, as `--verbose-tls` does |
+//!
+//! Each level includes those before it. A body is only shown when its length is
+//! known up front and it's at most 1MiB, as it has to be read in full before
+//! being passed on, so streamed bodies still stream. Requests dev answers
+//! itself, like CORS preflights, only get their log line.
+//!
+//! The flags for a single kind of output still work on their own, so
+//! `--verbose-tls` describes handshakes without showing any headers.
+//! `--verbose` is separate, and is about building and uploading the Worker.
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use hyper::body::HttpBody;
+use hyper::header::HeaderMap;
+use hyper::{Body, Request, Response};
+
+use crate::commands::dev::{replace, tui};
+
+/// the most of a body that is shown
+const MAX_SHOWN: usize = 2 * 1024;
+/// the largest body that is read to be shown
+const MAX_READ: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Requests,
+    Headers,
+    Bodies,
+    Connections,
+}
+
+impl Default for Verbosity {
+    fn default() -> Self {
+        Verbosity::Requests
+    }
+}
+
+impl Verbosity {
+    /// the level for the number of times `-v` was given
+    pub fn from_occurrences(count: u64) -> Verbosity {
+        match count {
+            0 => Verbosity::Requests,
+            1 => Verbosity::Headers,
+            2 => Verbosity::Bodies,
+            _ => Verbosity::Connections,
+        }
+    }
+}
+
+/// what is shown of a request or a response below its log line
+pub(super) struct Shown(String);
+
+/// the headers and maybe the body of a request about to be sent to the preview service
+pub(super) async fn request(
+    req: Request<Body>,
+    verbosity: Verbosity,
+) -> Result<(Request<Body>, Option<Shown>)> {
+    if verbosity < Verbosity::Headers {
+        return Ok((req, None));
+    }
+
+    let (parts, body) = req.into_parts();
+    let mut shown = format!("> {} {} {:?}\n", parts.method, parts.uri, parts.version);
+    headers(&mut shown, '>', &parts.headers);
+    let body = self::body(&mut shown, '>', &parts.headers, body, verbosity).await?;
+    Ok((Request::from_parts(parts, body), Some(Shown(shown))))
+}
+
+/// the headers and maybe the body of a response about to be sent to the client
+pub(super) async fn response(
+    resp: Response<Body>,
+    verbosity: Verbosity,
+) -> Result<(Response<Body>, Option<Shown>)> {
+    if verbosity < Verbosity::Headers {
+        return Ok((resp, None));
+    }
+
+    let (parts, body) = resp.into_parts();
+    let mut shown = format!("< {:?} {}\n", parts.version, parts.status);
+    headers(&mut shown, '<', &parts.headers);
+    let body = self::body(&mut shown, '<', &parts.headers, body, verbosity).await?;
+    Ok((Response::from_parts(parts, body), Some(Shown(shown))))
+}
+
+/// prints what was captured of a request and its response, after its log line
+pub(super) fn print(request: Option<Shown>, response: Option<Shown>) {
+    // the dashboard has no room for more than the log line
+    if tui::is_active() {
+        return;
+    }
+    for Shown(shown) in request.into_iter().chain(response) {
+        print!("{}", shown);
+    }
+}
+
+fn headers(shown: &mut String, direction: char, headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let _ = writeln!(shown, "{} {}: {}", direction, name, value);
+    }
+}
+
+async fn body(
+    shown: &mut String,
+    direction: char,
+    headers: &HeaderMap,
+    body: Body,
+    verbosity: Verbosity,
+) -> Result<Body> {
+    if verbosity < Verbosity::Bodies {
+        return Ok(body);
+    }
+
+    let len = match body.size_hint().exact() {
+        Some(0) => return Ok(body),
+        Some(len) if len <= MAX_READ => len,
+        Some(len) => {
+            let _ = writeln!(shown, "{} [{} bytes, too large to show]", direction, len);
+            return Ok(body);
+        }
+        None => {
+            let _ = writeln!(shown, "{} [streamed body, not shown]", direction);
+            return Ok(body);
+        }
+    };
+
+    let bytes = hyper::body::to_bytes(body).await?;
+    match std::str::from_utf8(&bytes) {
+        Ok(text) if replace::is_text(headers) => {
+            let end = (0..=MAX_SHOWN.min(text.len()))
+                .rev()
+                .find(|end| text.is_char_boundary(*end))
+                .unwrap_or(0);
+            for line in text[..end].lines() {
+                let _ = writeln!(shown, "{} {}", direction, line);
+            }
+            if end < text.len() {
+                let _ = writeln!(shown, "{} [{} more bytes]", direction, text.len() - end);
+            }
+        }
+        _ => {
+            let _ = writeln!(shown, "{} [{} bytes]", direction, len);
+        }
+    }
+    Ok(Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::CONTENT_TYPE;
+
+    #[test]
+    fn each_v_adds_a_level() {
+        assert_eq!(Verbosity::from_occurrences(0), Verbosity::Requests);
+        assert_eq!(Verbosity::from_occurrences(2), Verbosity::Bodies);
+        assert_eq!(Verbosity::from_occurrences(5), Verbosity::Connections);
+    }
+
+    #[tokio::test]
+    async fn bodies_are_shown_from_vv() {
+        let resp = || {
+            Response::builder()
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Body::from("hello\nworld"))
+                .unwrap()
+        };
+
+        let (_, shown) = response(resp(), Verbosity::Requests).await.unwrap();
+        assert!(shown.is_none());
+
+        let (_, shown) = response(resp(), Verbosity::Headers).await.unwrap();
+        let Shown(shown) = shown.unwrap();
+        assert_eq!(shown, "< HTTP/1.1 200 OK\n< content-type: text/plain\n");
+
+        let (resp, shown) = response(resp(), Verbosity::Bodies).await.unwrap();
+        let Shown(shown) = shown.unwrap();
+        assert!(shown.ends_with("< hello\n< world\n"));
+        // the body is still passed on
+        assert_eq!(hyper::body::to_bytes(resp).await.unwrap(), "hello\nworld");
+    }
+
+    #[tokio::test]
+    async fn streamed_bodies_are_left_alone() {
+        let (_sender, body) = Body::channel();
+        let req = Request::post("/upload").body(body).unwrap();
+
+        let (req, shown) = request(req, Verbosity::Bodies).await.unwrap();
+        let Shown(shown) = shown.unwrap();
+        assert!(shown.ends_with("> [streamed body, not shown]\n"));
+        assert_eq!(req.body().size_hint().exact(), None);
+    }
+}
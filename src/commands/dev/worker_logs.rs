@@ -0,0 +1,174 @@
+//! `--show-logs` prints the Worker's `console.log`, `console.error` and other
+//! console calls as plain lines among the request log, instead of the devtools
+//! events they arrive as, and tags each with the request that was being handled
+//! when it was logged:
+//!
+//! ```text
+//! [#3] console.log: loading user 42
+//! [2021-06-01 12:00:00] GET localhost:8787/users/42 HTTP/1.1 200 OK (#3)
+//! ```
+//!
+//! The preview service doesn't say which request a console call came from, so
+//! it's matched up by time: a line logged while a single request is in flight
+//! belongs to that request. While several are in flight it's tagged with all of
+//! them, like `[#3|#4]`, and one logged while none is, like from a request
+//! whose response has already been passed on, isn't tagged at all.
+//!
+//! While `--tui` has the terminal, console calls aren't printed, as they would
+//! draw over the dashboard, just like the request log.
+use crate::commands::dev::tui;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+static SHOW_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// ids of the requests being handled, oldest first
+static IN_FLIGHT: Lazy<Mutex<Vec<u64>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub(super) fn init() {
+    SHOW_LOGS.store(true, Ordering::Relaxed);
+}
+
+pub(super) fn is_enabled() -> bool {
+    SHOW_LOGS.load(Ordering::Relaxed)
+}
+
+/// counts a request as in flight, for tagging console calls, until it is dropped
+pub(super) struct InFlight {
+    id: u64,
+}
+
+impl InFlight {
+    pub(super) fn start(id: u64) -> Option<InFlight> {
+        if !is_enabled() {
+            return None;
+        }
+        IN_FLIGHT.lock().unwrap().push(id);
+        Some(InFlight { id })
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().retain(|id| *id != self.id);
+    }
+}
+
+/// prints a message from the devtools socket if it's a console call,
+/// returning whether it was one
+pub(super) fn print(message: &str) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    let message = match serde_json::from_str::<Value>(message) {
+        Ok(message) => message,
+        Err(_) => return false,
+    };
+    let in_flight = IN_FLIGHT.lock().unwrap().clone();
+    match format(&message, &in_flight) {
+        Some(line) => {
+            // the dashboard has the terminal, which the line would draw over
+            if !tui::is_active() {
+                println!("{}", line);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// a `Runtime.consoleAPICalled` event as a line of output
+fn format(message: &Value, in_flight: &[u64]) -> Option<String> {
+    if message["method"] != "Runtime.consoleAPICalled" {
+        return None;
+    }
+    let params = &message["params"];
+    let kind = params["type"].as_str().unwrap_or("log");
+    let args: Vec<String> = params["args"]
+        .as_array()
+        .map(|args| args.iter().map(format_arg).collect())
+        .unwrap_or_default();
+
+    let tag = if in_flight.is_empty() {
+        String::new()
+    } else {
+        let ids: Vec<String> = in_flight.iter().map(|id| format!("#{}", id)).collect();
+        format!("[{}] ", ids.join("|"))
+    };
+    Some(format!("{}console.{}: {}", tag, kind, args.join(" ")))
+}
+
+/// a console argument, printed the way the browser's console would show it
+fn format_arg(arg: &Value) -> String {
+    match &arg["value"] {
+        Value::String(value) => value.clone(),
+        Value::Null => arg["unserializableValue"]
+            .as_str()
+            .or_else(|| arg["description"].as_str())
+            .or_else(|| arg["type"].as_str())
+            .unwrap_or("")
+            .to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn console_call(kind: &str, args: Value) -> Value {
+        json!({
+            "method": "Runtime.consoleAPICalled",
+            "params": { "type": kind, "args": args, "executionContextId": 1 }
+        })
+    }
+
+    #[test]
+    fn console_calls_are_tagged_with_requests_in_flight() {
+        let message = console_call(
+            "log",
+            json!([
+                { "type": "string", "value": "loading user" },
+                { "type": "number", "value": 42 },
+                { "type": "object", "description": "Object" }
+            ]),
+        );
+
+        assert_eq!(
+            format(&message, &[3]).unwrap(),
+            "[#3] console.log: loading user 42 Object"
+        );
+        assert_eq!(
+            format(&message, &[3, 4]).unwrap(),
+            "[#3|#4] console.log: loading user 42 Object"
+        );
+        assert_eq!(
+            format(&message, &[]).unwrap(),
+            "console.log: loading user 42 Object"
+        );
+    }
+
+    #[test]
+    fn errors_keep_their_kind() {
+        let message = console_call(
+            "error",
+            json!([{ "type": "number", "unserializableValue": "NaN" }]),
+        );
+        assert_eq!(format(&message, &[7]).unwrap(), "[#7] console.error: NaN");
+    }
+
+    #[test]
+    fn other_events_are_left_alone() {
+        let message = json!({
+            "method": "Runtime.exceptionThrown",
+            "params": {}
+        });
+        assert_eq!(format(&message, &[1]), None);
+        assert_eq!(format(&json!({ "id": 2, "result": {} }), &[1]), None);
+    }
+}
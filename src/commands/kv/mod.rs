@@ -114,6 +114,7 @@ mod tests {
             build: None,
             wasm_modules: None,
             usage_model: None,
+            script_path: None,
         };
         assert!(kv::get_namespace_id(&target_with_dup_kv_bindings, "").is_err());
     }
@@ -17,7 +17,7 @@ pub(crate) fn headers(feature: Option<Feature>) -> HeaderMap {
     headers
 }
 
-fn get_user_agent(feature: Option<Feature>) -> String {
+pub(crate) fn get_user_agent(feature: Option<Feature>) -> String {
     let version = if install::target::DEBUG {
         "dev"
     } else {
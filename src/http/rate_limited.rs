@@ -0,0 +1,77 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use chrono::DateTime;
+use reqwest::blocking::Response;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+
+/// the error for a request the API turned away with a 429, so callers can tell
+/// being rate limited apart from auth and network errors, and wait it out
+#[derive(Debug)]
+pub struct RateLimited {
+    /// how long the API asked to wait before trying again, if it said
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimited {
+    /// the error for `res`, if it's a 429
+    pub fn from_response(res: &Response) -> Option<RateLimited> {
+        if res.status() != StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+        let retry_after = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        Some(RateLimited { retry_after })
+    }
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The Cloudflare API is rate limiting your requests")?;
+        if let Some(retry_after) = self.retry_after {
+            write!(f, ", and asked to wait {}s", retry_after.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// a Retry-After given as a number of seconds or as the HTTP date to wait until
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let until: SystemTime = DateTime::parse_from_rfc2822(value).ok()?.into();
+    // a date in the past means there's no need to wait
+    Some(until.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_can_be_seconds_or_a_date() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_450);
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
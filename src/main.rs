@@ -84,12 +84,14 @@ fn run() -> Result<()> {
             port,
             local_protocol,
             upstream_protocol,
+            options,
         } => exec::dev(
             host,
             ip,
             port,
             local_protocol,
             upstream_protocol,
+            options,
             &cli_params,
         ),
         Command::Whoami => exec::whoami(),
@@ -24,7 +24,7 @@ use crate::settings::global_user::GlobalUser;
 use crate::settings::toml::{Target, UploadFormat};
 use crate::terminal::message::{Message, StdOut};
 use crate::terminal::open_browser;
-use crate::watch::watch_and_build;
+use crate::watch::{watch_and_build, WatchScope};
 
 pub fn preview(
     mut target: Target,
@@ -161,7 +161,7 @@ fn watch_for_changes(
     let sites_preview: bool = target.site.is_some();
 
     let (tx, rx) = channel();
-    watch_and_build(&target, Some(tx))?;
+    watch_and_build(&target, Some(tx), WatchScope::default())?;
 
     while rx.recv().is_ok() {
         if let Ok(new_id) = upload(&mut target, user, sites_preview, verbose) {
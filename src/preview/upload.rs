@@ -162,6 +162,9 @@ fn authenticated_upload(
         .multipart(script_upload_form)
         .send()?;
 
+    if let Some(rate_limited) = http::RateLimited::from_response(&res) {
+        return Err(rate_limited.into());
+    }
     let status = res.status();
     let text = res.text()?;
     if !status.is_success() {
@@ -205,6 +208,9 @@ fn unauthenticated_upload(target: &Target) -> Result<Preview> {
         .multipart(script_upload_form)
         .send()?;
 
+    if let Some(rate_limited) = http::RateLimited::from_response(&res) {
+        return Err(rate_limited.into());
+    }
     let status = res.status();
     let text = res.text()?;
     if !status.is_success() {
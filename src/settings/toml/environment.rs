@@ -32,6 +32,7 @@ pub struct Environment {
     pub text_blobs: Option<HashMap<String, PathBuf>>,
     pub triggers: Option<Triggers>,
     pub durable_objects: Option<DurableObjects>,
+    pub preview_host: Option<String>,
 }
 
 impl Environment {
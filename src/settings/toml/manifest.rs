@@ -21,6 +21,7 @@ use crate::settings::toml::dev::Dev;
 use crate::settings::toml::durable_objects::DurableObjects;
 use crate::settings::toml::environment::Environment;
 use crate::settings::toml::kv_namespace::{ConfigKvNamespace, KvNamespace};
+use crate::settings::toml::preview_host::parse_preview_host;
 use crate::settings::toml::route::RouteConfig;
 use crate::settings::toml::site::Site;
 use crate::settings::toml::target_type::TargetType;
@@ -53,6 +54,7 @@ pub struct Manifest {
     // as a TOML inline table (this would prevent confusion with environments too!)
     pub site: Option<Site>,
     pub dev: Option<Dev>,
+    pub preview_host: Option<String>,
     #[serde(alias = "kv-namespaces")]
     pub kv_namespaces: Option<Vec<ConfigKvNamespace>>,
     pub env: Option<HashMap<String, Environment>>,
@@ -352,6 +354,7 @@ impl Manifest {
             text_blobs: self.text_blobs.clone(), // Inherited
             usage_model: self.usage_model, // Top level
             wasm_modules: self.wasm_modules.clone(),
+            script_path: None,
         };
 
         let environment = self.get_environment(environment_name)?;
@@ -386,6 +389,30 @@ impl Manifest {
         Ok(target)
     }
 
+    /// the preview service host `wrangler dev` sends requests to, if one is configured.
+    /// Inherited: an environment's preview_host overrides the top level one
+    pub fn get_preview_host(&self, environment_name: Option<&str>) -> Result<Option<String>> {
+        let environment = self.get_environment(environment_name)?;
+        let (preview_host, table) = match environment.and_then(|env| env.preview_host.as_ref()) {
+            Some(preview_host) => (
+                Some(preview_host),
+                format!("[env.{}]", environment_name.unwrap_or_default()),
+            ),
+            None => (self.preview_host.as_ref(), "the top level".to_string()),
+        };
+
+        match preview_host {
+            Some(preview_host) => parse_preview_host(preview_host).map(Some).map_err(|e| {
+                anyhow!(
+                    "preview_host in {} of your configuration file: {}",
+                    table,
+                    e
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+
     pub fn get_environment(&self, environment_name: Option<&str>) -> Result<Option<&Environment>> {
         // check for user-specified environment name
         if let Some(environment_name) = environment_name {
@@ -5,6 +5,7 @@ mod environment;
 mod kv_namespace;
 mod manifest;
 pub mod migrations;
+mod preview_host;
 mod route;
 mod site;
 mod target;
@@ -16,6 +17,7 @@ pub use durable_objects::{DurableObjects, DurableObjectsClass};
 pub use environment::Environment;
 pub use kv_namespace::{ConfigKvNamespace, KvNamespace};
 pub use manifest::Manifest;
+pub use preview_host::parse_preview_host;
 pub use route::{Route, RouteConfig};
 pub use site::Site;
 pub use target::Target;
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use hyper::http::uri::Authority;
+
+/// a preview service host, from `preview_host` in wrangler.toml or `--preview-host`
+pub fn parse_preview_host(host: &str) -> Result<String> {
+    let host = host.trim();
+    let authority = host
+        .parse::<Authority>()
+        .ok()
+        .filter(|authority| !authority.host().is_empty() && !authority.as_str().contains('@'));
+    match authority {
+        Some(authority) => Ok(authority.to_string()),
+        None => anyhow::bail!(
+            "{} is not a valid preview host, which is a host name and an optional port",
+            host
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_hosts_are_host_names_with_an_optional_port() {
+        assert_eq!(
+            parse_preview_host("preview.staging.example.com").unwrap(),
+            "preview.staging.example.com"
+        );
+        assert_eq!(
+            parse_preview_host("10.0.0.4:8443").unwrap(),
+            "10.0.0.4:8443"
+        );
+        assert!(parse_preview_host("https://preview.example.com").is_err());
+        assert!(parse_preview_host("user@preview.example.com").is_err());
+        assert!(parse_preview_host("").is_err());
+    }
+}
@@ -26,6 +26,8 @@ pub struct Target {
     pub text_blobs: Option<HashMap<String, PathBuf>>,
     pub usage_model: Option<UsageModel>,
     pub wasm_modules: Option<HashMap<String, PathBuf>>,
+    // a prebuilt script to upload in place of the project's build output
+    pub script_path: Option<PathBuf>,
 }
 
 impl Target {
@@ -124,6 +124,46 @@ fn it_uses_env_name_when_provided() {
     assert_eq!(manifest.worker_name(Some(TEST_ENV_NAME)), custom_env_name);
 }
 
+#[test]
+fn it_inherits_the_preview_host_unless_the_env_overrides_it() {
+    let manifest = Manifest::from_str(
+        r#"
+        name = "worker"
+        type = "javascript"
+        preview_host = "preview.example.com"
+
+        [env.staging]
+        preview_host = "preview.staging.example.com:8443"
+
+        [env.production]
+
+        [env.broken]
+        preview_host = "https://preview.example.com"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        manifest.get_preview_host(None).unwrap().as_deref(),
+        Some("preview.example.com")
+    );
+    assert_eq!(
+        manifest
+            .get_preview_host(Some("staging"))
+            .unwrap()
+            .as_deref(),
+        Some("preview.staging.example.com:8443")
+    );
+    assert_eq!(
+        manifest
+            .get_preview_host(Some("production"))
+            .unwrap()
+            .as_deref(),
+        Some("preview.example.com")
+    );
+    assert!(manifest.get_preview_host(Some("broken")).is_err());
+}
+
 fn base_fixture_path() -> PathBuf {
     let current_dir = env::current_dir().unwrap();
 
@@ -332,6 +332,7 @@ mod tests {
             text_blobs: None,
             usage_model: None,
             wasm_modules: None,
+            script_path: None,
         }
     }
 
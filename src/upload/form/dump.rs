@@ -0,0 +1,98 @@
+//! `wrangler dev --dump-bundle <dir>` writes each part of the form uploaded to
+//! the preview service to `dir`, to see exactly what was sent. Every upload
+//! overwrites the files of the one before, and leaves any others in `dir` alone.
+//!
+//! ```text
+//! <dir>/
+//!   metadata.json                 the metadata part: the script or main module,
+//!                                 bindings and usage model
+//!   wrangler-session-config.json  the session config part, for authenticated sessions
+//!   <part>                        every other part, under its name in the form:
+//!                                 the script or modules, Wasm modules and text blobs
+//! ```
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+
+static DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// dumps every upload from now on to `dir`
+pub fn init(dir: &Path) {
+    if DIR.set(dir.to_path_buf()).is_err() {
+        log::debug!("--dump-bundle was already set up");
+    }
+}
+
+/// where the parts of the upload being built are written
+pub(super) struct Dump {
+    dir: PathBuf,
+}
+
+impl Dump {
+    /// a dump of the upload being built, if `--dump-bundle` was given
+    pub(super) fn start() -> Result<Option<Dump>> {
+        match DIR.get() {
+            Some(dir) => {
+                fs::create_dir_all(dir)?;
+                log::info!("Writing the upload to {}", dir.display());
+                Ok(Some(Dump { dir: dir.clone() }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub(super) fn json(&self, part: &str, json: &serde_json::Value) -> Result<()> {
+        fs::write(self.path(part)?, serde_json::to_string_pretty(json)?)?;
+        Ok(())
+    }
+
+    pub(super) fn file(&self, part: &str, path: &Path) -> Result<()> {
+        fs::copy(path, self.path(part)?)?;
+        Ok(())
+    }
+
+    pub(super) fn text(&self, part: &str, text: &str) -> Result<()> {
+        fs::write(self.path(part)?, text)?;
+        Ok(())
+    }
+
+    /// where a part is written, which must be inside the dump directory.
+    /// Module names can have directories in them, which are created
+    fn path(&self, part: &str) -> Result<PathBuf> {
+        let relative = Path::new(part);
+        let inside = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if !inside || part.is_empty() {
+            anyhow::bail!("Could not dump the part {}, it isn't a file name", part)
+        }
+
+        let path = self.dir.join(relative);
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+        fs::create_dir_all(parent)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_stay_in_the_dump_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = Dump {
+            dir: dir.path().to_path_buf(),
+        };
+
+        dump.text("lib/util.mjs", "export {}").unwrap();
+        assert!(dir.path().join("lib").join("util.mjs").is_file());
+
+        assert!(dump.text("../escaped.js", "").is_err());
+        assert!(dump.text("/etc/escaped.js", "").is_err());
+    }
+}
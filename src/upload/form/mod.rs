@@ -1,3 +1,4 @@
+pub mod dump;
 mod modules_worker;
 mod plain_text;
 mod project_assets;
@@ -69,6 +70,25 @@ pub fn build(
         text_blobs.push(text_blob);
     }
 
+    // `wrangler dev --script` uploads a script as it is, whatever the project type
+    if let Some(script_path) = &target.script_path {
+        log::info!(
+            "Prebuilt script {} given. Publishing...",
+            script_path.display()
+        );
+        let assets = ServiceWorkerAssets::new(
+            script_path.clone(),
+            wasm_modules,
+            kv_namespaces.to_vec(),
+            durable_object_classes,
+            text_blobs,
+            plain_texts,
+            usage_model,
+        )?;
+
+        return service_worker::build_form(&assets, session_config);
+    }
+
     match target_type {
         TargetType::Rust => {
             log::info!("Rust project detected. Publishing...");
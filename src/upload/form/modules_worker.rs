@@ -7,6 +7,7 @@ use serde::Serialize;
 use crate::settings::binding::Binding;
 use crate::settings::toml::migrations::ApiMigration;
 
+use super::dump::Dump;
 use super::{ModulesAssets, UsageModel};
 
 #[derive(Serialize, Debug)]
@@ -21,6 +22,10 @@ pub fn build_form(
     assets: &ModulesAssets,
     session_config: Option<serde_json::Value>,
 ) -> Result<Form> {
+    if let Some(dump) = Dump::start()? {
+        dump_parts(&dump, assets, session_config.as_ref())?;
+    }
+
     let mut form = Form::new();
 
     // The preview service in particular streams the request form, and requires that the
@@ -47,13 +52,33 @@ fn add_files(mut form: Form, assets: &ModulesAssets) -> Result<Form> {
     Ok(form)
 }
 
-fn add_metadata(mut form: Form, assets: &ModulesAssets) -> Result<Form> {
-    let metadata_json = serde_json::json!(&Metadata {
+/// writes the parts `build_form` adds to the form, for `wrangler dev --dump-bundle`
+fn dump_parts(
+    dump: &Dump,
+    assets: &ModulesAssets,
+    session_config: Option<&serde_json::Value>,
+) -> Result<()> {
+    dump.json("metadata.json", &metadata_json(assets))?;
+    for (name, module) in &assets.manifest.modules {
+        dump.file(name, &module.path)?;
+    }
+    if let Some(session_config) = session_config {
+        dump.json("wrangler-session-config.json", session_config)?;
+    }
+    Ok(())
+}
+
+fn metadata_json(assets: &ModulesAssets) -> serde_json::Value {
+    serde_json::json!(&Metadata {
         main_module: assets.manifest.main.clone(),
         bindings: assets.bindings(),
         migrations: assets.migration.clone(),
         usage_model: assets.usage_model,
-    });
+    })
+}
+
+fn add_metadata(mut form: Form, assets: &ModulesAssets) -> Result<Form> {
+    let metadata_json = metadata_json(assets);
 
     let metadata = Part::text(metadata_json.to_string())
         .file_name("metadata.json")
@@ -4,6 +4,7 @@ use serde::Serialize;
 
 use crate::settings::binding::Binding;
 
+use super::dump::Dump;
 use super::{ServiceWorkerAssets, UsageModel};
 
 #[derive(Serialize, Debug)]
@@ -17,6 +18,10 @@ pub fn build_form(
     assets: &ServiceWorkerAssets,
     session_config: Option<serde_json::Value>,
 ) -> Result<Form> {
+    if let Some(dump) = Dump::start()? {
+        dump_parts(&dump, assets, session_config.as_ref())?;
+    }
+
     let mut form = Form::new();
 
     // The preview service in particular streams the request form, and requires that the
@@ -51,12 +56,36 @@ fn add_files(mut form: Form, assets: &ServiceWorkerAssets) -> Result<Form> {
     Ok(form)
 }
 
-fn add_metadata(mut form: Form, assets: &ServiceWorkerAssets) -> Result<Form> {
-    let metadata_json = serde_json::json!(&Metadata {
+/// writes the parts `build_form` adds to the form, for `wrangler dev --dump-bundle`
+fn dump_parts(
+    dump: &Dump,
+    assets: &ServiceWorkerAssets,
+    session_config: Option<&serde_json::Value>,
+) -> Result<()> {
+    dump.json("metadata.json", &metadata_json(assets))?;
+    dump.file(&assets.script_name(), &assets.script_path())?;
+    for wasm_module in &assets.wasm_modules {
+        dump.file(&wasm_module.filename(), &wasm_module.path())?;
+    }
+    for text_blob in &assets.text_blobs {
+        dump.text(&text_blob.binding, &text_blob.data)?;
+    }
+    if let Some(session_config) = session_config {
+        dump.json("wrangler-session-config.json", session_config)?;
+    }
+    Ok(())
+}
+
+fn metadata_json(assets: &ServiceWorkerAssets) -> serde_json::Value {
+    serde_json::json!(&Metadata {
         body_part: assets.script_name(),
         bindings: assets.bindings(),
         usage_model: assets.usage_model,
-    });
+    })
+}
+
+fn add_metadata(mut form: Form, assets: &ServiceWorkerAssets) -> Result<Form> {
+    let metadata_json = metadata_json(assets);
 
     let metadata = Part::text(metadata_json.to_string())
         .file_name("metadata.json")
@@ -1,6 +1,8 @@
+mod scope;
 mod watcher;
 use ignore::overrides::OverrideBuilder;
 use ignore::WalkBuilder;
+pub use scope::WatchScope;
 pub use watcher::wait_for_changes;
 
 use crate::settings::toml::{Target, TargetType};
@@ -23,8 +25,13 @@ const RUST_PATH: &str = "./";
 const RUST_IGNORE: &[&str] = &["pkg", "target", "worker/generated"];
 
 // watch a project for changes and re-build it when necessary,
-// outputting a build event to tx.
-pub fn watch_and_build(target: &Target, tx: Option<mpsc::Sender<()>>) -> Result<()> {
+// outputting a build event to tx. Changes outside of scope are ignored,
+// except by webpack, which watches its own inputs
+pub fn watch_and_build(
+    target: &Target,
+    tx: Option<mpsc::Sender<()>>,
+    scope: WatchScope,
+) -> Result<()> {
     let target_type = &target.target_type;
     let build = target.build.clone();
     match target_type {
@@ -40,7 +47,7 @@ pub fn watch_and_build(target: &Target, tx: Option<mpsc::Sender<()>>) -> Result<
                         StdOut::info(&format!("watching {:?}", &JAVASCRIPT_PATH));
 
                         loop {
-                            match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD) {
+                            match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD, &scope) {
                                 Ok(_path) => {
                                     if let Some(tx) = tx.clone() {
                                         send_change_or_log_error(tx);
@@ -58,7 +65,7 @@ pub fn watch_and_build(target: &Target, tx: Option<mpsc::Sender<()>>) -> Result<
                         watcher.watch(config.watch_dir, notify::RecursiveMode::Recursive)?;
 
                         loop {
-                            match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD) {
+                            match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD, &scope) {
                                 Ok(_path) => match build_target(&target) {
                                     Ok(output) => {
                                         StdOut::success(&output);
@@ -110,7 +117,7 @@ pub fn watch_and_build(target: &Target, tx: Option<mpsc::Sender<()>>) -> Result<
                 StdOut::info(&format!("watching {:?}", &RUST_PATH));
 
                 loop {
-                    match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD) {
+                    match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD, &scope) {
                         Ok(_path) => {
                             let command = command(&args, &binary_path);
                             let command_name = format!("{:?}", command);
@@ -0,0 +1,208 @@
+//! Which changed files trigger a rebuild. By default that's anything in the
+//! project, other than dependencies, build output, version control and whatever
+//! the project's `.gitignore` lists, so writing build output doesn't set off
+//! another build.
+//!
+//! `--watch-paths` narrows that down to files matching the given globs, which
+//! then apply in place of the defaults, and `--ignore-paths` leaves out files
+//! matching the given globs, whatever else matches them. Globs are matched
+//! against paths relative to the project, and `*` matches across `/`
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+
+/// build output and directories that aren't the project's own source
+const DEFAULT_IGNORES: &[&str] = &["**/node_modules/**", "**/.git/**", "**/dist/**"];
+
+#[derive(Debug, Clone, Default)]
+pub struct WatchScope {
+    root: PathBuf,
+    /// with `--watch-paths`, only files matching one of these are watched
+    watch: Option<GlobSet>,
+    ignore: GlobSet,
+    /// left out unless `--watch-paths` is given
+    defaults: Option<(GlobSet, Option<Gitignore>)>,
+    /// files that are always watched, like the script given to `wrangler dev --script`
+    always: Vec<PathBuf>,
+    /// files that are never watched, like a wrangler.toml that is watched on its own
+    never: Vec<PathBuf>,
+    description: String,
+}
+
+impl WatchScope {
+    /// the scope of the project in the current directory,
+    /// given the globs passed to `--watch-paths` and `--ignore-paths`
+    pub fn new(watch_paths: &[String], ignore_paths: &[String]) -> Result<WatchScope> {
+        let root = env::current_dir()?;
+
+        let (watch, defaults) = if watch_paths.is_empty() {
+            let gitignore = root.join(".gitignore");
+            let gitignore = if gitignore.is_file() {
+                let (gitignore, error) = Gitignore::new(gitignore);
+                if let Some(error) = error {
+                    log::debug!("Could not read all of .gitignore: {}", error);
+                }
+                Some(gitignore)
+            } else {
+                None
+            };
+            (None, Some((globs(DEFAULT_IGNORES, "")?, gitignore)))
+        } else {
+            (Some(globs(watch_paths, "--watch-paths")?), None)
+        };
+
+        let mut ignored: Vec<String> = ignore_paths.to_vec();
+        if let Some((_, gitignore)) = &defaults {
+            ignored.extend(DEFAULT_IGNORES.iter().map(|glob| glob.to_string()));
+            if gitignore.is_some() {
+                ignored.push("anything in .gitignore".to_string());
+            }
+        }
+        let watched = if watch_paths.is_empty() {
+            root.display().to_string()
+        } else {
+            watch_paths.join(", ")
+        };
+        let mut description = format!("Watching {} for changes", watched);
+        if !ignored.is_empty() {
+            description.push_str(&format!(", ignoring {}", ignored.join(", ")));
+        }
+
+        Ok(WatchScope {
+            watch,
+            ignore: globs(ignore_paths, "--ignore-paths")?,
+            defaults,
+            always: Vec::new(),
+            never: Vec::new(),
+            root,
+            description,
+        })
+    }
+
+    /// watches `file` whatever the globs say about it
+    pub fn always_watch(mut self, file: &Path) -> WatchScope {
+        self.always.push(self.root.join(file));
+        self
+    }
+
+    /// leaves `file` out whatever the globs say about it
+    pub fn never_watch(mut self, file: &Path) -> WatchScope {
+        self.never.push(self.root.join(file));
+        self
+    }
+
+    /// whether a change to `path` should trigger a rebuild
+    pub fn includes(&self, path: &Path) -> bool {
+        let path = self.root.join(path);
+        if self.never.contains(&path) {
+            return false;
+        }
+        if self.always.contains(&path) {
+            return true;
+        }
+
+        // files outside the project can only be matched by absolute globs
+        let relative = path.strip_prefix(&self.root).unwrap_or(&path);
+        if self.ignore.is_match(relative) {
+            return false;
+        }
+        if let Some(watch) = &self.watch {
+            return watch.is_match(relative);
+        }
+        if let Some((ignore, gitignore)) = &self.defaults {
+            if ignore.is_match(relative) {
+                return false;
+            }
+            if let Some(gitignore) = gitignore {
+                if relative.is_relative()
+                    && gitignore
+                        .matched_path_or_any_parents(relative, path.is_dir())
+                        .is_ignore()
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// what is watched, for `--verbose`
+    pub fn describe(&self) -> &str {
+        &self.description
+    }
+}
+
+fn globs<S: AsRef<str>>(patterns: &[S], flag: &str) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        let glob =
+            Glob::new(pattern).map_err(|e| anyhow!("Invalid {} {}: {}", flag, pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(watch_paths: &[&str], ignore_paths: &[&str]) -> WatchScope {
+        let owned = |globs: &[&str]| {
+            globs
+                .iter()
+                .map(|glob| glob.to_string())
+                .collect::<Vec<_>>()
+        };
+        WatchScope::new(&owned(watch_paths), &owned(ignore_paths)).unwrap()
+    }
+
+    #[test]
+    fn dependencies_and_build_output_are_ignored_by_default() {
+        let scope = scope(&[], &["*.md"]);
+
+        assert!(scope.includes(Path::new("./src/index.js")));
+        assert!(!scope.includes(Path::new("./node_modules/lodash/index.js")));
+        assert!(!scope.includes(Path::new("./dist/worker.js")));
+        assert!(!scope.includes(Path::new("./docs/README.md")));
+    }
+
+    #[test]
+    fn watch_paths_replace_the_defaults() {
+        let scope = scope(&["src/**", "dist/**"], &["**/*.test.js"]);
+
+        assert!(scope.includes(Path::new("src/index.js")));
+        assert!(scope.includes(Path::new("dist/worker.js")));
+        assert!(!scope.includes(Path::new("src/index.test.js")));
+        assert!(!scope.includes(Path::new("package.json")));
+    }
+
+    #[test]
+    fn some_files_are_always_watched() {
+        let scope = scope(&[], &[]).always_watch(Path::new("dist/worker.js"));
+
+        assert!(scope.includes(Path::new("./dist/worker.js")));
+        assert!(!scope.includes(Path::new("./dist/other.js")));
+    }
+
+    #[test]
+    fn some_files_are_never_watched() {
+        let scope = scope(&[], &[]).never_watch(Path::new("wrangler.toml"));
+
+        assert!(!scope.includes(Path::new("./wrangler.toml")));
+        assert!(scope.includes(Path::new("./package.json")));
+    }
+
+    #[test]
+    fn everything_is_watched_without_a_scope() {
+        assert!(WatchScope::default().includes(Path::new("./node_modules/lodash/index.js")));
+    }
+
+    #[test]
+    fn bad_globs_are_rejected() {
+        assert!(WatchScope::new(&["src/[".to_string()], &[]).is_err());
+    }
+}
@@ -5,14 +5,26 @@ use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 
+use super::WatchScope;
 use crate::terminal::message::{Message, StdOut};
 use log::info;
 
 // Add cooldown for all types of events to watching logic
-pub fn wait_for_changes(rx: &Receiver<DebouncedEvent>, cooldown: Duration) -> Result<PathBuf> {
+pub fn wait_for_changes(
+    rx: &Receiver<DebouncedEvent>,
+    cooldown: Duration,
+    scope: &WatchScope,
+) -> Result<PathBuf> {
     loop {
         let event = rx.recv()?;
         match get_changed_path_from_event(event) {
+            Ok(Some(path)) if !scope.includes(&path) => {
+                info!(
+                    "Ignoring change to {:?}, which is outside the watched paths",
+                    path
+                );
+                continue;
+            }
             Ok(Some(path)) => {
                 StdOut::working("Detected changes...");
                 // wait for cooldown
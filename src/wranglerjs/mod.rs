@@ -26,7 +26,7 @@ use crate::install;
 use crate::settings::toml::Target;
 use crate::terminal::message::{Message, StdErr, StdOut};
 use crate::upload::package::Package;
-use crate::watch::{wait_for_changes, COOLDOWN_PERIOD};
+use crate::watch::{wait_for_changes, WatchScope, COOLDOWN_PERIOD};
 
 use guarded_command::GuardedCommand;
 
@@ -92,7 +92,7 @@ pub fn run_build_and_watch(target: &Target, tx: Option<Sender<()>>) -> Result<()
         let mut is_first = true;
 
         loop {
-            match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD) {
+            match wait_for_changes(&watcher_rx, COOLDOWN_PERIOD, &WatchScope::default()) {
                 Ok(_) => {
                     if is_first {
                         is_first = false;
@@ -0,0 +1,181 @@
+#![cfg(unix)]
+
+use wrangler::fixtures::{Fixture, WranglerToml};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use assert_cmd::prelude::*;
+use rustls::{
+    Certificate, ClientConfig, ClientSession, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, TLSError,
+};
+use tokio_rustls::webpki::DNSNameRef;
+
+#[test]
+fn it_exits_cleanly_on_sigterm() {
+    let fixture = Fixture::new();
+    fixture.create_empty_js();
+    fixture.create_wrangler_toml(WranglerToml::javascript("test-dev-sigterm"));
+
+    // --echo serves without building or uploading anything
+    let mut dev = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let mut dev = dev
+        .current_dir(fixture.get_path())
+        .args(&["dev", "--echo", "--port", "0"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(dev.stdout.take().unwrap());
+    let addr = address(&dev_url(&mut stdout));
+    wait_until_listening(&mut dev, &addr);
+    let killed = Command::new("kill")
+        .args(&["-TERM", &dev.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let status = dev.wait().unwrap();
+    assert!(status.success(), "wrangler dev exited with {}", status);
+}
+
+#[test]
+fn it_serves_http_and_https_with_dual_listen() {
+    let fixture = Fixture::new();
+    fixture.create_empty_js();
+    fixture.create_wrangler_toml(WranglerToml::javascript("test-dev-dual-listen"));
+
+    let mut dev = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let mut dev = dev
+        .current_dir(fixture.get_path())
+        // where the https server's certificate is generated
+        .env("WRANGLER_HOME", fixture.get_path())
+        .args(&[
+            "dev",
+            "--echo",
+            "--dual-listen",
+            "--port",
+            "0",
+            "--https-port",
+            "0",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // the http server's URL comes first on stdout, the https server's is only in its banner
+    let mut stdout = BufReader::new(dev.stdout.take().unwrap());
+    let http_addr = address(&dev_url(&mut stdout));
+    let mut stderr = BufReader::new(dev.stderr.take().unwrap());
+    let https_addr = address(&banner_url(&mut stderr, "https://"));
+    wait_until_listening(&mut dev, &http_addr);
+    wait_until_listening(&mut dev, &https_addr);
+
+    let mut http = TcpStream::connect(&http_addr).unwrap();
+    let http_status = status_line(&mut http);
+
+    let mut tls = ClientConfig::new();
+    tls.dangerous()
+        .set_certificate_verifier(Arc::new(AcceptAnyCert));
+    let mut session = ClientSession::new(
+        &Arc::new(tls),
+        DNSNameRef::try_from_ascii_str("localhost").unwrap(),
+    );
+    let mut tcp = TcpStream::connect(&https_addr).unwrap();
+    let https_status = status_line(&mut rustls::Stream::new(&mut session, &mut tcp));
+
+    assert_eq!(http_status, "HTTP/1.1 200 OK");
+    assert_eq!(https_status, "HTTP/1.1 200 OK");
+
+    // and both are shut down together
+    let killed = Command::new("kill")
+        .args(&["-TERM", &dev.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let status = dev.wait().unwrap();
+    assert!(status.success(), "wrangler dev exited with {}", status);
+    assert!(TcpStream::connect(&http_addr).is_err());
+    assert!(TcpStream::connect(&https_addr).is_err());
+}
+
+/// the URL `wrangler dev --port 0` prints as the first line of its stdout
+fn dev_url(stdout: &mut impl BufRead) -> String {
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    match line.trim().strip_prefix("WRANGLER_DEV_URL=") {
+        Some(url) => url.to_string(),
+        None => panic!("wrangler dev printed {:?} instead of its URL", line),
+    }
+}
+
+/// the URL starting with `scheme` in a "Listening on" banner on stderr
+fn banner_url(stderr: &mut impl BufRead, scheme: &str) -> String {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let n = stderr.read_line(&mut line).unwrap();
+        assert!(n > 0, "wrangler dev exited before listening on {}", scheme);
+        if let Some(start) = line.find(&format!("Listening on {}", scheme)) {
+            let url = line[start + "Listening on ".len()..].trim();
+            return url.to_string();
+        }
+    }
+}
+
+/// the host and port of `url`, to connect to
+fn address(url: &str) -> String {
+    url.splitn(2, "://").nth(1).unwrap().to_string()
+}
+
+/// sends a GET to `stream` and reads the status line of the response
+fn status_line<S: Read + Write>(stream: &mut S) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut read = Vec::new();
+    let mut buf = [0; 1024];
+    while !read.windows(2).any(|end| end == b"\r\n") {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "the connection closed before a response was sent");
+        read.extend_from_slice(&buf[..n]);
+    }
+    let read = String::from_utf8_lossy(&read);
+    read.lines().next().unwrap().to_string()
+}
+
+/// accepts the self-signed certificate the https server generates
+struct AcceptAnyCert;
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn wait_until_listening(dev: &mut Child, addr: &str) {
+    let started = Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        if let Some(status) = dev.try_wait().unwrap() {
+            panic!("wrangler dev exited with {} before listening", status);
+        }
+        if started.elapsed() > Duration::from_secs(30) {
+            dev.kill().ok();
+            panic!("wrangler dev didn't start listening on {}", addr);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}